@@ -60,7 +60,7 @@ echo "Creating environment file..."
 cat > "$ENV_FILE" << EOF
 SERVER_URL=${SERVER_URL}
 CLIENT_TOKEN=${CLIENT_TOKEN}
-DOCKER_PATH=/var/lib/docker
+DOCKER_SOCKET=/var/run/docker.sock
 # GITHUB_REPO=username/status-monitor  # Uncomment to enable auto-updates
 EOF
 chmod 600 "$ENV_FILE"
@@ -1,11 +1,35 @@
 use std::env;
 
+use crate::diskio::DeviceFilter;
+use crate::filesystems::MountFilter;
+use crate::network::InterfaceFilter;
+
+/// Filesystem types that are never worth reporting: container layers and
+/// read-only images
+const DEFAULT_FS_TYPE_EXCLUDE: &str = "overlay,squashfs,iso9660";
+const DEFAULT_MOUNT_EXCLUDE: &str = "/snap/*,/var/lib/kubelet/*";
+/// Loopback and the host side of container network pairs
+const DEFAULT_INTERFACE_EXCLUDE: &str = "lo,veth*";
+/// Loop devices and RAM disks only ever reflect I/O already counted elsewhere
+const DEFAULT_DISK_DEVICE_EXCLUDE: &str = "loop*,ram*,zram*";
+const DEFAULT_TOP_PROCESSES: usize = 10;
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub server_url: String,
     pub token: String,
     pub hostname: String,
-    pub docker_path: String,
+    /// Unix socket of the Docker Engine API
+    pub docker_socket: String,
+    pub mount_filter: MountFilter,
+    pub interface_filter: InterfaceFilter,
+    pub device_filter: DeviceFilter,
+    /// systemd units whose state is reported, on top of any failed unit
+    pub systemd_units: Vec<String>,
+    /// Size of the process snapshot; 0 turns snapshots off
+    pub top_processes: usize,
+    /// Directory of `*.prom` files whose series are reported as they are
+    pub textfile_dir: Option<String>,
     pub github_repo: Option<String>,
 }
 
@@ -21,8 +45,40 @@ impl Config {
             .or_else(|_| hostname::get().map(|h| h.to_string_lossy().to_string()))
             .unwrap_or_else(|_| "unknown".to_string());
 
-        let docker_path = env::var("DOCKER_PATH")
-            .unwrap_or_else(|_| "/var/lib/docker".to_string());
+        let docker_socket = env::var("DOCKER_SOCKET")
+            .ok()
+            .or_else(|| {
+                env::var("DOCKER_HOST")
+                    .ok()
+                    .and_then(|h| h.strip_prefix("unix://").map(str::to_string))
+            })
+            .unwrap_or_else(|| "/var/run/docker.sock".to_string());
+
+        let mount_filter = MountFilter {
+            include_mounts: list_var("MOUNT_INCLUDE", ""),
+            exclude_mounts: list_var("MOUNT_EXCLUDE", DEFAULT_MOUNT_EXCLUDE),
+            include_fs_types: list_var("FS_TYPE_INCLUDE", ""),
+            exclude_fs_types: list_var("FS_TYPE_EXCLUDE", DEFAULT_FS_TYPE_EXCLUDE),
+        };
+
+        let interface_filter = InterfaceFilter {
+            include: list_var("NET_INTERFACE_INCLUDE", ""),
+            exclude: list_var("NET_INTERFACE_EXCLUDE", DEFAULT_INTERFACE_EXCLUDE),
+        };
+
+        let device_filter = DeviceFilter {
+            include: list_var("DISK_DEVICE_INCLUDE", ""),
+            exclude: list_var("DISK_DEVICE_EXCLUDE", DEFAULT_DISK_DEVICE_EXCLUDE),
+        };
+
+        let systemd_units = list_var("SYSTEMD_UNITS", "");
+
+        let top_processes = env::var("TOP_PROCESSES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_TOP_PROCESSES);
+
+        let textfile_dir = env::var("TEXTFILE_DIR").ok().filter(|d| !d.is_empty());
 
         let github_repo = env::var("GITHUB_REPO").ok();
 
@@ -30,8 +86,38 @@ impl Config {
             server_url,
             token,
             hostname,
-            docker_path,
+            docker_socket,
+            mount_filter,
+            interface_filter,
+            device_filter,
+            systemd_units,
+            top_processes,
+            textfile_dir,
             github_repo,
         })
     }
 }
+
+/// Comma-separated list from the environment
+fn list_var(name: &str, default: &str) -> Vec<String> {
+    env::var(name)
+        .unwrap_or_else(|_| default.to_string())
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Whether a value passes include/exclude lists. Patterns match exactly, or
+/// as a prefix when they end in `*`; an empty include list allows everything.
+pub fn pattern_allows(value: &str, include: &[String], exclude: &[String]) -> bool {
+    (include.is_empty() || include.iter().any(|p| matches(p, value)))
+        && !exclude.iter().any(|p| matches(p, value))
+}
+
+fn matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
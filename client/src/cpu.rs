@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+/// CPU breakdown from /proc/stat and /proc/loadavg
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CpuDetail {
+    pub user: f64,
+    pub system: f64,
+    pub iowait: f64,
+    pub steal: f64,
+    pub load1: f64,
+    pub load5: f64,
+    pub load15: f64,
+    /// Utilization of each core, in /proc/stat order; a core that just came
+    /// online is left out until it has two readings
+    pub cores: Vec<f64>,
+}
+
+/// Jiffies of one `cpu` line of /proc/stat
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct CpuTimes {
+    user: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    steal: u64,
+    total: u64,
+}
+
+impl CpuTimes {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<u64> = line
+            .split_whitespace()
+            .skip(1)
+            .map(|f| f.parse().ok())
+            .collect::<Option<_>>()?;
+        let field = |i: usize| fields.get(i).copied().unwrap_or(0);
+
+        // user nice system idle iowait irq softirq steal guest guest_nice;
+        // guest time is already included in user and nice
+        Some(Self {
+            user: field(0) + field(1),
+            system: field(2) + field(5) + field(6),
+            idle: field(3),
+            iowait: field(4),
+            steal: field(7),
+            total: fields.iter().take(8).sum(),
+        })
+    }
+}
+
+/// Aggregate and per-core times, by `cpuN` id, from the contents of
+/// /proc/stat. Offline cores have no line, so ids can have gaps.
+fn parse_stat(stat: &str) -> Option<(CpuTimes, Vec<(String, CpuTimes)>)> {
+    let mut total = None;
+    let mut cores = vec![];
+
+    for line in stat.lines() {
+        if line.starts_with("cpu ") {
+            total = CpuTimes::parse(line);
+        } else if let Some(id) = line
+            .split_whitespace()
+            .next()
+            .filter(|f| f.starts_with("cpu"))
+        {
+            cores.push((id.to_string(), CpuTimes::parse(line)?));
+        }
+    }
+
+    Some((total?, cores))
+}
+
+fn parse_loadavg(loadavg: &str) -> Option<(f64, f64, f64)> {
+    let mut fields = loadavg.split_whitespace().map(|f| f.parse::<f64>().ok());
+    Some((fields.next()??, fields.next()??, fields.next()??))
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
+/// Turns successive /proc/stat readings into utilization over the interval
+#[derive(Default)]
+pub struct CpuSampler {
+    last: Option<(CpuTimes, HashMap<String, CpuTimes>)>,
+}
+
+impl CpuSampler {
+    /// Read /proc; `None` on the first call or where /proc isn't available
+    pub fn sample(&mut self) -> Option<CpuDetail> {
+        let stat = fs::read_to_string("/proc/stat").ok()?;
+        let loadavg = fs::read_to_string("/proc/loadavg").ok()?;
+        self.update(&stat, &loadavg)
+    }
+
+    fn update(&mut self, stat: &str, loadavg: &str) -> Option<CpuDetail> {
+        let (total, cores) = parse_stat(stat)?;
+        let (load1, load5, load15) = parse_loadavg(loadavg)?;
+        let (prev_total, prev_cores) = self
+            .last
+            .replace((total, cores.iter().cloned().collect()))?;
+
+        // Counters only go backwards if a core went offline and came back
+        let delta = |a: u64, b: u64| a.saturating_sub(b);
+        let elapsed = delta(total.total, prev_total.total);
+
+        Some(CpuDetail {
+            user: percent(delta(total.user, prev_total.user), elapsed),
+            system: percent(delta(total.system, prev_total.system), elapsed),
+            iowait: percent(delta(total.iowait, prev_total.iowait), elapsed),
+            steal: percent(delta(total.steal, prev_total.steal), elapsed),
+            load1,
+            load5,
+            load15,
+            // Match cores by id, since they go offline and online
+            cores: cores
+                .iter()
+                .filter_map(|(id, now)| Some((now, prev_cores.get(id)?)))
+                .map(|(now, prev)| {
+                    let elapsed = delta(now.total, prev.total);
+                    let idle = delta(now.idle + now.iowait, prev.idle + prev.iowait);
+                    percent(elapsed.saturating_sub(idle), elapsed)
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STAT_1: &str = "cpu  100 0 50 800 50 0 0 0 0 0\n\
+                          cpu0 50 0 25 400 25 0 0 0 0 0\n\
+                          cpu1 50 0 25 400 25 0 0 0 0 0\n\
+                          intr 12345\n\
+                          ctxt 678\n";
+    const STAT_2: &str = "cpu  160 0 70 900 60 0 0 10 0 0\n\
+                          cpu0 105 0 35 410 25 0 0 5 0 0\n\
+                          cpu1 60 0 35 490 35 0 0 5 0 0\n\
+                          intr 12400\n";
+
+    #[test]
+    fn test_breakdown_between_samples() {
+        let mut sampler = CpuSampler::default();
+        assert_eq!(sampler.update(STAT_1, "0.50 0.40 0.30 1/100 42\n"), None);
+
+        let detail = sampler.update(STAT_2, "1.50 0.75 0.25 2/100 43\n").unwrap();
+        assert_eq!(detail.user, 30.0);
+        assert_eq!(detail.system, 10.0);
+        assert_eq!(detail.iowait, 5.0);
+        assert_eq!(detail.steal, 5.0);
+        assert_eq!(
+            (detail.load1, detail.load5, detail.load15),
+            (1.5, 0.75, 0.25)
+        );
+        assert_eq!(detail.cores, vec![87.5, 20.0]);
+    }
+
+    #[test]
+    fn test_cores_matched_by_id() {
+        let mut sampler = CpuSampler::default();
+        sampler.update(STAT_1, "0.50 0.40 0.30 1/100 42\n");
+
+        // cpu0 went offline and cpu2 came online
+        let stat = "cpu  160 0 70 900 60 0 0 10 0 0\n\
+                    cpu1 60 0 35 490 35 0 0 5 0 0\n\
+                    cpu2 10 0 0 10 0 0 0 0 0 0\n";
+        let detail = sampler.update(stat, "1.50 0.75 0.25 2/100 43\n").unwrap();
+        assert_eq!(detail.cores, vec![20.0]);
+
+        // cpu2 is reported from its second reading
+        let stat = "cpu  200 0 80 1000 60 0 0 10 0 0\n\
+                    cpu1 70 0 35 580 35 0 0 5 0 0\n\
+                    cpu2 40 0 0 20 0 0 0 0 0 0\n";
+        let detail = sampler.update(stat, "1.50 0.75 0.25 2/100 43\n").unwrap();
+        assert_eq!(detail.cores, vec![10.0, 75.0]);
+    }
+
+    #[test]
+    fn test_rejects_malformed_input() {
+        assert!(parse_stat("intr 1\n").is_none());
+        assert!(parse_loadavg("0.5 abc").is_none());
+    }
+}
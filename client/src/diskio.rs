@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::time::Instant;
+
+use crate::config::pattern_allows;
+
+/// Bytes per sector in /proc/diskstats, regardless of the device's sector size
+const SECTOR_SIZE: f64 = 512.0;
+
+/// Throughput, IOPS and latency of one block device
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DiskIo {
+    pub device: String,
+    pub read_bytes: f64,
+    pub write_bytes: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    /// Average time a request took to complete, queueing included, in ms
+    pub await_ms: f64,
+    /// Share of the interval the device was busy, in percent
+    pub util: f64,
+}
+
+/// Which block devices to report, using the same patterns as mounts
+#[derive(Debug, Clone, Default)]
+pub struct DeviceFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// Cumulative counters of one /proc/diskstats line
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Counters {
+    reads: u64,
+    sectors_read: u64,
+    ms_reading: u64,
+    writes: u64,
+    sectors_written: u64,
+    ms_writing: u64,
+    ms_doing_io: u64,
+}
+
+fn parse_diskstats(text: &str) -> HashMap<String, Counters> {
+    text.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 14 {
+                return None;
+            }
+            let field = |i: usize| fields[i].parse::<u64>().ok();
+            Some((
+                fields[2].to_string(),
+                Counters {
+                    reads: field(3)?,
+                    sectors_read: field(5)?,
+                    ms_reading: field(6)?,
+                    writes: field(7)?,
+                    sectors_written: field(9)?,
+                    ms_writing: field(10)?,
+                    ms_doing_io: field(12)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Whole disks listed in /sys/block, so partitions aren't counted twice
+fn whole_disks() -> Option<HashSet<String>> {
+    let entries = fs::read_dir("/sys/block").ok()?;
+    Some(
+        entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect(),
+    )
+}
+
+/// Turns successive /proc/diskstats readings into per-second rates
+pub struct DiskIoSampler {
+    filter: DeviceFilter,
+    last: Option<(Instant, HashMap<String, Counters>)>,
+}
+
+impl DiskIoSampler {
+    pub fn new(filter: DeviceFilter) -> Self {
+        Self { filter, last: None }
+    }
+
+    /// Read /proc; empty on the first call or where /proc isn't available
+    pub fn sample(&mut self) -> Vec<DiskIo> {
+        let text = match fs::read_to_string("/proc/diskstats") {
+            Ok(text) => text,
+            Err(_) => return vec![],
+        };
+        let now = Instant::now();
+        let mut counters = parse_diskstats(&text);
+        if let Some(disks) = whole_disks() {
+            counters.retain(|name, _| disks.contains(name));
+        }
+
+        match self.last.replace((now, counters.clone())) {
+            Some((at, previous)) => {
+                self.rates(&previous, &counters, now.duration_since(at).as_secs_f64())
+            }
+            None => vec![],
+        }
+    }
+
+    fn rates(
+        &self,
+        previous: &HashMap<String, Counters>,
+        current: &HashMap<String, Counters>,
+        elapsed: f64,
+    ) -> Vec<DiskIo> {
+        if elapsed <= 0.0 {
+            return vec![];
+        }
+
+        let mut usage: Vec<DiskIo> = current
+            .iter()
+            .filter(|(name, _)| pattern_allows(name, &self.filter.include, &self.filter.exclude))
+            .filter_map(|(name, now)| {
+                let prev = previous.get(name)?;
+                // Counters only go backwards when a device is re-attached
+                let delta = |now: u64, prev: u64| now.checked_sub(prev);
+                let reads = delta(now.reads, prev.reads)?;
+                let writes = delta(now.writes, prev.writes)?;
+                let ms_waiting = delta(now.ms_reading, prev.ms_reading)?
+                    + delta(now.ms_writing, prev.ms_writing)?;
+                let ios = reads + writes;
+
+                Some(DiskIo {
+                    device: name.clone(),
+                    read_bytes: delta(now.sectors_read, prev.sectors_read)? as f64 * SECTOR_SIZE
+                        / elapsed,
+                    write_bytes: delta(now.sectors_written, prev.sectors_written)? as f64
+                        * SECTOR_SIZE
+                        / elapsed,
+                    read_iops: reads as f64 / elapsed,
+                    write_iops: writes as f64 / elapsed,
+                    await_ms: if ios > 0 {
+                        ms_waiting as f64 / ios as f64
+                    } else {
+                        0.0
+                    },
+                    util: (delta(now.ms_doing_io, prev.ms_doing_io)? as f64 / (elapsed * 10.0))
+                        .min(100.0),
+                })
+            })
+            .collect();
+        usage.sort_by(|a, b| a.device.cmp(&b.device));
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISKSTATS_1: &str = "\
+   8       0 sda 1000 0 80000 500 2000 0 160000 1500 0 3000 2000 0 0 0 0
+   8       1 sda1 900 0 70000 450 1900 0 150000 1400 0 2900 1850 0 0 0 0
+   7       0 loop0 10 0 20 1 0 0 0 0 0 1 1 0 0 0 0
+";
+    const DISKSTATS_2: &str = "\
+   8       0 sda 1100 0 88000 700 2300 0 184000 2100 0 3500 2800 0 0 0 0
+   8       1 sda1 1000 0 78000 650 2200 0 174000 2000 0 3400 2650 0 0 0 0
+   7       0 loop0 10 0 20 1 0 0 0 0 0 1 1 0 0 0 0
+";
+
+    #[test]
+    fn test_rates_between_readings() {
+        let sampler = DiskIoSampler::new(DeviceFilter {
+            include: vec![],
+            exclude: vec!["loop*".to_string(), "sda1".to_string()],
+        });
+        let usage = sampler.rates(
+            &parse_diskstats(DISKSTATS_1),
+            &parse_diskstats(DISKSTATS_2),
+            2.0,
+        );
+
+        assert_eq!(
+            usage,
+            vec![DiskIo {
+                device: "sda".to_string(),
+                read_bytes: 2_048_000.0,
+                write_bytes: 6_144_000.0,
+                read_iops: 50.0,
+                write_iops: 150.0,
+                await_ms: 2.0,
+                util: 25.0,
+            }]
+        );
+    }
+}
@@ -0,0 +1,549 @@
+use anyhow::{anyhow, bail, Context};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+/// How long the daemon gets to answer a request. A wedged dockerd can
+/// accept connections and never reply, which would stall the poll loops.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// `/system/df` walks every image layer and volume, so it gets longer
+const DF_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Container counts and disk usage of the Docker daemon, sent with every sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DockerSummary {
+    pub running: u32,
+    pub stopped: u32,
+    pub unhealthy: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disk: Option<DockerDiskUsage>,
+}
+
+/// Space used by Docker, as `docker system df` reports it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DockerDiskUsage {
+    pub images_bytes: u64,
+    pub containers_bytes: u64,
+    pub volumes_bytes: u64,
+    pub build_cache_bytes: u64,
+}
+
+impl DockerDiskUsage {
+    pub fn total(&self) -> u64 {
+        self.images_bytes + self.containers_bytes + self.volumes_bytes + self.build_cache_bytes
+    }
+}
+
+/// State and resource usage of one container
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContainerStats {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    /// running, exited, restarting, paused, ...
+    pub state: String,
+    /// healthy, unhealthy or starting; `None` without a healthcheck
+    pub health: Option<String>,
+    pub restart_count: u32,
+    /// CPU usage in percent of one core; needs two polls of a running container
+    pub cpu_percent: Option<f64>,
+    pub memory_used: Option<u64>,
+    pub memory_limit: Option<u64>,
+}
+
+impl DockerSummary {
+    pub fn new(containers: &[ContainerStats], disk: Option<DockerDiskUsage>) -> Self {
+        let running = containers.iter().filter(|c| c.state == "running").count() as u32;
+        Self {
+            running,
+            stopped: containers.len() as u32 - running,
+            unhealthy: containers
+                .iter()
+                .filter(|c| c.health.as_deref() == Some("unhealthy"))
+                .count() as u32,
+            disk,
+        }
+    }
+}
+
+/// Minimal Docker Engine API client over the daemon's unix socket
+#[derive(Debug, Clone)]
+pub struct DockerClient {
+    socket: PathBuf,
+}
+
+impl DockerClient {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        self.get_within(path, REQUEST_TIMEOUT).await
+    }
+
+    async fn get_within<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        limit: Duration,
+    ) -> anyhow::Result<T> {
+        let response = tokio::time::timeout(limit, self.request(path))
+            .await
+            .map_err(|_| anyhow!("GET {path} timed out after {}s", limit.as_secs()))??;
+        let body = parse_response(&response).with_context(|| format!("GET {path}"))?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    async fn request(&self, path: &str) -> anyhow::Result<Vec<u8>> {
+        let mut stream = UnixStream::connect(&self.socket)
+            .await
+            .with_context(|| format!("connecting to {}", self.socket.display()))?;
+        let request = format!("GET {path} HTTP/1.1\r\nHost: docker\r\nConnection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok(response)
+    }
+
+    /// Image, container, volume and build cache sizes; slow on big hosts
+    pub async fn disk_usage(&self) -> anyhow::Result<DockerDiskUsage> {
+        let df: SystemDf = self.get_within("/system/df", DF_TIMEOUT).await?;
+        Ok(DockerDiskUsage {
+            images_bytes: df.layers_size.max(0) as u64,
+            containers_bytes: df.containers.iter().map(|c| c.size_rw.max(0) as u64).sum(),
+            volumes_bytes: df
+                .volumes
+                .iter()
+                .filter_map(|v| v.usage_data.as_ref())
+                .map(|u| u.size.max(0) as u64)
+                .sum(),
+            build_cache_bytes: df
+                .build_cache
+                .iter()
+                .filter(|b| !b.shared)
+                .map(|b| b.size.max(0) as u64)
+                .sum(),
+        })
+    }
+}
+
+/// Body of an HTTP/1.1 response, de-chunked; errors on non-2xx statuses
+fn parse_response(response: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("truncated response"))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = &response[split + 4..];
+
+    let status: u16 = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow!("malformed status line"))?;
+    if !(200..300).contains(&status) {
+        bail!("status {status}: {}", String::from_utf8_lossy(body).trim());
+    }
+
+    let chunked = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    if chunked {
+        dechunk(body)
+    } else {
+        Ok(body.to_vec())
+    }
+}
+
+fn dechunk(mut body: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| anyhow!("truncated chunk"))?;
+        let size_field = String::from_utf8_lossy(&body[..line_end]);
+        let size = usize::from_str_radix(size_field.split(';').next().unwrap_or("").trim(), 16)
+            .context("malformed chunk size")?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if body.len() < size + 2 {
+            bail!("truncated chunk");
+        }
+        out.extend_from_slice(&body[..size]);
+        body = &body[size + 2..];
+    }
+}
+
+/// Polls containers, keeping the CPU counters needed for usage between polls
+pub struct DockerMonitor {
+    client: DockerClient,
+    last_cpu: HashMap<String, CpuCounters>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CpuCounters {
+    container: u64,
+    system: u64,
+}
+
+impl DockerMonitor {
+    pub fn new(client: DockerClient) -> Self {
+        Self {
+            client,
+            last_cpu: HashMap::new(),
+        }
+    }
+
+    /// Every container, running or not, sorted by name
+    pub async fn containers(&mut self) -> anyhow::Result<Vec<ContainerStats>> {
+        let list: Vec<ContainerListEntry> = self.client.get("/containers/json?all=true").await?;
+
+        let mut containers = Vec::with_capacity(list.len());
+        let mut seen_cpu = HashMap::new();
+        for entry in list {
+            // Containers can disappear between the list and these calls
+            let inspect: Option<ContainerInspect> = self
+                .client
+                .get(&format!("/containers/{}/json", entry.id))
+                .await
+                .ok();
+            let stats: Option<StatsResponse> = if entry.state == "running" {
+                self.client
+                    .get(&format!(
+                        "/containers/{}/stats?stream=false&one-shot=true",
+                        entry.id
+                    ))
+                    .await
+                    .ok()
+            } else {
+                None
+            };
+
+            let mut container = ContainerStats {
+                name: entry
+                    .names
+                    .first()
+                    .map(|n| n.trim_start_matches('/').to_string())
+                    .unwrap_or_else(|| entry.id.chars().take(12).collect()),
+                image: entry.image,
+                state: entry.state,
+                health: inspect
+                    .as_ref()
+                    .and_then(|i| i.state.health.as_ref())
+                    .map(|h| h.status.clone()),
+                restart_count: inspect.map(|i| i.restart_count).unwrap_or(0),
+                ..Default::default()
+            };
+
+            if let Some(stats) = stats {
+                let counters = CpuCounters {
+                    container: stats.cpu_stats.cpu_usage.total_usage,
+                    system: stats.cpu_stats.system_cpu_usage.unwrap_or(0),
+                };
+                container.cpu_percent = self
+                    .last_cpu
+                    .get(&entry.id)
+                    .and_then(|prev| cpu_percent(prev, &counters, stats.cpu_stats.cpus()));
+                container.memory_used = stats.memory_stats.working_set();
+                container.memory_limit = stats.memory_stats.limit;
+                seen_cpu.insert(entry.id.clone(), counters);
+            }
+
+            container.id = entry.id;
+            containers.push(container);
+        }
+
+        // Forget containers that stopped or were removed
+        self.last_cpu = seen_cpu;
+        containers.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(containers)
+    }
+}
+
+/// Share of host CPU time the container used between two polls, scaled so
+/// one fully busy core is 100%
+fn cpu_percent(prev: &CpuCounters, now: &CpuCounters, cpus: u32) -> Option<f64> {
+    let container = now.container.checked_sub(prev.container)?;
+    let system = now.system.checked_sub(prev.system)?;
+    if system == 0 {
+        return None;
+    }
+    Some(container as f64 / system as f64 * cpus as f64 * 100.0)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerListEntry {
+    id: String,
+    #[serde(default)]
+    names: Vec<String>,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    state: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerInspect {
+    #[serde(default)]
+    restart_count: u32,
+    state: InspectState,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectState {
+    health: Option<InspectHealth>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InspectHealth {
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsResponse {
+    cpu_stats: CpuStats,
+    memory_stats: MemoryStats,
+}
+
+#[derive(Debug, Deserialize)]
+struct CpuStats {
+    cpu_usage: CpuUsage,
+    system_cpu_usage: Option<u64>,
+    online_cpus: Option<u32>,
+}
+
+impl CpuStats {
+    fn cpus(&self) -> u32 {
+        self.online_cpus
+            .or_else(|| self.cpu_usage.percpu_usage.as_ref().map(|p| p.len() as u32))
+            .unwrap_or(1)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CpuUsage {
+    total_usage: u64,
+    percpu_usage: Option<Vec<u64>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MemoryStats {
+    usage: Option<u64>,
+    limit: Option<u64>,
+    #[serde(default)]
+    stats: HashMap<String, u64>,
+}
+
+impl MemoryStats {
+    /// Usage without reclaimable page cache, matching `docker stats`
+    fn working_set(&self) -> Option<u64> {
+        let cache = self
+            .stats
+            .get("inactive_file")
+            .or_else(|| self.stats.get("total_inactive_file"))
+            .copied()
+            .unwrap_or(0);
+        self.usage.map(|usage| usage.saturating_sub(cache))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SystemDf {
+    #[serde(default)]
+    layers_size: i64,
+    #[serde(default)]
+    containers: Vec<DfContainer>,
+    #[serde(default)]
+    volumes: Vec<DfVolume>,
+    #[serde(default)]
+    build_cache: Vec<DfBuildCache>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DfContainer {
+    #[serde(default)]
+    size_rw: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DfVolume {
+    usage_data: Option<DfUsageData>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DfUsageData {
+    size: i64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DfBuildCache {
+    #[serde(default)]
+    size: i64,
+    #[serde(default)]
+    shared: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use tokio::net::UnixListener;
+
+    /// Answers Engine API requests on a unix socket with canned JSON; the
+    /// container's CPU counter advances on every stats call
+    async fn fake_daemon(socket: PathBuf) {
+        let listener = UnixListener::bind(&socket).unwrap();
+        let polls = Arc::new(AtomicU64::new(0));
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or("").to_string();
+
+                let body = match path.as_str() {
+                    "/containers/json?all=true" => r#"[
+                        {"Id": "aaa", "Names": ["/web"], "Image": "nginx:1.27", "State": "running"},
+                        {"Id": "bbb", "Names": ["/backup"], "Image": "restic", "State": "exited"}
+                    ]"#
+                    .to_string(),
+                    "/containers/aaa/json" => {
+                        r#"{"RestartCount": 3, "State": {"Health": {"Status": "unhealthy"}}}"#
+                            .to_string()
+                    }
+                    "/containers/bbb/json" => r#"{"RestartCount": 0, "State": {}}"#.to_string(),
+                    "/containers/aaa/stats?stream=false&one-shot=true" => {
+                        let poll = polls.fetch_add(1, Ordering::SeqCst);
+                        format!(
+                            r#"{{"cpu_stats": {{"cpu_usage": {{"total_usage": {}}}, "system_cpu_usage": {}, "online_cpus": 4}},
+                                "memory_stats": {{"usage": 300, "limit": 1000, "stats": {{"inactive_file": 100}}}}}}"#,
+                            poll * 500,
+                            poll * 4000
+                        )
+                    }
+                    "/system/df" => r#"{
+                        "LayersSize": 5000,
+                        "Containers": [{"SizeRw": 10}, {"SizeRw": 20}],
+                        "Volumes": [{"UsageData": {"Size": 700}}, {"UsageData": {"Size": -1}}],
+                        "BuildCache": [{"Size": 40, "Shared": false}, {"Size": 99, "Shared": true}]
+                    }"#
+                    .to_string(),
+                    _ => {
+                        let _ = stream
+                            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+                            .await;
+                        continue;
+                    }
+                };
+                // Chunked, as dockerd sends it
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nTransfer-Encoding: chunked\r\n\r\n{:x}\r\n{}\r\n0\r\n\r\n",
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn test_containers_and_disk_usage_from_fake_daemon() {
+        let socket = std::env::temp_dir().join(format!("fake-docker-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        fake_daemon(socket.clone()).await;
+
+        let client = DockerClient::new(&socket);
+        let mut monitor = DockerMonitor::new(client.clone());
+
+        let first = monitor.containers().await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[1].cpu_percent, None);
+
+        let containers = monitor.containers().await.unwrap();
+        assert_eq!(
+            containers,
+            vec![
+                ContainerStats {
+                    id: "bbb".to_string(),
+                    name: "backup".to_string(),
+                    image: "restic".to_string(),
+                    state: "exited".to_string(),
+                    ..Default::default()
+                },
+                ContainerStats {
+                    id: "aaa".to_string(),
+                    name: "web".to_string(),
+                    image: "nginx:1.27".to_string(),
+                    state: "running".to_string(),
+                    health: Some("unhealthy".to_string()),
+                    restart_count: 3,
+                    cpu_percent: Some(50.0),
+                    memory_used: Some(200),
+                    memory_limit: Some(1000),
+                },
+            ]
+        );
+
+        let disk = client.disk_usage().await.unwrap();
+        assert_eq!(
+            disk,
+            DockerDiskUsage {
+                images_bytes: 5000,
+                containers_bytes: 30,
+                volumes_bytes: 700,
+                build_cache_bytes: 40,
+            }
+        );
+
+        let summary = DockerSummary::new(&containers, Some(disk));
+        assert_eq!(
+            (summary.running, summary.stopped, summary.unhealthy),
+            (1, 1, 1)
+        );
+
+        let _ = std::fs::remove_file(&socket);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_wedged_daemon_times_out() {
+        let socket =
+            std::env::temp_dir().join(format!("wedged-docker-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket);
+        // Accepts connections, never answers
+        let listener = UnixListener::bind(&socket).unwrap();
+        tokio::spawn(async move {
+            let mut held = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let started = tokio::time::Instant::now();
+        let err = DockerClient::new(&socket).disk_usage().await.unwrap_err();
+        let _ = std::fs::remove_file(&socket);
+
+        assert!(err.to_string().contains("timed out"), "{err}");
+        assert!(started.elapsed() >= DF_TIMEOUT);
+    }
+}
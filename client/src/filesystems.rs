@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use sysinfo::{Disk, Disks};
+
+use crate::config::pattern_allows;
+
+/// Space and inode usage of one mounted filesystem
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FilesystemUsage {
+    pub mount_point: String,
+    pub device: String,
+    pub fs_type: String,
+    pub total_bytes: u64,
+    pub used_bytes: u64,
+    pub disk_usage: f64,
+    pub inode_usage: Option<f64>,
+}
+
+/// Which mounts to report, by mount point and fs type
+#[derive(Debug, Clone, Default)]
+pub struct MountFilter {
+    pub include_mounts: Vec<String>,
+    pub exclude_mounts: Vec<String>,
+    pub include_fs_types: Vec<String>,
+    pub exclude_fs_types: Vec<String>,
+}
+
+impl MountFilter {
+    pub fn allows(&self, mount_point: &str, fs_type: &str) -> bool {
+        pattern_allows(mount_point, &self.include_mounts, &self.exclude_mounts)
+            && pattern_allows(fs_type, &self.include_fs_types, &self.exclude_fs_types)
+    }
+}
+
+/// Space and inode figures of the mounted filesystems, as of the last refresh
+#[derive(Debug, Clone, Default)]
+pub struct MountUsage {
+    /// Every mount the filter allows, in mount order
+    pub filesystems: Vec<FilesystemUsage>,
+    /// The root filesystem, whatever the filter
+    pub root: Option<FilesystemUsage>,
+}
+
+/// The mounted filesystems. Listing mounts and reading their space and inode
+/// figures (statvfs/statfs) all block on slow or hung mounts, so both run
+/// off the collector; samples only read the last `MountUsage`.
+pub struct Mounts {
+    disks: Disks,
+}
+
+impl Mounts {
+    pub fn scan() -> Self {
+        Self {
+            disks: Disks::new_with_refreshed_list(),
+        }
+    }
+
+    /// Refresh space and inode figures of the scanned mounts
+    pub fn usage(&mut self, filter: &MountFilter) -> MountUsage {
+        self.disks.refresh();
+
+        let root = self
+            .disks
+            .iter()
+            .find(|d| d.mount_point() == Path::new("/"))
+            .map(read_usage);
+        let filesystems = self
+            .disks
+            .iter()
+            .filter(|disk| filter.allows(&mount_point(disk), &fs_type(disk)))
+            .map(read_usage)
+            .collect();
+        MountUsage { filesystems, root }
+    }
+}
+
+fn read_usage(disk: &Disk) -> FilesystemUsage {
+    let mount_point = mount_point(disk);
+    let total_bytes = disk.total_space();
+    let used_bytes = total_bytes.saturating_sub(disk.available_space());
+    FilesystemUsage {
+        disk_usage: percent(used_bytes, total_bytes),
+        inode_usage: inode_usage(&mount_point),
+        device: disk.name().to_string_lossy().to_string(),
+        fs_type: fs_type(disk),
+        mount_point,
+        total_bytes,
+        used_bytes,
+    }
+}
+
+fn mount_point(disk: &Disk) -> String {
+    disk.mount_point().to_string_lossy().to_string()
+}
+
+fn fs_type(disk: &Disk) -> String {
+    disk.file_system().to_string_lossy().to_string()
+}
+
+fn percent(used: u64, total: u64) -> f64 {
+    if total > 0 {
+        (used as f64 / total as f64) * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Inode usage from statfs; `None` for filesystems without a fixed inode
+/// table (btrfs, some network filesystems)
+#[cfg(target_os = "linux")]
+pub fn inode_usage(path: &str) -> Option<f64> {
+    use std::ffi::CString;
+    use std::mem::MaybeUninit;
+
+    let c_path = CString::new(path).ok()?;
+
+    unsafe {
+        let mut stat: MaybeUninit<libc::statfs> = MaybeUninit::uninit();
+        if libc::statfs(c_path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return None;
+        }
+        let stat = stat.assume_init();
+        let total_inodes = stat.f_files;
+        if total_inodes == 0 {
+            return None;
+        }
+        Some(percent(total_inodes - stat.f_ffree, total_inodes))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn inode_usage(_path: &str) -> Option<f64> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_mount_filter() {
+        let filter = MountFilter {
+            include_mounts: vec![],
+            exclude_mounts: list(&["/snap/*", "/boot/efi"]),
+            include_fs_types: vec![],
+            exclude_fs_types: list(&["overlay", "squashfs"]),
+        };
+        assert!(filter.allows("/", "ext4"));
+        assert!(filter.allows("/data", "xfs"));
+        assert!(filter.allows("/boot", "ext4"));
+        assert!(!filter.allows("/boot/efi", "vfat"));
+        assert!(!filter.allows("/snap/core/123", "ext4"));
+        assert!(!filter.allows("/var/lib/docker/overlay2/x/merged", "overlay"));
+
+        let only_data = MountFilter {
+            include_mounts: list(&["/data*"]),
+            ..filter
+        };
+        assert!(only_data.allows("/data", "xfs"));
+        assert!(only_data.allows("/data2", "xfs"));
+        assert!(!only_data.allows("/", "ext4"));
+    }
+}
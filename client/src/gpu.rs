@@ -0,0 +1,139 @@
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::{Device, Nvml};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tracing::debug;
+
+/// State of one GPU at sampling time. Readings a device doesn't support are
+/// left out rather than reported as 0.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GpuDetail {
+    pub index: u32,
+    pub name: String,
+    pub uuid: Option<String>,
+    /// Utilization in percent
+    pub utilization: Option<f64>,
+    pub memory_used: Option<u64>,
+    pub memory_total: Option<u64>,
+    /// Core temperature in °C
+    pub temperature: Option<f64>,
+    pub power_watts: Option<f64>,
+    /// Average fan speed in percent
+    pub fan_speed: Option<f64>,
+    /// Compute and graphics processes using the device
+    pub processes: Option<u32>,
+}
+
+/// Where GPU readings come from; NVML in production, a fake in tests
+pub trait GpuSource: Send {
+    fn devices(&self) -> Vec<GpuDetail>;
+}
+
+pub struct NvmlSource {
+    nvml: Nvml,
+}
+
+impl NvmlSource {
+    /// `None` when there is no NVIDIA driver
+    pub fn init() -> Option<Self> {
+        match Nvml::init() {
+            Ok(nvml) => {
+                debug!("NVIDIA NVML initialized successfully");
+                Some(Self { nvml })
+            }
+            Err(_) => {
+                debug!("NVIDIA NVML not available (no GPU or driver not installed)");
+                None
+            }
+        }
+    }
+}
+
+impl GpuSource for NvmlSource {
+    fn devices(&self) -> Vec<GpuDetail> {
+        let count = self.nvml.device_count().unwrap_or(0);
+        (0..count)
+            .filter_map(|index| {
+                let device = self.nvml.device_by_index(index).ok()?;
+                Some(read_device(index, &device))
+            })
+            .collect()
+    }
+}
+
+fn read_device(index: u32, device: &Device) -> GpuDetail {
+    let memory = device.memory_info().ok();
+
+    let fan_speed = device.num_fans().ok().and_then(|fans| {
+        let speeds: Vec<u32> = (0..fans).filter_map(|i| device.fan_speed(i).ok()).collect();
+        if speeds.is_empty() {
+            None
+        } else {
+            Some(speeds.iter().sum::<u32>() as f64 / speeds.len() as f64)
+        }
+    });
+
+    let compute = device.running_compute_processes().ok();
+    let graphics = device.running_graphics_processes().ok();
+    let processes = if compute.is_some() || graphics.is_some() {
+        let pids: HashSet<u32> = compute
+            .into_iter()
+            .chain(graphics)
+            .flatten()
+            .map(|p| p.pid)
+            .collect();
+        Some(pids.len() as u32)
+    } else {
+        None
+    };
+
+    GpuDetail {
+        index,
+        name: device.name().unwrap_or_default(),
+        uuid: device.uuid().ok(),
+        utilization: device.utilization_rates().ok().map(|u| u.gpu as f64),
+        memory_used: memory.as_ref().map(|m| m.used),
+        memory_total: memory.as_ref().map(|m| m.total),
+        temperature: device
+            .temperature(TemperatureSensor::Gpu)
+            .ok()
+            .map(|t| t as f64),
+        power_watts: device.power_usage().ok().map(|mw| mw as f64 / 1000.0),
+        fan_speed,
+        processes,
+    }
+}
+
+/// Average utilization across devices, kept as the single `gpu_usage` figure
+pub fn average_utilization(gpus: &[GpuDetail]) -> Option<f64> {
+    let values: Vec<f64> = gpus.iter().filter_map(|g| g.utilization).collect();
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().sum::<f64>() / values.len() as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gpu(index: u32, utilization: Option<f64>) -> GpuDetail {
+        GpuDetail {
+            index,
+            name: "NVIDIA A100-SXM4-80GB".to_string(),
+            utilization,
+            memory_used: Some(40 << 30),
+            memory_total: Some(80 << 30),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_average_utilization() {
+        let gpus = [gpu(0, Some(100.0)), gpu(1, Some(50.0)), gpu(2, None)];
+        assert_eq!(average_utilization(&gpus), Some(75.0));
+        assert_eq!(average_utilization(&[gpu(0, None)]), None);
+        assert_eq!(average_utilization(&[]), None);
+    }
+}
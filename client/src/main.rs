@@ -1,21 +1,42 @@
 mod config;
+mod cpu;
+mod diskio;
+mod docker;
+mod filesystems;
+mod gpu;
+mod memory;
 mod metrics;
+mod network;
+mod processes;
 mod reporter;
+mod sensors;
+mod systemd;
+mod textfile;
 mod updater;
 
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tokio::time::{interval, sleep};
-use tracing::{error, info};
+use tracing::{debug, error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use config::Config;
+use docker::{DockerClient, DockerMonitor};
+use filesystems::Mounts;
 use metrics::MetricCollector;
 use reporter::Reporter;
 use updater::Updater;
 
 const FAST_INTERVAL: Duration = Duration::from_secs(1);
+const DOCKER_INTERVAL: Duration = Duration::from_secs(30);
+const SYSTEMD_INTERVAL: Duration = Duration::from_secs(10);
+const PROCESS_INTERVAL: Duration = Duration::from_secs(10);
+const SENSOR_INTERVAL: Duration = Duration::from_secs(10);
+const DISK_INTERVAL: Duration = Duration::from_secs(10);
+const MOUNT_INTERVAL: Duration = Duration::from_secs(60);
+const TEXTFILE_INTERVAL: Duration = Duration::from_secs(10);
 const SLOW_INTERVAL: Duration = Duration::from_secs(300); // 5 minutes
 const REPORT_INTERVAL: Duration = Duration::from_secs(10);
 const UPDATE_INTERVAL: Duration = Duration::from_secs(86400); // 24 hours
@@ -39,10 +60,10 @@ async fn main() -> anyhow::Result<()> {
     info!("Starting status-monitor-client v{}", env!("CARGO_PKG_VERSION"));
     info!("Hostname: {}", config.hostname);
     info!("Server: {}", config.server_url);
-    info!("Docker path: {}", config.docker_path);
+    info!("Docker socket: {}", config.docker_socket);
 
     // Initialize components
-    let collector = Arc::new(Mutex::new(MetricCollector::new(config.docker_path.clone())));
+    let collector = Arc::new(Mutex::new(MetricCollector::new(&config)));
     let reporter = Arc::new(Reporter::new(config.clone()));
     let updater = Updater::new(config.github_repo.clone());
 
@@ -71,22 +92,139 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
-    // Spawn slow collection loop (5m interval) - Docker size
-    let collector_slow = Arc::clone(&collector);
+    // Spawn process snapshot loop (10s interval)
+    let collector_processes = Arc::clone(&collector);
+    tokio::spawn(async move {
+        let mut ticker = interval(PROCESS_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+            collector_processes.lock().await.collect_processes();
+        }
+    });
+
+    // Spawn container poll loop (30s interval)
+    let docker = DockerClient::new(&config.docker_socket);
+    let collector_docker = Arc::clone(&collector);
+    let mut monitor = DockerMonitor::new(docker.clone());
+    tokio::spawn(async move {
+        let mut ticker = interval(DOCKER_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            // Poll without holding the collector, the API can be slow
+            let containers = monitor
+                .containers()
+                .await
+                .map_err(|e| debug!("Docker containers unavailable: {:#}", e))
+                .ok();
+            collector_docker.lock().await.set_containers(containers);
+        }
+    });
+
+    // Spawn systemd poll loop (10s interval)
+    let collector_systemd = Arc::clone(&collector);
+    let systemd_units = config.systemd_units.clone();
     tokio::spawn(async move {
-        // Initial collection
-        {
-            let collector = collector_slow.lock().await;
-            collector.update_docker_size();
+        let mut ticker = interval(SYSTEMD_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            let status = systemd::collect(&systemd_units)
+                .await
+                .map_err(|e| debug!("systemd status unavailable: {:#}", e))
+                .ok();
+            collector_systemd.lock().await.set_systemd(status);
         }
+    });
 
+    // Spawn sensor poll loop (10s interval)
+    let collector_sensors = Arc::clone(&collector);
+    tokio::spawn(async move {
+        let mut ticker = interval(SENSOR_INTERVAL);
+
+        loop {
+            ticker.tick().await;
+
+            // Some hwmon drivers wake the device they read, so keep it off the runtime
+            let readings = tokio::task::spawn_blocking(|| sensors::collect(Path::new("/sys")))
+                .await
+                .ok()
+                .filter(|r| !r.temperatures.is_empty() || !r.fans.is_empty());
+            collector_sensors.lock().await.set_sensors(readings);
+        }
+    });
+
+    // Spawn disk space loop (10s interval, mounts rescanned every 60s)
+    let collector_mounts = Arc::clone(&collector);
+    let mount_filter = config.mount_filter.clone();
+    tokio::spawn(async move {
+        let mut ticker = interval(DISK_INTERVAL);
+        let mut mounts: Option<Mounts> = None;
+        let mut scanned = Instant::now();
+
+        loop {
+            ticker.tick().await;
+
+            // Mounts come and go (USB drives, network shares), and a hung
+            // network mount blocks both the scan and statvfs, so keep them
+            // off the runtime and the collector lock; samples keep the last
+            // figures until this returns
+            let rescan = scanned.elapsed() >= MOUNT_INTERVAL;
+            let previous = mounts.take().filter(|_| !rescan);
+            let filter = mount_filter.clone();
+            let refreshed = tokio::task::spawn_blocking(move || {
+                let mut mounts = previous.unwrap_or_else(Mounts::scan);
+                let usage = mounts.usage(&filter);
+                (mounts, usage)
+            })
+            .await;
+
+            if let Ok((refreshed, usage)) = refreshed {
+                if rescan {
+                    scanned = Instant::now();
+                }
+                mounts = Some(refreshed);
+                collector_mounts.lock().await.set_mounts(usage);
+            }
+        }
+    });
+
+    // Spawn textfile read loop (10s interval)
+    if let Some(dir) = config.textfile_dir.clone() {
+        let collector_textfile = Arc::clone(&collector);
+        tokio::spawn(async move {
+            let mut ticker = interval(TEXTFILE_INTERVAL);
+
+            loop {
+                ticker.tick().await;
+
+                let dir = dir.clone();
+                let values =
+                    tokio::task::spawn_blocking(move || textfile::collect(Path::new(&dir)))
+                        .await
+                        .unwrap_or_default();
+                collector_textfile.lock().await.set_values(values);
+            }
+        });
+    }
+
+    // Spawn slow collection loop (5m interval) - Docker disk usage
+    let collector_slow = Arc::clone(&collector);
+    tokio::spawn(async move {
         let mut ticker = interval(SLOW_INTERVAL);
-        ticker.tick().await; // Skip first (we already did initial)
 
         loop {
             ticker.tick().await;
-            let collector = collector_slow.lock().await;
-            collector.update_docker_size();
+
+            let disk = docker
+                .disk_usage()
+                .await
+                .map_err(|e| debug!("Docker disk usage unavailable: {:#}", e))
+                .ok();
+            collector_slow.lock().await.set_docker_disk_usage(disk);
         }
     });
 
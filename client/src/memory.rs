@@ -0,0 +1,174 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Memory, swap and pressure figures from /proc/meminfo, /proc/pressure and /proc/vmstat
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryDetail {
+    pub total_bytes: u64,
+    /// What the kernel could hand out without swapping, page cache included
+    pub available_bytes: u64,
+    /// Page cache and reclaimable slab
+    pub cached_bytes: u64,
+    pub swap_total_bytes: u64,
+    pub swap_used_bytes: u64,
+    /// Pressure stall information; `None` on kernels without PSI
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<Pressure>,
+    /// OOM kills since boot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oom_kills_total: Option<u64>,
+    /// OOM kills since the previous sample
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oom_kills: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pressure {
+    pub cpu: PsiResource,
+    pub memory: PsiResource,
+    pub io: PsiResource,
+}
+
+/// Share of time some (or all) runnable tasks were stalled on a resource
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PsiResource {
+    pub some: PsiAverages,
+    /// Not reported for CPU by older kernels
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full: Option<PsiAverages>,
+}
+
+/// Percentages over the last 10, 60 and 300 seconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PsiAverages {
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+}
+
+/// Keeps the last OOM-kill count so each sample carries the kills since the previous one
+#[derive(Default)]
+pub struct MemorySampler {
+    last_oom_kills: Option<u64>,
+}
+
+impl MemorySampler {
+    /// `None` where /proc/meminfo can't be read
+    pub fn sample(&mut self) -> Option<MemoryDetail> {
+        let mut detail = parse_meminfo(&fs::read_to_string("/proc/meminfo").ok()?)?;
+
+        detail.pressure = read_pressure();
+        detail.oom_kills_total = fs::read_to_string("/proc/vmstat")
+            .ok()
+            .and_then(|vmstat| parse_oom_kills(&vmstat));
+        detail.oom_kills = match (self.last_oom_kills, detail.oom_kills_total) {
+            (Some(last), Some(now)) => Some(now.saturating_sub(last)),
+            _ => None,
+        };
+        self.last_oom_kills = detail.oom_kills_total;
+
+        Some(detail)
+    }
+}
+
+/// Sizes from /proc/meminfo, which reports them in kB
+fn parse_meminfo(meminfo: &str) -> Option<MemoryDetail> {
+    let field = |name: &str| {
+        meminfo.lines().find_map(|line| {
+            let rest = line.strip_prefix(name)?.strip_prefix(':')?;
+            let kb: u64 = rest.split_whitespace().next()?.parse().ok()?;
+            Some(kb * 1024)
+        })
+    };
+
+    let total_bytes = field("MemTotal")?;
+    let swap_total_bytes = field("SwapTotal").unwrap_or(0);
+    Some(MemoryDetail {
+        total_bytes,
+        // MemAvailable appeared in 3.14; free memory is the closest older figure
+        available_bytes: field("MemAvailable").or_else(|| field("MemFree"))?,
+        cached_bytes: field("Cached").unwrap_or(0) + field("SReclaimable").unwrap_or(0),
+        swap_total_bytes,
+        swap_used_bytes: swap_total_bytes.saturating_sub(field("SwapFree").unwrap_or(0)),
+        ..Default::default()
+    })
+}
+
+fn read_pressure() -> Option<Pressure> {
+    let read = |resource: &str| {
+        let text = fs::read_to_string(format!("/proc/pressure/{resource}")).ok()?;
+        parse_psi(&text)
+    };
+    Some(Pressure {
+        cpu: read("cpu")?,
+        memory: read("memory")?,
+        io: read("io")?,
+    })
+}
+
+/// `some avg10=0.00 avg60=0.00 avg300=0.00 total=0` and the matching `full` line
+fn parse_psi(text: &str) -> Option<PsiResource> {
+    let averages = |kind: &str| {
+        let line = text.lines().find(|l| l.starts_with(kind))?;
+        let mut averages = PsiAverages::default();
+        for field in line.split_whitespace().skip(1) {
+            match field.split_once('=') {
+                Some(("avg10", v)) => averages.avg10 = v.parse().ok()?,
+                Some(("avg60", v)) => averages.avg60 = v.parse().ok()?,
+                Some(("avg300", v)) => averages.avg300 = v.parse().ok()?,
+                _ => {}
+            }
+        }
+        Some(averages)
+    };
+
+    Some(PsiResource {
+        some: averages("some ")?,
+        full: averages("full "),
+    })
+}
+
+fn parse_oom_kills(vmstat: &str) -> Option<u64> {
+    vmstat
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill ")?.trim().parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_files() {
+        let meminfo = "\
+MemTotal:        8000000 kB
+MemFree:          500000 kB
+MemAvailable:    3000000 kB
+Buffers:          100000 kB
+Cached:          2000000 kB
+SwapCached:            0 kB
+SwapTotal:       2000000 kB
+SwapFree:        1500000 kB
+SReclaimable:     200000 kB
+";
+        let detail = parse_meminfo(meminfo).unwrap();
+        assert_eq!(detail.total_bytes, 8_000_000 * 1024);
+        assert_eq!(detail.available_bytes, 3_000_000 * 1024);
+        assert_eq!(detail.cached_bytes, 2_200_000 * 1024);
+        assert_eq!(detail.swap_used_bytes, 500_000 * 1024);
+        assert!(parse_meminfo("").is_none());
+
+        let psi = "\
+some avg10=12.50 avg60=4.00 avg300=1.25 total=123456
+full avg10=3.00 avg60=1.00 avg300=0.50 total=4567
+";
+        let resource = parse_psi(psi).unwrap();
+        assert_eq!(resource.some.avg10, 12.5);
+        assert_eq!(resource.full.unwrap().avg300, 0.5);
+        let cpu = parse_psi("some avg10=0.00 avg60=0.10 avg300=0.00 total=1\n").unwrap();
+        assert_eq!(cpu.full, None);
+
+        assert_eq!(parse_oom_kills("pgfault 10\noom_kill 3\n"), Some(3));
+        assert_eq!(parse_oom_kills("pgfault 10\n"), None);
+    }
+}
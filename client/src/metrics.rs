@@ -1,11 +1,20 @@
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use sysinfo::{Disks, System};
-use tracing::{debug, warn};
-use walkdir::WalkDir;
+use sysinfo::{System, Users};
+use tracing::warn;
+
+use crate::config::Config;
+use crate::cpu::{CpuDetail, CpuSampler};
+use crate::diskio::{DiskIo, DiskIoSampler};
+use crate::docker::{ContainerStats, DockerDiskUsage, DockerSummary};
+use crate::filesystems::{FilesystemUsage, MountUsage, Mounts};
+use crate::gpu::{self, GpuDetail, GpuSource, NvmlSource};
+use crate::memory::{MemoryDetail, MemorySampler};
+use crate::network::{NetworkSampler, NetworkUsage};
+use crate::processes::{self, ProcessInfo};
+use crate::sensors::SensorReadings;
+use crate::systemd::SystemdStatus;
+use crate::textfile::SeriesValue;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metric {
@@ -15,36 +24,77 @@ pub struct Metric {
     pub inode_usage: f64,
     pub docker_sz: Option<i64>,
     pub gpu_usage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<CpuDetail>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<MemoryDetail>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gpus: Vec<GpuDetail>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub filesystems: Vec<FilesystemUsage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub network: Vec<NetworkUsage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disk_io: Vec<DiskIo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub docker: Option<DockerSummary>,
+    /// Only on the first sample after each container poll
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub containers: Option<Vec<ContainerStats>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub systemd: Option<SystemdStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sensors: Option<SensorReadings>,
+    /// Only on the first sample after each process snapshot
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processes: Option<Vec<ProcessInfo>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub values: Vec<SeriesValue>,
     pub timestamp: String,
 }
 
 pub struct MetricCollector {
     system: System,
-    disks: Disks,
-    docker_path: String,
-    cached_docker_size: Arc<AtomicU64>,
-    nvml: Option<nvml_wrapper::Nvml>,
+    mounts: MountUsage,
+    docker: Option<DockerSummary>,
+    docker_disk: Option<DockerDiskUsage>,
+    pending_containers: Option<Vec<ContainerStats>>,
+    systemd: Option<SystemdStatus>,
+    sensors: Option<SensorReadings>,
+    users: Users,
+    top_processes: usize,
+    pending_processes: Option<Vec<ProcessInfo>>,
+    values: Vec<SeriesValue>,
+    gpu_source: Option<Box<dyn GpuSource>>,
+    cpu_sampler: CpuSampler,
+    memory_sampler: MemorySampler,
+    network_sampler: NetworkSampler,
+    disk_io_sampler: DiskIoSampler,
 }
 
 impl MetricCollector {
-    pub fn new(docker_path: String) -> Self {
-        let nvml = nvml_wrapper::Nvml::init().ok();
-        if nvml.is_some() {
-            debug!("NVIDIA NVML initialized successfully");
-        } else {
-            debug!("NVIDIA NVML not available (no GPU or driver not installed)");
-        }
-
+    pub fn new(config: &Config) -> Self {
         Self {
             system: System::new_all(),
-            disks: Disks::new_with_refreshed_list(),
-            docker_path,
-            cached_docker_size: Arc::new(AtomicU64::new(0)),
-            nvml,
+            mounts: Mounts::scan().usage(&config.mount_filter),
+            docker: None,
+            docker_disk: None,
+            pending_containers: None,
+            systemd: None,
+            sensors: None,
+            users: Users::new(),
+            top_processes: config.top_processes,
+            pending_processes: None,
+            values: vec![],
+            gpu_source: NvmlSource::init().map(|s| Box::new(s) as Box<dyn GpuSource>),
+            cpu_sampler: CpuSampler::default(),
+            memory_sampler: MemorySampler::default(),
+            network_sampler: NetworkSampler::new(config.interface_filter.clone()),
+            disk_io_sampler: DiskIoSampler::new(config.device_filter.clone()),
         }
     }
 
-    /// Collect fast metrics (CPU, RAM, GPU, inodes) - called every 1 second
+    /// Collect fast metrics (CPU, RAM, pressure, GPU, inodes) - called every 1 second
     pub fn collect_fast(&mut self) -> Metric {
         // Refresh CPU and memory
         self.system.refresh_cpu_usage();
@@ -52,18 +102,21 @@ impl MetricCollector {
 
         let cpu_usage = self.system.global_cpu_usage() as f64;
         let ram_usage = self.calculate_ram_usage();
-        let (disk_usage, inode_usage) = self.calculate_disk_usage();
-        let gpu_usage = self.collect_gpu_usage();
-
-        // Use cached docker size
-        let docker_sz = {
-            let cached = self.cached_docker_size.load(Ordering::Relaxed);
-            if cached > 0 {
-                Some(cached as i64)
-            } else {
-                None
-            }
-        };
+        let (disk_usage, inode_usage, filesystems) = self.calculate_disk_usage();
+        let gpus = self
+            .gpu_source
+            .as_ref()
+            .map(|source| source.devices())
+            .unwrap_or_default();
+        let gpu_usage = gpu::average_utilization(&gpus);
+        let cpu = self.cpu_sampler.sample();
+        let memory = self.memory_sampler.sample();
+        let network = self.network_sampler.sample();
+        let disk_io = self.disk_io_sampler.sample();
+
+        // Docker figures come from the slower polls below
+        let docker_sz = self.docker_disk.as_ref().map(|d| d.total() as i64);
+        let containers = self.pending_containers.take();
 
         Metric {
             cpu_usage,
@@ -72,25 +125,73 @@ impl MetricCollector {
             inode_usage,
             docker_sz,
             gpu_usage,
+            cpu,
+            memory,
+            gpus,
+            filesystems,
+            network,
+            disk_io,
+            docker: self.docker.clone(),
+            containers,
+            systemd: self.systemd.clone(),
+            sensors: self.sensors.clone(),
+            processes: self.pending_processes.take(),
+            values: self.values.clone(),
             timestamp: Utc::now().to_rfc3339(),
         }
     }
 
-    /// Calculate Docker directory size - called every 5 minutes
-    pub fn update_docker_size(&self) {
-        let path = Path::new(&self.docker_path);
-        if !path.exists() {
-            debug!("Docker path {} does not exist", self.docker_path);
-            return;
+    /// Record a container poll; `None` when the daemon can't be reached
+    pub fn set_containers(&mut self, containers: Option<Vec<ContainerStats>>) {
+        match containers {
+            Some(containers) => {
+                self.docker = Some(DockerSummary::new(&containers, self.docker_disk.clone()));
+                self.pending_containers = Some(containers);
+            }
+            None => {
+                self.docker = None;
+                self.pending_containers = None;
+            }
         }
+    }
 
-        let size = calculate_dir_size(path);
-        self.cached_docker_size.store(size, Ordering::Relaxed);
-        debug!("Updated Docker size: {} bytes", size);
+    /// Take a top-N process snapshot for the next sample
+    pub fn collect_processes(&mut self) {
+        if self.top_processes > 0 {
+            self.pending_processes = Some(processes::snapshot(
+                &mut self.system,
+                &mut self.users,
+                self.top_processes,
+            ));
+        }
     }
 
-    pub fn get_cached_docker_size(&self) -> Arc<AtomicU64> {
-        Arc::clone(&self.cached_docker_size)
+    /// Record series read from the textfile directory
+    pub fn set_values(&mut self, values: Vec<SeriesValue>) {
+        self.values = values;
+    }
+
+    /// Record a systemd poll; `None` where systemd isn't available
+    pub fn set_systemd(&mut self, status: Option<SystemdStatus>) {
+        self.systemd = status;
+    }
+
+    /// Record a sensor poll; `None` on hosts without any sensors
+    pub fn set_sensors(&mut self, sensors: Option<SensorReadings>) {
+        self.sensors = sensors;
+    }
+
+    /// Record mount space and inode figures - called every 10 seconds
+    pub fn set_mounts(&mut self, mounts: MountUsage) {
+        self.mounts = mounts;
+    }
+
+    /// Record Docker disk usage - called every 5 minutes
+    pub fn set_docker_disk_usage(&mut self, disk: Option<DockerDiskUsage>) {
+        if let Some(summary) = &mut self.docker {
+            summary.disk = disk.clone();
+        }
+        self.docker_disk = disk;
     }
 
     fn calculate_ram_usage(&self) -> f64 {
@@ -102,80 +203,82 @@ impl MetricCollector {
         (used as f64 / total as f64) * 100.0
     }
 
-    fn calculate_disk_usage(&mut self) -> (f64, f64) {
-        self.disks.refresh();
-
-        // Find root disk
-        let root_disk = self.disks.iter().find(|d| d.mount_point() == Path::new("/"));
+    fn calculate_disk_usage(&self) -> (f64, f64, Vec<FilesystemUsage>) {
+        let filesystems = self.mounts.filesystems.clone();
 
-        if let Some(disk) = root_disk {
-            let total = disk.total_space();
-            let available = disk.available_space();
-            let used = total.saturating_sub(available);
+        // The root disk keeps feeding the headline figures, whatever the filter
+        match &self.mounts.root {
+            Some(root) => (
+                root.disk_usage,
+                root.inode_usage.unwrap_or(0.0),
+                filesystems,
+            ),
+            None => {
+                warn!("Root disk not found");
+                (0.0, 0.0, filesystems)
+            }
+        }
+    }
+}
 
-            let disk_usage = if total > 0 {
-                (used as f64 / total as f64) * 100.0
-            } else {
-                0.0
-            };
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            // Calculate inode usage using statfs
-            let inode_usage = calculate_inode_usage("/");
+    struct FakeGpus(Vec<GpuDetail>);
 
-            (disk_usage, inode_usage)
-        } else {
-            warn!("Root disk not found");
-            (0.0, 0.0)
+    impl GpuSource for FakeGpus {
+        fn devices(&self) -> Vec<GpuDetail> {
+            self.0.clone()
         }
     }
 
-    fn collect_gpu_usage(&self) -> Option<f64> {
-        let nvml = self.nvml.as_ref()?;
-
-        // Try to get the first GPU
-        let device = nvml.device_by_index(0).ok()?;
-        let utilization = device.utilization_rates().ok()?;
+    fn gpu(index: u32, utilization: Option<f64>) -> GpuDetail {
+        GpuDetail {
+            index,
+            name: "NVIDIA A100-SXM4-80GB".to_string(),
+            utilization,
+            memory_used: Some(40 << 30),
+            memory_total: Some(80 << 30),
+            ..Default::default()
+        }
+    }
 
-        Some(utilization.gpu as f64)
+    fn collector() -> MetricCollector {
+        MetricCollector::new(&Config {
+            server_url: "http://localhost:8080".to_string(),
+            token: "token".to_string(),
+            hostname: "test".to_string(),
+            docker_socket: "/var/run/docker.sock".to_string(),
+            mount_filter: Default::default(),
+            interface_filter: Default::default(),
+            device_filter: Default::default(),
+            systemd_units: vec![],
+            top_processes: 0,
+            textfile_dir: None,
+            github_repo: None,
+        })
     }
-}
 
-fn calculate_dir_size(path: &Path) -> u64 {
-    WalkDir::new(path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter_map(|e| e.metadata().ok())
-        .filter(|m| m.is_file())
-        .map(|m| m.len())
-        .sum()
-}
+    #[test]
+    fn test_collect_fast_reports_each_gpu() {
+        let mut collector = collector();
+        collector.gpu_source = Some(Box::new(FakeGpus(vec![
+            gpu(0, Some(100.0)),
+            gpu(1, Some(50.0)),
+            gpu(2, None),
+        ])));
 
-#[cfg(target_os = "linux")]
-fn calculate_inode_usage(path: &str) -> f64 {
-    use std::ffi::CString;
-    use std::mem::MaybeUninit;
-
-    let c_path = match CString::new(path) {
-        Ok(p) => p,
-        Err(_) => return 0.0,
-    };
-
-    unsafe {
-        let mut stat: MaybeUninit<libc::statfs> = MaybeUninit::uninit();
-        if libc::statfs(c_path.as_ptr(), stat.as_mut_ptr()) == 0 {
-            let stat = stat.assume_init();
-            let total_inodes = stat.f_files;
-            let free_inodes = stat.f_ffree;
-            if total_inodes > 0 {
-                let used_inodes = total_inodes - free_inodes;
-                return (used_inodes as f64 / total_inodes as f64) * 100.0;
-            }
-        }
-    }
-    0.0
-}
+        let metric = collector.collect_fast();
+        let indexes: Vec<u32> = metric.gpus.iter().map(|g| g.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2]);
+        assert_eq!(metric.gpus[1].memory_used, Some(40 << 30));
+        assert_eq!(metric.gpu_usage, Some(75.0));
 
-#[cfg(not(target_os = "linux"))]
-fn calculate_inode_usage(_path: &str) -> f64 {
-    0.0
+        // Without a source there is nothing to report
+        collector.gpu_source = None;
+        let metric = collector.collect_fast();
+        assert!(metric.gpus.is_empty());
+        assert_eq!(metric.gpu_usage, None);
+    }
 }
@@ -0,0 +1,173 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::time::Instant;
+
+use crate::config::pattern_allows;
+
+/// Per-second rates of one network interface
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NetworkUsage {
+    pub interface: String,
+    pub rx_bytes: f64,
+    pub tx_bytes: f64,
+    pub rx_packets: f64,
+    pub tx_packets: f64,
+    pub rx_errors: f64,
+    pub tx_errors: f64,
+    pub rx_drops: f64,
+    pub tx_drops: f64,
+}
+
+/// Which interfaces to report, using the same patterns as mounts
+#[derive(Debug, Clone, Default)]
+pub struct InterfaceFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+/// Cumulative counters of one /proc/net/dev line
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Counters([u64; 8]);
+
+/// Counters per interface from the contents of /proc/net/dev
+fn parse_net_dev(text: &str) -> HashMap<String, Counters> {
+    text.lines()
+        .filter_map(|line| {
+            let (name, fields) = line.split_once(':')?;
+            let fields: Vec<u64> = fields
+                .split_whitespace()
+                .map(|f| f.parse().ok())
+                .collect::<Option<_>>()?;
+            if fields.len() < 12 {
+                return None;
+            }
+            // Receive: bytes packets errs drop ...; transmit starts at field 8
+            Some((
+                name.trim().to_string(),
+                Counters([
+                    fields[0], fields[8], fields[1], fields[9], fields[2], fields[10], fields[3],
+                    fields[11],
+                ]),
+            ))
+        })
+        .collect()
+}
+
+/// Turns successive /proc/net/dev readings into per-second rates
+pub struct NetworkSampler {
+    filter: InterfaceFilter,
+    last: Option<(Instant, HashMap<String, Counters>)>,
+}
+
+impl NetworkSampler {
+    pub fn new(filter: InterfaceFilter) -> Self {
+        Self { filter, last: None }
+    }
+
+    /// Read /proc; empty on the first call or where /proc isn't available
+    pub fn sample(&mut self) -> Vec<NetworkUsage> {
+        let text = match fs::read_to_string("/proc/net/dev") {
+            Ok(text) => text,
+            Err(_) => return vec![],
+        };
+        let now = Instant::now();
+        let elapsed = self
+            .last
+            .as_ref()
+            .map(|(at, _)| now.duration_since(*at).as_secs_f64());
+        let counters = parse_net_dev(&text);
+        let previous = self.last.replace((now, counters.clone()));
+
+        match (previous, elapsed) {
+            (Some((_, previous)), Some(elapsed)) => self.rates(&previous, &counters, elapsed),
+            _ => vec![],
+        }
+    }
+
+    fn rates(
+        &self,
+        previous: &HashMap<String, Counters>,
+        current: &HashMap<String, Counters>,
+        elapsed: f64,
+    ) -> Vec<NetworkUsage> {
+        if elapsed <= 0.0 {
+            return vec![];
+        }
+
+        let mut usage: Vec<NetworkUsage> = current
+            .iter()
+            .filter(|(name, _)| pattern_allows(name, &self.filter.include, &self.filter.exclude))
+            .filter_map(|(name, now)| {
+                let prev = previous.get(name)?;
+                // Counters reset when an interface is recreated; skip that tick
+                if now.0.iter().zip(prev.0.iter()).any(|(n, p)| n < p) {
+                    return None;
+                }
+                let rate = |i: usize| (now.0[i] - prev.0[i]) as f64 / elapsed;
+                Some(NetworkUsage {
+                    interface: name.clone(),
+                    rx_bytes: rate(0),
+                    tx_bytes: rate(1),
+                    rx_packets: rate(2),
+                    tx_packets: rate(3),
+                    rx_errors: rate(4),
+                    tx_errors: rate(5),
+                    rx_drops: rate(6),
+                    tx_drops: rate(7),
+                })
+            })
+            .collect();
+        usage.sort_by(|a, b| a.interface.cmp(&b.interface));
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NET_DEV_1: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:    1000      10    0    0    0     0          0         0     1000      10    0    0    0     0       0          0
+  eth0: 5000000    4000    1    2    0     0          0         0  2000000    3000    0    0    0     0       0          0
+";
+    const NET_DEV_2: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:    3000      30    0    0    0     0          0         0     3000      30    0    0    0     0       0          0
+  eth0: 7000000    6000    3    2    0     0          0         0  2500000    3500    0    4    0     0       0          0
+";
+
+    #[test]
+    fn test_rates_between_readings() {
+        let sampler = NetworkSampler::new(InterfaceFilter {
+            include: vec![],
+            exclude: vec!["lo".to_string()],
+        });
+        let usage = sampler.rates(&parse_net_dev(NET_DEV_1), &parse_net_dev(NET_DEV_2), 2.0);
+
+        assert_eq!(
+            usage,
+            vec![NetworkUsage {
+                interface: "eth0".to_string(),
+                rx_bytes: 1_000_000.0,
+                tx_bytes: 250_000.0,
+                rx_packets: 1000.0,
+                tx_packets: 250.0,
+                rx_errors: 1.0,
+                tx_errors: 0.0,
+                rx_drops: 0.0,
+                tx_drops: 2.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_counter_reset_is_skipped() {
+        let sampler = NetworkSampler::new(InterfaceFilter::default());
+        let usage = sampler.rates(&parse_net_dev(NET_DEV_2), &parse_net_dev(NET_DEV_1), 1.0);
+        assert!(usage.is_empty());
+    }
+}
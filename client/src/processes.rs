@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind, Users};
+
+/// One process in a top-N snapshot
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    pub user: Option<String>,
+    /// CPU usage in percent of one core since the previous snapshot
+    pub cpu_percent: f64,
+    pub rss_bytes: u64,
+}
+
+/// Refresh the process table and take a top-N snapshot
+pub fn snapshot(system: &mut System, users: &mut Users, n: usize) -> Vec<ProcessInfo> {
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::new()
+            .with_cpu()
+            .with_memory()
+            .with_user(UpdateKind::OnlyIfNotSet),
+    );
+    users.refresh_list();
+
+    let all = system
+        .processes()
+        .values()
+        .map(|p| ProcessInfo {
+            pid: p.pid().as_u32(),
+            name: p.name().to_string_lossy().to_string(),
+            user: p
+                .user_id()
+                .and_then(|uid| users.get_user_by_id(uid))
+                .map(|u| u.name().to_string()),
+            cpu_percent: p.cpu_usage() as f64,
+            rss_bytes: p.memory(),
+        })
+        .collect();
+    top(all, n)
+}
+
+/// The `n` busiest processes by CPU plus the `n` largest by memory, so both
+/// CPU and RAM alerts have their culprits; sorted by CPU
+fn top(mut all: Vec<ProcessInfo>, n: usize) -> Vec<ProcessInfo> {
+    all.sort_by_key(|p| Reverse(p.rss_bytes));
+    let largest: Vec<u32> = all.iter().take(n).map(|p| p.pid).collect();
+
+    all.sort_by(|a, b| b.cpu_percent.total_cmp(&a.cpu_percent));
+    let mut top: Vec<ProcessInfo> = Vec::with_capacity(n * 2);
+    for (rank, process) in all.into_iter().enumerate() {
+        if rank < n || largest.contains(&process.pid) {
+            top.push(process);
+        }
+    }
+    top
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, cpu_percent: f64, rss_bytes: u64) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            name: format!("proc{pid}"),
+            cpu_percent,
+            rss_bytes,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_top_by_cpu_and_memory() {
+        let all = vec![
+            process(1, 0.1, 10 << 20),
+            process(2, 95.0, 200 << 20),
+            process(3, 0.0, 8 << 30),
+            process(4, 40.0, 50 << 20),
+            process(5, 1.0, 1 << 20),
+        ];
+
+        let pids: Vec<u32> = top(all, 2).iter().map(|p| p.pid).collect();
+        assert_eq!(pids, vec![2, 4, 3]);
+    }
+}
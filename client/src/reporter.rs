@@ -31,10 +31,6 @@ impl Reporter {
         }
     }
 
-    pub fn get_buffer(&self) -> Arc<Mutex<Vec<Metric>>> {
-        Arc::clone(&self.buffer)
-    }
-
     /// Add a metric to the buffer
     pub async fn add_metric(&self, metric: Metric) {
         let mut buffer = self.buffer.lock().await;
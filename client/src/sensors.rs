@@ -0,0 +1,239 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Temperatures and fan speeds from hwmon and thermal zones
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SensorReadings {
+    pub temperatures: Vec<Temperature>,
+    pub fans: Vec<Fan>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Temperature {
+    /// Driver name, e.g. coretemp, k10temp, nvme or acpitz
+    pub chip: String,
+    /// `tempN_label` where the driver provides one, e.g. "Package id 0", else "tempN"
+    pub label: String,
+    pub celsius: f64,
+    /// Driver's warning threshold
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub high: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critical: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fan {
+    pub chip: String,
+    /// `fanN_label` where the driver provides one, else "fanN"
+    pub label: String,
+    pub rpm: f64,
+}
+
+/// Read every hwmon chip under `sys` (normally /sys), plus thermal zones that
+/// aren't also registered as hwmon chips
+pub fn collect(sys: &Path) -> SensorReadings {
+    let mut readings = SensorReadings::default();
+
+    for dir in sorted_entries(&sys.join("class/hwmon")) {
+        let Some(chip) = read_trimmed(&dir.join("name")) else {
+            continue;
+        };
+        read_hwmon(&dir, &chip, &mut readings);
+    }
+
+    for dir in sorted_entries(&sys.join("class/thermal")) {
+        let is_zone = dir
+            .file_name()
+            .is_some_and(|n| n.to_string_lossy().starts_with("thermal_zone"));
+        let Some(zone_type) = read_trimmed(&dir.join("type")).filter(|_| is_zone) else {
+            continue;
+        };
+        if readings.temperatures.iter().any(|t| t.chip == zone_type) {
+            continue;
+        }
+        if let Some(celsius) = read_millis(&dir.join("temp")) {
+            readings.temperatures.push(Temperature {
+                chip: zone_type.clone(),
+                label: zone_type,
+                celsius,
+                ..Default::default()
+            });
+        }
+    }
+
+    readings
+}
+
+fn read_hwmon(dir: &Path, chip: &str, readings: &mut SensorReadings) {
+    for (index, name) in channels(dir, "temp") {
+        let Some(celsius) = read_millis(&dir.join(format!("{name}_input"))) else {
+            continue;
+        };
+        readings.temperatures.push(Temperature {
+            chip: chip.to_string(),
+            label: read_trimmed(&dir.join(format!("{name}_label")))
+                .unwrap_or_else(|| format!("temp{index}")),
+            celsius,
+            high: read_millis(&dir.join(format!("{name}_max"))),
+            critical: read_millis(&dir.join(format!("{name}_crit"))),
+        });
+    }
+
+    for (index, name) in channels(dir, "fan") {
+        let Some(rpm) =
+            read_trimmed(&dir.join(format!("{name}_input"))).and_then(|v| v.parse::<f64>().ok())
+        else {
+            continue;
+        };
+        readings.fans.push(Fan {
+            chip: chip.to_string(),
+            label: read_trimmed(&dir.join(format!("{name}_label")))
+                .unwrap_or_else(|| format!("fan{index}")),
+            rpm,
+        });
+    }
+}
+
+/// `tempN`/`fanN` channels with an `_input` file, in numeric order
+fn channels(dir: &Path, prefix: &str) -> Vec<(u32, String)> {
+    let mut channels: Vec<(u32, String)> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.ok()?.file_name().to_string_lossy().to_string();
+            let index = file.strip_prefix(prefix)?.strip_suffix("_input")?;
+            Some((index.parse().ok()?, format!("{prefix}{index}")))
+        })
+        .collect();
+    channels.sort();
+    channels
+}
+
+/// Directory entries sorted by name and then numeric suffix, so hwmon10
+/// follows hwmon9 consistently
+fn sorted_entries(dir: &Path) -> Vec<std::path::PathBuf> {
+    let mut entries: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    entries.sort_by_cached_key(|path| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        let index: Option<u64> = name[prefix.len()..].parse().ok();
+        (prefix.to_string(), index)
+    });
+    entries
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let text = fs::read_to_string(path).ok()?;
+    Some(text.trim().to_string()).filter(|s| !s.is_empty())
+}
+
+/// sysfs temperatures are in millidegrees Celsius
+fn read_millis(path: &Path) -> Option<f64> {
+    let millis: f64 = read_trimmed(path)?.parse().ok()?;
+    Some(millis / 1000.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sorted_entries_in_numeric_order() {
+        let dir = std::env::temp_dir().join(format!("fake-hwmon-{}", std::process::id()));
+        for name in ["hwmon10", "hwmon2", "hwmon9", "hwmon0"] {
+            fs::create_dir_all(dir.join(name)).unwrap();
+        }
+
+        let names: Vec<String> = sorted_entries(&dir)
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        fs::remove_dir_all(&dir).ok();
+
+        assert_eq!(names, vec!["hwmon0", "hwmon2", "hwmon9", "hwmon10"]);
+    }
+
+    #[test]
+    fn test_collect_from_fake_sysfs() {
+        let sys = std::env::temp_dir().join(format!("fake-sysfs-{}", std::process::id()));
+        let write = |path: &str, contents: &str| {
+            let path = sys.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        };
+        write("class/hwmon/hwmon0/name", "coretemp\n");
+        write("class/hwmon/hwmon0/temp1_input", "64000\n");
+        write("class/hwmon/hwmon0/temp1_label", "Package id 0\n");
+        write("class/hwmon/hwmon0/temp1_max", "80000\n");
+        write("class/hwmon/hwmon0/temp1_crit", "100000\n");
+        write("class/hwmon/hwmon0/temp2_input", "58500\n");
+        write("class/hwmon/hwmon0/temp2_label", "Core 0\n");
+        write("class/hwmon/hwmon1/name", "nct6775\n");
+        write("class/hwmon/hwmon1/fan2_input", "1250\n");
+        write("class/hwmon/hwmon1/fan1_input", "0\n");
+        write("class/hwmon/hwmon1/fan1_label", "CPU Fan\n");
+        write("class/hwmon/hwmon2/name", "acpitz\n");
+        write("class/hwmon/hwmon2/temp1_input", "27800\n");
+        // acpitz again as a thermal zone, and one that only exists there
+        write("class/thermal/thermal_zone0/type", "acpitz\n");
+        write("class/thermal/thermal_zone0/temp", "27800\n");
+        write("class/thermal/thermal_zone1/type", "x86_pkg_temp\n");
+        write("class/thermal/thermal_zone1/temp", "65000\n");
+        write("class/thermal/cooling_device0/type", "Processor\n");
+
+        let readings = collect(&sys);
+        fs::remove_dir_all(&sys).ok();
+
+        assert_eq!(
+            readings.temperatures,
+            vec![
+                Temperature {
+                    chip: "coretemp".to_string(),
+                    label: "Package id 0".to_string(),
+                    celsius: 64.0,
+                    high: Some(80.0),
+                    critical: Some(100.0),
+                },
+                Temperature {
+                    chip: "coretemp".to_string(),
+                    label: "Core 0".to_string(),
+                    celsius: 58.5,
+                    ..Default::default()
+                },
+                Temperature {
+                    chip: "acpitz".to_string(),
+                    label: "temp1".to_string(),
+                    celsius: 27.8,
+                    ..Default::default()
+                },
+                Temperature {
+                    chip: "x86_pkg_temp".to_string(),
+                    label: "x86_pkg_temp".to_string(),
+                    celsius: 65.0,
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(
+            readings.fans,
+            vec![
+                Fan {
+                    chip: "nct6775".to_string(),
+                    label: "CPU Fan".to_string(),
+                    rpm: 0.0,
+                },
+                Fan {
+                    chip: "nct6775".to_string(),
+                    label: "fan2".to_string(),
+                    rpm: 1250.0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+
+/// Failed units and the state of the watched ones, sent with every sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemdStatus {
+    /// Every unit systemd currently lists as failed
+    pub failed: Vec<String>,
+    /// Units from `SYSTEMD_UNITS`, by their full name (`nginx` becomes `nginx.service`)
+    pub watched: Vec<UnitState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UnitState {
+    pub name: String,
+    /// active, inactive, failed, activating, deactivating or reloading
+    pub active_state: String,
+    /// Unit-type specific detail, e.g. running, exited or dead
+    pub sub_state: String,
+}
+
+/// Ask systemctl; errors where systemd isn't running
+pub async fn collect(watched: &[String]) -> anyhow::Result<SystemdStatus> {
+    let failed = systemctl(&[
+        "list-units",
+        "--all",
+        "--state=failed",
+        "--plain",
+        "--no-legend",
+        "--no-pager",
+    ])
+    .await?;
+
+    let watched = if watched.is_empty() {
+        vec![]
+    } else {
+        let mut args = vec!["show", "--property=Id,ActiveState,SubState", "--"];
+        args.extend(watched.iter().map(String::as_str));
+        parse_show(&systemctl(&args).await?)
+    };
+
+    Ok(SystemdStatus {
+        failed: parse_failed(&failed),
+        watched,
+    })
+}
+
+async fn systemctl(args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("systemctl")
+        .args(args)
+        .output()
+        .await
+        .context("running systemctl")?;
+    if !output.status.success() {
+        bail!(
+            "systemctl {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Unit names from `systemctl list-units --plain --no-legend`
+fn parse_failed(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            // Older versions still prefix failed units with a bullet
+            let line = line.trim_start().trim_start_matches('●').trim_start();
+            line.split_whitespace().next().map(str::to_string)
+        })
+        .collect()
+}
+
+/// Property blocks from `systemctl show`, one per unit, blank-line separated
+fn parse_show(text: &str) -> Vec<UnitState> {
+    text.split("\n\n")
+        .filter_map(|block| {
+            let mut unit = UnitState::default();
+            for line in block.lines() {
+                match line.split_once('=') {
+                    Some(("Id", v)) => unit.name = v.to_string(),
+                    Some(("ActiveState", v)) => unit.active_state = v.to_string(),
+                    Some(("SubState", v)) => unit.sub_state = v.to_string(),
+                    _ => {}
+                }
+            }
+            (!unit.name.is_empty()).then_some(unit)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_systemctl_output() {
+        let failed = "\
+nginx.service     loaded failed failed A high performance web server
+● backup.timer   loaded failed failed Nightly backup
+";
+        assert_eq!(parse_failed(failed), vec!["nginx.service", "backup.timer"]);
+        assert!(parse_failed("").is_empty());
+
+        let show = "\
+Id=nginx.service
+ActiveState=failed
+SubState=failed
+
+SubState=running
+ActiveState=active
+Id=postgresql.service
+";
+        assert_eq!(
+            parse_show(show),
+            vec![
+                UnitState {
+                    name: "nginx.service".to_string(),
+                    active_state: "failed".to_string(),
+                    sub_state: "failed".to_string(),
+                },
+                UnitState {
+                    name: "postgresql.service".to_string(),
+                    active_state: "active".to_string(),
+                    sub_state: "running".to_string(),
+                },
+            ]
+        );
+    }
+}
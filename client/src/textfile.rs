@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use tracing::debug;
+
+/// One value of a named series, e.g. `queue_depth{queue="mail"} 12`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SeriesValue {
+    pub name: String,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+/// Series from every `*.prom` file in `dir`, in the Prometheus text format
+/// node_exporter's textfile collector reads, so cron jobs and scripts can
+/// publish their own metrics
+pub fn collect(dir: &Path) -> Vec<SeriesValue> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|ext| ext == "prom"))
+        .collect();
+    files.sort();
+
+    files
+        .iter()
+        .filter_map(|path| {
+            fs::read_to_string(path)
+                .map_err(|e| debug!("Skipping {}: {}", path.display(), e))
+                .ok()
+        })
+        .flat_map(|text| parse(&text))
+        .collect()
+}
+
+/// Sample lines of a text exposition; comments, timestamps and lines that
+/// don't parse are skipped
+fn parse(text: &str) -> Vec<SeriesValue> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(parse_line)
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<SeriesValue> {
+    let name_end = line
+        .find(|c: char| c == '{' || c.is_whitespace())
+        .unwrap_or(line.len());
+    let name = &line[..name_end];
+    if !is_valid_name(name) {
+        return None;
+    }
+
+    let mut rest = &line[name_end..];
+    let mut labels = BTreeMap::new();
+    if let Some(body) = rest.strip_prefix('{') {
+        let (parsed, after) = parse_labels(body)?;
+        labels = parsed;
+        rest = after;
+    }
+
+    let value: f64 = rest.split_whitespace().next()?.parse().ok()?;
+    value.is_finite().then(|| SeriesValue {
+        name: name.to_string(),
+        labels,
+        value,
+    })
+}
+
+/// `key="value",...}` up to and including the closing brace
+fn parse_labels(mut body: &str) -> Option<(BTreeMap<String, String>, &str)> {
+    let mut labels = BTreeMap::new();
+    loop {
+        body = body.trim_start().trim_start_matches(',').trim_start();
+        if let Some(rest) = body.strip_prefix('}') {
+            return Some((labels, rest));
+        }
+
+        let (key, rest) = body.split_once('=')?;
+        let key = key.trim();
+        if !is_valid_name(key) {
+            return None;
+        }
+
+        let mut value = String::new();
+        let mut chars = rest.trim_start().strip_prefix('"')?.char_indices();
+        let end = loop {
+            match chars.next()? {
+                (i, '"') => break i,
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                (_, c) => value.push(c),
+            }
+        };
+        labels.insert(key.to_string(), value);
+        body = &rest.trim_start()[1 + end + 1..];
+    }
+}
+
+/// `[a-zA-Z_:][a-zA-Z0-9_:]*`
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_exposition() {
+        let text = r#"
+# HELP backup_age_seconds Time since the last successful backup
+# TYPE backup_age_seconds gauge
+backup_age_seconds 3600
+queue_depth{queue="mail",env="prod"} 12 1700000000000
+queue_depth{ queue = "say \"hi\"\n" } 3.5
+broken{queue="mail" 1
+9lives 1
+temperature NaN
+"#;
+        let labels = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+
+        assert_eq!(
+            parse(text),
+            vec![
+                SeriesValue {
+                    name: "backup_age_seconds".to_string(),
+                    labels: BTreeMap::new(),
+                    value: 3600.0,
+                },
+                SeriesValue {
+                    name: "queue_depth".to_string(),
+                    labels: labels(&[("env", "prod"), ("queue", "mail")]),
+                    value: 12.0,
+                },
+                SeriesValue {
+                    name: "queue_depth".to_string(),
+                    labels: labels(&[("queue", "say \"hi\"\n")]),
+                    value: 3.5,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+-- Initial PostgreSQL schema, equivalent to SQLite's as of 017_metric_values.
+-- Timestamps are kept as the RFC 3339 text clients and the server produce,
+-- except that samples also carry a TIMESTAMPTZ for range scans and
+-- partitioning. Per-sample detail is stored as JSONB on the sample itself
+-- instead of in side tables, which keeps ingest to one row per sample and
+-- lets the table become a hypertable.
+
+-- Clients table: Registry of monitored servers
+CREATE TABLE IF NOT EXISTS clients (
+    id TEXT PRIMARY KEY NOT NULL,
+    hostname TEXT NOT NULL,
+    token TEXT NOT NULL UNIQUE,
+    last_seen TEXT NOT NULL,
+    version TEXT
+);
+
+-- Metrics table: Time-series data, with CPU, memory, Docker, systemd and
+-- sensor detail, per-device readings and reported series as JSONB
+CREATE TABLE IF NOT EXISTS metrics (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY,
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    cpu_usage DOUBLE PRECISION NOT NULL,
+    ram_usage DOUBLE PRECISION NOT NULL,
+    disk_usage DOUBLE PRECISION NOT NULL,
+    inode_usage DOUBLE PRECISION NOT NULL,
+    docker_sz BIGINT,
+    gpu_usage DOUBLE PRECISION,
+    cpu JSONB,
+    memory JSONB,
+    docker JSONB,
+    systemd JSONB,
+    sensors JSONB,
+    gpus JSONB NOT NULL DEFAULT '[]',
+    filesystems JSONB NOT NULL DEFAULT '[]',
+    network JSONB NOT NULL DEFAULT '[]',
+    disk_io JSONB NOT NULL DEFAULT '[]',
+    "values" JSONB NOT NULL DEFAULT '[]',
+    timestamp TEXT NOT NULL,
+    time TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (id, time)
+);
+
+-- Index for efficient range queries
+CREATE INDEX IF NOT EXISTS idx_metrics_client_time ON metrics(client_id, time);
+
+-- With TimescaleDB installed in this database, partition samples by time
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'timescaledb') THEN
+        PERFORM create_hypertable('metrics', 'time', if_not_exists => TRUE, migrate_data => TRUE);
+    END IF;
+END
+$$;
+
+-- Alert rules table: User-defined thresholds
+CREATE TABLE IF NOT EXISTS alert_rules (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id TEXT REFERENCES clients(id) ON DELETE CASCADE,
+    metric_type TEXT NOT NULL,
+    threshold DOUBLE PRECISION NOT NULL,
+    duration_sec BIGINT NOT NULL DEFAULT 30
+);
+
+-- Alert rule targets table: Mount point (or other instance) a rule watches
+CREATE TABLE IF NOT EXISTS alert_rule_targets (
+    rule_id BIGINT PRIMARY KEY NOT NULL REFERENCES alert_rules(id) ON DELETE CASCADE,
+    target TEXT NOT NULL
+);
+
+-- Settings table: Global configuration
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL
+);
+
+-- Incidents table: One row per alert firing, closed when the metric recovers
+CREATE TABLE IF NOT EXISTS incidents (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    rule_id BIGINT REFERENCES alert_rules(id) ON DELETE SET NULL,
+    metric_type TEXT NOT NULL,
+    threshold DOUBLE PRECISION NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    peak_value DOUBLE PRECISION NOT NULL
+);
+
+-- Index for per-client postmortem queries
+CREATE INDEX IF NOT EXISTS idx_incidents_client_started ON incidents(client_id, started_at);
+
+-- Notification channels table: Named destinations for alert notifications
+CREATE TABLE IF NOT EXISTS notification_channels (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL,
+    config TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+-- Alert rule channels table: Which channels each alert rule notifies
+CREATE TABLE IF NOT EXISTS alert_rule_channels (
+    rule_id BIGINT NOT NULL REFERENCES alert_rules(id) ON DELETE CASCADE,
+    channel_id BIGINT NOT NULL REFERENCES notification_channels(id) ON DELETE CASCADE,
+    PRIMARY KEY (rule_id, channel_id)
+);
+
+-- API keys table: Credentials for the management API
+CREATE TABLE IF NOT EXISTS api_keys (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    key_hash TEXT NOT NULL UNIQUE,
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+-- Metric rollups: Downsampled min/max/avg/p95/count per metric type.
+-- resolution is the bucket width in seconds (60, 3600, 86400) and bucket
+-- the RFC 3339 start of the bucket.
+CREATE TABLE IF NOT EXISTS metric_rollups (
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    resolution BIGINT NOT NULL,
+    metric_type TEXT NOT NULL,
+    bucket TEXT NOT NULL,
+    min DOUBLE PRECISION NOT NULL,
+    max DOUBLE PRECISION NOT NULL,
+    avg DOUBLE PRECISION NOT NULL,
+    p95 DOUBLE PRECISION NOT NULL,
+    count BIGINT NOT NULL,
+    PRIMARY KEY (client_id, resolution, metric_type, bucket)
+);
+
+-- Index for per-tier retention cleanup
+CREATE INDEX IF NOT EXISTS idx_metric_rollups_resolution_bucket ON metric_rollups(resolution, bucket);
+
+-- Rollup progress: Buckets before completed_until have been aggregated
+CREATE TABLE IF NOT EXISTS rollup_progress (
+    resolution BIGINT PRIMARY KEY,
+    completed_until TEXT NOT NULL
+);
+
+-- Client settings table: Per-client overrides of global settings
+CREATE TABLE IF NOT EXISTS client_settings (
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (client_id, key)
+);
+
+-- Containers table: The latest container list of each client, replaced on every poll
+CREATE TABLE IF NOT EXISTS containers (
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    container_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    image TEXT NOT NULL,
+    state TEXT NOT NULL,
+    health TEXT,
+    restart_count BIGINT NOT NULL,
+    cpu_percent DOUBLE PRECISION,
+    memory_used BIGINT,
+    memory_limit BIGINT,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (client_id, container_id)
+);
+
+-- Process snapshots table: Recent top-N process lists of each client
+CREATE TABLE IF NOT EXISTS process_snapshots (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    timestamp TEXT NOT NULL,
+    processes JSONB NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_process_snapshots_client_time ON process_snapshots(client_id, timestamp);
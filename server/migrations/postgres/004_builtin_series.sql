@@ -0,0 +1,23 @@
+-- Built-in series: The headline fields are stored in "values" as
+-- unlabelled series named after their metric types, like any other series
+UPDATE metrics SET "values" = jsonb_build_array(
+        jsonb_build_object('name', 'cpu', 'labels', '{}'::jsonb, 'value', cpu_usage),
+        jsonb_build_object('name', 'ram', 'labels', '{}'::jsonb, 'value', ram_usage),
+        jsonb_build_object('name', 'disk', 'labels', '{}'::jsonb, 'value', disk_usage),
+        jsonb_build_object('name', 'inode', 'labels', '{}'::jsonb, 'value', inode_usage)
+    )
+    || CASE WHEN gpu_usage IS NULL THEN '[]'::jsonb ELSE jsonb_build_array(
+        jsonb_build_object('name', 'gpu', 'labels', '{}'::jsonb, 'value', gpu_usage)
+    ) END
+    || CASE WHEN docker_sz IS NULL THEN '[]'::jsonb ELSE jsonb_build_array(
+        jsonb_build_object('name', 'docker', 'labels', '{}'::jsonb, 'value', docker_sz)
+    ) END
+    || "values";
+
+ALTER TABLE metrics
+    DROP COLUMN cpu_usage,
+    DROP COLUMN ram_usage,
+    DROP COLUMN disk_usage,
+    DROP COLUMN inode_usage,
+    DROP COLUMN docker_sz,
+    DROP COLUMN gpu_usage;
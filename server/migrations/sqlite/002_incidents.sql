@@ -0,0 +1,14 @@
+-- Incidents table: One row per alert firing, closed when the metric recovers
+CREATE TABLE IF NOT EXISTS incidents (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    rule_id INTEGER REFERENCES alert_rules(id) ON DELETE SET NULL,
+    metric_type TEXT NOT NULL,
+    threshold REAL NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT,
+    peak_value REAL NOT NULL
+);
+
+-- Index for per-client postmortem queries
+CREATE INDEX IF NOT EXISTS idx_incidents_client_started ON incidents(client_id, started_at);
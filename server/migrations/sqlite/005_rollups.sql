@@ -0,0 +1,24 @@
+-- Metric rollups: Downsampled min/max/avg/p95/count per metric type.
+-- resolution is the bucket width in seconds (60, 3600, 86400) and bucket
+-- the RFC 3339 start of the bucket.
+CREATE TABLE IF NOT EXISTS metric_rollups (
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    resolution INTEGER NOT NULL,
+    metric_type TEXT NOT NULL,
+    bucket TEXT NOT NULL,
+    min REAL NOT NULL,
+    max REAL NOT NULL,
+    avg REAL NOT NULL,
+    p95 REAL NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (client_id, resolution, metric_type, bucket)
+);
+
+-- Index for per-tier retention cleanup
+CREATE INDEX IF NOT EXISTS idx_metric_rollups_resolution_bucket ON metric_rollups(resolution, bucket);
+
+-- Rollup progress: Buckets before completed_until have been aggregated
+CREATE TABLE IF NOT EXISTS rollup_progress (
+    resolution INTEGER PRIMARY KEY,
+    completed_until TEXT NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Client settings table: Per-client overrides of global settings
+CREATE TABLE IF NOT EXISTS client_settings (
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    PRIMARY KEY (client_id, key)
+);
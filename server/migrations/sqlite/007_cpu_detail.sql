@@ -0,0 +1,5 @@
+-- CPU detail table: Per-sample CPU breakdown (user/system/iowait/steal, load, per-core) as JSON
+CREATE TABLE IF NOT EXISTS cpu_metrics (
+    metric_id INTEGER PRIMARY KEY NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    detail TEXT NOT NULL
+);
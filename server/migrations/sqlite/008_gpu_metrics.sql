@@ -0,0 +1,15 @@
+-- GPU metrics table: Per-device readings of each sample
+CREATE TABLE IF NOT EXISTS gpu_metrics (
+    metric_id INTEGER NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    gpu_index INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    uuid TEXT,
+    utilization REAL,
+    memory_used INTEGER,
+    memory_total INTEGER,
+    temperature REAL,
+    power_watts REAL,
+    fan_speed REAL,
+    processes INTEGER,
+    PRIMARY KEY (metric_id, gpu_index)
+);
@@ -0,0 +1,18 @@
+-- Filesystem metrics table: Per-mount space and inode usage of each sample
+CREATE TABLE IF NOT EXISTS filesystem_metrics (
+    metric_id INTEGER NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    mount_point TEXT NOT NULL,
+    device TEXT NOT NULL,
+    fs_type TEXT NOT NULL,
+    total_bytes INTEGER NOT NULL,
+    used_bytes INTEGER NOT NULL,
+    disk_usage REAL NOT NULL,
+    inode_usage REAL,
+    PRIMARY KEY (metric_id, mount_point)
+);
+
+-- Alert rule targets table: Mount point (or other instance) a rule watches
+CREATE TABLE IF NOT EXISTS alert_rule_targets (
+    rule_id INTEGER PRIMARY KEY NOT NULL REFERENCES alert_rules(id) ON DELETE CASCADE,
+    target TEXT NOT NULL
+);
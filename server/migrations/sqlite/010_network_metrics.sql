@@ -0,0 +1,14 @@
+-- Network metrics table: Per-interface rates (per second) of each sample
+CREATE TABLE IF NOT EXISTS network_metrics (
+    metric_id INTEGER NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    interface TEXT NOT NULL,
+    rx_bytes REAL NOT NULL,
+    tx_bytes REAL NOT NULL,
+    rx_packets REAL NOT NULL,
+    tx_packets REAL NOT NULL,
+    rx_errors REAL NOT NULL,
+    tx_errors REAL NOT NULL,
+    rx_drops REAL NOT NULL,
+    tx_drops REAL NOT NULL,
+    PRIMARY KEY (metric_id, interface)
+);
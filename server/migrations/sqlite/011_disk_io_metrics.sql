@@ -0,0 +1,12 @@
+-- Disk I/O metrics table: Per-block-device throughput, IOPS and latency of each sample
+CREATE TABLE IF NOT EXISTS disk_io_metrics (
+    metric_id INTEGER NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    device TEXT NOT NULL,
+    read_bytes REAL NOT NULL,
+    write_bytes REAL NOT NULL,
+    read_iops REAL NOT NULL,
+    write_iops REAL NOT NULL,
+    await_ms REAL NOT NULL,
+    util REAL NOT NULL,
+    PRIMARY KEY (metric_id, device)
+);
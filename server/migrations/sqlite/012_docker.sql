@@ -0,0 +1,21 @@
+-- Docker metrics table: Per-sample container counts and disk usage as JSON
+CREATE TABLE IF NOT EXISTS docker_metrics (
+    metric_id INTEGER PRIMARY KEY NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    detail TEXT NOT NULL
+);
+
+-- Containers table: The latest container list of each client, replaced on every poll
+CREATE TABLE IF NOT EXISTS containers (
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    container_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    image TEXT NOT NULL,
+    state TEXT NOT NULL,
+    health TEXT,
+    restart_count INTEGER NOT NULL,
+    cpu_percent REAL,
+    memory_used INTEGER,
+    memory_limit INTEGER,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (client_id, container_id)
+);
@@ -0,0 +1,5 @@
+-- systemd metrics table: Per-sample failed units and watched unit states as JSON
+CREATE TABLE IF NOT EXISTS systemd_metrics (
+    metric_id INTEGER PRIMARY KEY NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    detail TEXT NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Process snapshots table: Recent top-N process lists of each client as JSON
+CREATE TABLE IF NOT EXISTS process_snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    client_id TEXT NOT NULL REFERENCES clients(id) ON DELETE CASCADE,
+    timestamp TEXT NOT NULL,
+    processes TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_process_snapshots_client_time ON process_snapshots(client_id, timestamp);
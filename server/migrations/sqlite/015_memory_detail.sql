@@ -0,0 +1,5 @@
+-- memory metrics table: Per-sample swap, available memory, pressure stall and OOM-kill figures as JSON
+CREATE TABLE IF NOT EXISTS memory_metrics (
+    metric_id INTEGER PRIMARY KEY NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    detail TEXT NOT NULL
+);
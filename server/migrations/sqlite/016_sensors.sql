@@ -0,0 +1,5 @@
+-- sensor metrics table: Per-sample hwmon and thermal zone temperatures and fan speeds as JSON
+CREATE TABLE IF NOT EXISTS sensor_metrics (
+    metric_id INTEGER PRIMARY KEY NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    detail TEXT NOT NULL
+);
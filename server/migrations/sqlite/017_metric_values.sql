@@ -0,0 +1,9 @@
+-- metric values table: Named series reported alongside the built-in fields,
+-- labels as a JSON object
+CREATE TABLE IF NOT EXISTS metric_values (
+    metric_id INTEGER NOT NULL REFERENCES metrics(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    labels TEXT NOT NULL DEFAULT '{}',
+    value REAL NOT NULL,
+    PRIMARY KEY (metric_id, name, labels)
+);
@@ -0,0 +1,9 @@
+-- Metric rollups: Median, p90, p99 and standard deviation per bucket, so
+-- stats can be computed from rollups instead of raw samples. Buckets rolled
+-- up before this migration only had p95, so their values are estimates.
+ALTER TABLE metric_rollups ADD COLUMN p50 REAL NOT NULL DEFAULT 0;
+ALTER TABLE metric_rollups ADD COLUMN p90 REAL NOT NULL DEFAULT 0;
+ALTER TABLE metric_rollups ADD COLUMN p99 REAL NOT NULL DEFAULT 0;
+ALTER TABLE metric_rollups ADD COLUMN stddev REAL NOT NULL DEFAULT 0;
+
+UPDATE metric_rollups SET p50 = avg, p90 = p95, p99 = max;
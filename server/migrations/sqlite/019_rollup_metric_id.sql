@@ -0,0 +1,7 @@
+-- Rollup progress: Newest sample id each tier has seen, so samples that
+-- arrive after their bucket was rolled up (an agent flushing its buffer
+-- after an outage) get their buckets re-aggregated. Existing samples count
+-- as seen.
+ALTER TABLE rollup_progress ADD COLUMN metric_id INTEGER NOT NULL DEFAULT 0;
+
+UPDATE rollup_progress SET metric_id = (SELECT COALESCE(MAX(id), 0) FROM metrics);
@@ -0,0 +1,16 @@
+-- Built-in series: The headline fields are stored in metric_values as
+-- unlabelled series named after their metric types, like any other series
+INSERT OR IGNORE INTO metric_values (metric_id, name, labels, value)
+SELECT id, 'cpu', '{}', cpu_usage FROM metrics
+UNION ALL SELECT id, 'ram', '{}', ram_usage FROM metrics
+UNION ALL SELECT id, 'disk', '{}', disk_usage FROM metrics
+UNION ALL SELECT id, 'inode', '{}', inode_usage FROM metrics
+UNION ALL SELECT id, 'gpu', '{}', gpu_usage FROM metrics WHERE gpu_usage IS NOT NULL
+UNION ALL SELECT id, 'docker', '{}', docker_sz FROM metrics WHERE docker_sz IS NOT NULL;
+
+ALTER TABLE metrics DROP COLUMN cpu_usage;
+ALTER TABLE metrics DROP COLUMN ram_usage;
+ALTER TABLE metrics DROP COLUMN disk_usage;
+ALTER TABLE metrics DROP COLUMN inode_usage;
+ALTER TABLE metrics DROP COLUMN docker_sz;
+ALTER TABLE metrics DROP COLUMN gpu_usage;
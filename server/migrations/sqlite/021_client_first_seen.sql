@@ -0,0 +1,9 @@
+-- Clients: When each client first reported, so ones that were registered
+-- but never reported show as pending rather than offline. Clients with an
+-- agent version or any samples have reported; their first report is long
+-- gone, so their last one stands in for it.
+ALTER TABLE clients ADD COLUMN first_seen TEXT;
+
+UPDATE clients SET first_seen = last_seen
+WHERE version IS NOT NULL
+    OR EXISTS (SELECT 1 FROM metrics WHERE metrics.client_id = clients.id);
@@ -0,0 +1,396 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    models::{AlertRule, Client, ClientStatus},
+    DbPool,
+};
+
+/// Metric type of alert rules and incidents for hosts that stopped reporting
+pub const OFFLINE_METRIC: &str = "offline";
+
+/// Silence window used when the `offline_after_sec` setting is not set
+const DEFAULT_OFFLINE_AFTER_SEC: i64 = 60;
+
+/// Samples further apart than this break a pending breach: the agent was
+/// silent in between, so we cannot claim the metric stayed over threshold.
+const MAX_SAMPLE_GAP_SECS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertState {
+    Ok,
+    Pending { since: DateTime<Utc>, peak: f64 },
+    Firing { since: DateTime<Utc>, peak: f64 },
+    Resolved { at: DateTime<Utc> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertTransition {
+    Fired { since: DateTime<Utc> },
+    Resolved { since: DateTime<Utc>, peak: f64 },
+}
+
+/// Event sent from ingestion to the alert worker
+#[derive(Debug, Clone)]
+pub enum AlertEvent {
+    Fired {
+        client_id: String,
+        rule: AlertRule,
+        value: f64,
+        started_at: DateTime<Utc>,
+    },
+    Resolved {
+        client_id: String,
+        rule: AlertRule,
+        value: f64,
+        peak: f64,
+        started_at: DateTime<Utc>,
+        ended_at: DateTime<Utc>,
+    },
+    /// Client has been silent for longer than its offline window
+    Offline {
+        client_id: String,
+        rule_id: Option<i64>,
+        window_sec: i64,
+        last_seen: DateTime<Utc>,
+    },
+    /// Previously silent client is reporting again
+    Online {
+        client_id: String,
+        rule_id: Option<i64>,
+        offline_since: DateTime<Utc>,
+        back_at: DateTime<Utc>,
+    },
+}
+
+impl AlertEvent {
+    pub fn client_id(&self) -> &str {
+        match self {
+            AlertEvent::Fired { client_id, .. }
+            | AlertEvent::Resolved { client_id, .. }
+            | AlertEvent::Offline { client_id, .. }
+            | AlertEvent::Online { client_id, .. } => client_id,
+        }
+    }
+
+    /// Rule whose notification channels this event is routed to
+    pub fn rule_id(&self) -> Option<i64> {
+        match self {
+            AlertEvent::Fired { rule, .. } | AlertEvent::Resolved { rule, .. } => Some(rule.id),
+            AlertEvent::Offline { rule_id, .. } | AlertEvent::Online { rule_id, .. } => *rule_id,
+        }
+    }
+
+    pub fn metric_type(&self) -> &str {
+        match self {
+            AlertEvent::Fired { rule, .. } | AlertEvent::Resolved { rule, .. } => &rule.metric_type,
+            AlertEvent::Offline { .. } | AlertEvent::Online { .. } => OFFLINE_METRIC,
+        }
+    }
+}
+
+/// How long each client may stay silent before it is considered offline.
+/// The `offline_after_sec` setting is the default; an `offline` alert rule
+/// overrides it (threshold in seconds), client-specific rules winning over
+/// global ones.
+pub struct OfflinePolicy {
+    default_window_sec: i64,
+    rules: Vec<AlertRule>,
+}
+
+impl OfflinePolicy {
+    pub async fn load(pool: &DbPool) -> Result<Self> {
+        let default_window_sec = pool
+            .get_setting("offline_after_sec")
+            .await?
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_OFFLINE_AFTER_SEC);
+
+        let rules = pool
+            .get_alert_rules()
+            .await?
+            .into_iter()
+            .filter(|r| r.metric_type == OFFLINE_METRIC)
+            .collect();
+
+        Ok(Self {
+            default_window_sec,
+            rules,
+        })
+    }
+
+    pub fn rule_for(&self, client_id: &str) -> Option<&AlertRule> {
+        self.rules
+            .iter()
+            .find(|r| r.client_id.as_deref() == Some(client_id))
+            .or_else(|| self.rules.iter().find(|r| r.client_id.is_none()))
+    }
+
+    pub fn window_for(&self, client_id: &str) -> i64 {
+        self.rule_for(client_id)
+            .map(|r| r.threshold as i64)
+            .unwrap_or(self.default_window_sec)
+    }
+
+    /// When the client went silent, if it is currently offline. Clients that
+    /// never reported are still being set up, so they are pending instead.
+    pub fn offline_since(&self, client: &Client, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        client.first_seen.as_ref()?;
+        let last_seen = parse_timestamp(&client.last_seen)?;
+        if now - last_seen > Duration::seconds(self.window_for(&client.id)) {
+            Some(last_seen)
+        } else {
+            None
+        }
+    }
+
+    /// Pending until the first report, then online or offline by the window;
+    /// the dashboard, the offline monitor and /metrics all go by this
+    pub fn status(&self, client: &Client, now: DateTime<Utc>) -> ClientStatus {
+        if client.first_seen.is_none() {
+            ClientStatus::Pending
+        } else if self.offline_since(client, now).is_some() {
+            ClientStatus::Offline
+        } else {
+            ClientStatus::Online
+        }
+    }
+}
+
+/// Per-(client, rule) state machine: ok → pending → firing → resolved
+#[derive(Debug, Clone)]
+pub struct AlertTracker {
+    pub state: AlertState,
+    last_sample: Option<DateTime<Utc>>,
+}
+
+impl Default for AlertTracker {
+    fn default() -> Self {
+        Self {
+            state: AlertState::Ok,
+            last_sample: None,
+        }
+    }
+}
+
+impl AlertTracker {
+    /// Feed one sample into the state machine, returning a transition when
+    /// the alert starts firing or resolves.
+    pub fn observe(
+        &mut self,
+        rule: &AlertRule,
+        value: f64,
+        at: DateTime<Utc>,
+    ) -> Option<AlertTransition> {
+        // Ignore samples we've already seen (history replay, retried batches)
+        if let Some(last) = self.last_sample {
+            if at <= last {
+                return None;
+            }
+            if at - last > Duration::seconds(MAX_SAMPLE_GAP_SECS) {
+                if let AlertState::Pending { .. } = self.state {
+                    self.state = AlertState::Ok;
+                }
+            }
+        }
+        self.last_sample = Some(at);
+
+        let breached = value > rule.threshold;
+        let required = Duration::seconds(rule.duration_sec.max(0));
+
+        match self.state {
+            AlertState::Ok | AlertState::Resolved { .. } if breached => {
+                if required.is_zero() {
+                    self.state = AlertState::Firing {
+                        since: at,
+                        peak: value,
+                    };
+                    return Some(AlertTransition::Fired { since: at });
+                }
+                self.state = AlertState::Pending {
+                    since: at,
+                    peak: value,
+                };
+                None
+            }
+            AlertState::Pending { since, peak } if breached => {
+                let peak = peak.max(value);
+                if at - since >= required {
+                    self.state = AlertState::Firing { since, peak };
+                    return Some(AlertTransition::Fired { since });
+                }
+                self.state = AlertState::Pending { since, peak };
+                None
+            }
+            AlertState::Pending { .. } => {
+                self.state = AlertState::Ok;
+                None
+            }
+            AlertState::Firing { since, peak } if breached => {
+                self.state = AlertState::Firing {
+                    since,
+                    peak: peak.max(value),
+                };
+                None
+            }
+            AlertState::Firing { since, peak } => {
+                self.state = AlertState::Resolved { at };
+                Some(AlertTransition::Resolved { since, peak })
+            }
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_timestamp(timestamp: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AlertRuleInput;
+
+    fn rule(threshold: f64, duration_sec: i64) -> AlertRule {
+        AlertRule {
+            id: 1,
+            client_id: None,
+            metric_type: "cpu".to_string(),
+            threshold,
+            duration_sec,
+            target: None,
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    #[test]
+    fn test_short_spike_does_not_fire() {
+        let rule = rule(90.0, 30);
+        let mut tracker = AlertTracker::default();
+
+        for s in 0..5 {
+            assert_eq!(tracker.observe(&rule, 99.0, at(s)), None);
+        }
+        assert_eq!(tracker.observe(&rule, 10.0, at(5)), None);
+        assert_eq!(tracker.state, AlertState::Ok);
+    }
+
+    #[test]
+    fn test_sustained_breach_fires_once_then_resolves() {
+        let rule = rule(90.0, 30);
+        let mut tracker = AlertTracker::default();
+
+        let fired: Vec<_> = (0..=40)
+            .filter_map(|s| tracker.observe(&rule, 95.0 + (s % 3) as f64, at(s)))
+            .collect();
+        assert_eq!(fired, vec![AlertTransition::Fired { since: at(0) }]);
+
+        assert_eq!(
+            tracker.observe(&rule, 50.0, at(41)),
+            Some(AlertTransition::Resolved {
+                since: at(0),
+                peak: 97.0
+            })
+        );
+        assert_eq!(tracker.state, AlertState::Resolved { at: at(41) });
+    }
+
+    #[test]
+    fn test_zero_duration_fires_immediately() {
+        let rule = rule(90.0, 0);
+        let mut tracker = AlertTracker::default();
+
+        assert_eq!(
+            tracker.observe(&rule, 95.0, at(0)),
+            Some(AlertTransition::Fired { since: at(0) })
+        );
+    }
+
+    #[test]
+    fn test_gap_resets_pending_and_replays_are_ignored() {
+        let rule = rule(90.0, 30);
+        let mut tracker = AlertTracker::default();
+
+        tracker.observe(&rule, 95.0, at(0));
+        assert_eq!(tracker.observe(&rule, 95.0, at(0)), None);
+        tracker.observe(&rule, 95.0, at(200));
+        assert_eq!(
+            tracker.state,
+            AlertState::Pending {
+                since: at(200),
+                peak: 95.0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_offline_window_precedence() {
+        let db = crate::db::memory().await;
+        let web = db.create_client("web-1").await.unwrap();
+        let other = db.create_client("db-1").await.unwrap();
+        let offline_rule = |client_id: Option<&str>, threshold: f64| AlertRuleInput {
+            client_id: client_id.map(str::to_string),
+            metric_type: OFFLINE_METRIC.to_string(),
+            threshold,
+            duration_sec: None,
+            channel_ids: None,
+            target: None,
+        };
+
+        let policy = OfflinePolicy::load(&db).await.unwrap();
+        assert_eq!(policy.window_for(&web.id), 60);
+
+        db.set_setting("offline_after_sec", "120").await.unwrap();
+        let policy = OfflinePolicy::load(&db).await.unwrap();
+        assert_eq!(policy.window_for(&web.id), 120);
+        assert!(policy.rule_for(&web.id).is_none());
+
+        // A global rule beats the setting, and a client's own rule beats both
+        db.create_alert_rule(&offline_rule(None, 300.0))
+            .await
+            .unwrap();
+        db.create_alert_rule(&offline_rule(Some(&web.id), 30.0))
+            .await
+            .unwrap();
+        let policy = OfflinePolicy::load(&db).await.unwrap();
+        assert_eq!(policy.window_for(&web.id), 30);
+        assert_eq!(policy.window_for(&other.id), 300);
+
+        db.update_client_last_seen(&other.id, Some("1.0"))
+            .await
+            .unwrap();
+        let other = db.get_client_by_id(&other.id).await.unwrap().unwrap();
+        let last_seen = parse_timestamp(&other.last_seen).unwrap();
+        assert_eq!(
+            policy.offline_since(&other, last_seen + Duration::seconds(300)),
+            None
+        );
+        assert_eq!(
+            policy.offline_since(&other, last_seen + Duration::seconds(301)),
+            Some(last_seen)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_never_reported_client_is_pending() {
+        let db = crate::db::memory().await;
+        let client = db.create_client("provisioning").await.unwrap();
+        let policy = OfflinePolicy::load(&db).await.unwrap();
+        let later = Utc::now() + Duration::days(1);
+
+        assert_eq!(policy.status(&client, later), ClientStatus::Pending);
+        assert_eq!(policy.offline_since(&client, later), None);
+
+        db.update_client_last_seen(&client.id, None).await.unwrap();
+        let client = db.get_client_by_id(&client.id).await.unwrap().unwrap();
+        let last_seen = parse_timestamp(&client.last_seen).unwrap();
+        assert_eq!(client.first_seen, Some(client.last_seen.clone()));
+        assert_eq!(policy.status(&client, last_seen), ClientStatus::Online);
+        assert_eq!(policy.status(&client, later), ClientStatus::Offline);
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::DbPool;
+
+/// Name of the key managed through the `ADMIN_API_KEY` environment variable
+const ENV_KEY_NAME: &str = "env:ADMIN_API_KEY";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Admin,
+    Readonly,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Readonly => "readonly",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "admin" => Some(Role::Admin),
+            "readonly" => Some(Role::Readonly),
+            _ => None,
+        }
+    }
+}
+
+/// Identity of the caller, attached to the request by the auth middleware
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthContext {
+    pub key_id: i64,
+    pub name: String,
+    pub role: Role,
+}
+
+pub fn generate_key() -> String {
+    format!("smk_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// Whether a request needs the admin role: anything that modifies state,
+/// plus endpoints whose responses contain credentials.
+pub fn requires_admin(method: &axum::http::Method, path: &str) -> bool {
+    const SENSITIVE: &[&str] = &["/api/keys", "/api/settings", "/api/channels", "/api/admin"];
+
+    let safe = matches!(
+        *method,
+        axum::http::Method::GET | axum::http::Method::HEAD | axum::http::Method::OPTIONS
+    );
+    !safe || SENSITIVE.iter().any(|prefix| path.starts_with(prefix))
+}
+
+/// Make sure an admin credential exists. `ADMIN_API_KEY` is (re)registered on
+/// every boot; otherwise a key is generated once and logged for a fresh install.
+pub async fn bootstrap_admin_key(pool: &DbPool) -> Result<()> {
+    if let Ok(key) = std::env::var("ADMIN_API_KEY") {
+        if !key.is_empty() {
+            pool.upsert_api_key(ENV_KEY_NAME, &hash_key(&key), Role::Admin)
+                .await?;
+            return Ok(());
+        }
+    }
+
+    if pool.count_api_keys().await? == 0 {
+        let key = generate_key();
+        pool.upsert_api_key("bootstrap", &hash_key(&key), Role::Admin)
+            .await?;
+        warn!("No API keys configured, generated admin key: {}", key);
+        warn!("Store it now, it will not be shown again");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Method;
+
+    #[test]
+    fn test_requires_admin() {
+        assert!(!requires_admin(&Method::GET, "/api/clients"));
+        assert!(!requires_admin(&Method::GET, "/api/metrics/abc"));
+        assert!(requires_admin(&Method::DELETE, "/api/clients/abc"));
+        assert!(requires_admin(&Method::POST, "/api/alerts"));
+        assert!(requires_admin(&Method::GET, "/api/settings"));
+        assert!(requires_admin(&Method::GET, "/api/keys"));
+        assert!(requires_admin(&Method::GET, "/api/admin/storage"));
+    }
+
+    #[test]
+    fn test_hash_key_is_stable() {
+        let key = generate_key();
+        assert!(key.starts_with("smk_"));
+        assert_eq!(hash_key(&key), hash_key(&key));
+        assert_ne!(hash_key(&key), hash_key("other"));
+    }
+}
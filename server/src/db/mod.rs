@@ -0,0 +1,1131 @@
+//! Storage backends. Everything the server persists goes through the
+//! [`Storage`] trait; `DATABASE_URL` picks SQLite (`sqlite:...`, the default)
+//! or PostgreSQL (`postgres://...`), which also makes use of TimescaleDB
+//! when the extension is installed.
+
+mod postgres;
+mod sqlite;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use std::{collections::BTreeMap, sync::Arc};
+
+use crate::alerts;
+use crate::auth::Role;
+use crate::migrations::{self, Migration};
+use crate::models::{
+    AlertRule, AlertRuleInput, ApiKey, Client, ClientStorage, Container, DiskIoSample,
+    FilesystemSample, GpuSample, Incident, IncidentQuery, Metric, MetricInput, MetricRollup,
+    NetworkSample, NotificationChannel, NotificationChannelInput, ProcessSnapshot, Setting, Stats,
+    METRIC_TYPES,
+};
+use crate::rollups::{self, bucket_start, Aggregate, AggregateSums, RollupSums, Tier};
+
+pub use postgres::PostgresStorage;
+pub use sqlite::SqliteStorage;
+
+pub type DbPool = Arc<dyn Storage>;
+
+/// Snapshots kept per client; a few minutes' worth at the agent's cadence
+const PROCESS_SNAPSHOTS_KEPT: i64 = 30;
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    // Schema operations
+
+    /// Migrations of this backend, oldest first
+    fn migrations(&self) -> &'static [Migration];
+
+    /// Highest applied migration; 0 for a new (or pre-versioning) database
+    async fn schema_version(&self) -> Result<i64>;
+
+    /// Apply one migration and record it, in a single transaction
+    async fn apply_migration(&self, migration: &Migration) -> Result<()>;
+
+    // Client operations
+    async fn get_client_by_token(&self, token: &str) -> Result<Option<Client>>;
+
+    async fn get_client_by_id(&self, id: &str) -> Result<Option<Client>>;
+
+    async fn get_all_clients(&self) -> Result<Vec<Client>>;
+
+    async fn create_client(&self, hostname: &str) -> Result<Client>;
+
+    async fn update_client_last_seen(&self, client_id: &str, version: Option<&str>) -> Result<()>;
+
+    async fn delete_client(&self, id: &str) -> Result<bool>;
+
+    // Metric operations
+    /// Store a batch of samples in one transaction, along with the latest
+    /// container list and process snapshots they carry
+    async fn insert_metrics(&self, client_id: &str, metrics: &[MetricInput])
+        -> Result<Vec<Metric>>;
+
+    async fn get_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Metric>>;
+
+    async fn get_metrics_since(&self, client_id: &str, since: &str) -> Result<Vec<Metric>>;
+
+    async fn get_metrics_between(
+        &self,
+        client_id: &str,
+        start: &str,
+        end: &str,
+    ) -> Result<Vec<Metric>>;
+
+    async fn get_oldest_metric_timestamp(&self) -> Result<Option<String>>;
+
+    async fn get_latest_metrics(&self, client_id: &str, count: i64) -> Result<Vec<Metric>>;
+
+    /// Most recent sample of every client that has reported
+    async fn get_latest_metric_per_client(&self) -> Result<Vec<Metric>>;
+
+    /// Per-mount filesystem readings of a client, oldest first
+    async fn get_filesystem_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        mount: Option<&str>,
+    ) -> Result<Vec<FilesystemSample>>;
+
+    /// Per-interface network rates of a client, oldest first
+    async fn get_network_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        interface: Option<&str>,
+    ) -> Result<Vec<NetworkSample>>;
+
+    /// Per-device disk I/O of a client, oldest first
+    async fn get_disk_io_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        device: Option<&str>,
+    ) -> Result<Vec<DiskIoSample>>;
+
+    /// Per-device GPU readings of a client, oldest first
+    async fn get_gpu_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        gpu: Option<i64>,
+    ) -> Result<Vec<GpuSample>>;
+
+    /// Stats per metric type over the last `hours`, and per bucket of the
+    /// `bucket` tier when given. Computed from rollups, plus the raw samples
+    /// newer than the last rolled up bucket.
+    async fn get_stats(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        metric_type: Option<&str>,
+        bucket: Option<Tier>,
+    ) -> Result<Vec<Stats>> {
+        let since = Utc::now() - Duration::hours(hours.unwrap_or(24));
+        let tier = rollups::stats_tier(hours.unwrap_or(24), bucket);
+        let bucket_sec = bucket.map(|b| b.resolution_sec);
+        let group = |at: DateTime<Utc>| bucket_sec.map_or(since, |sec| bucket_start(at, sec));
+
+        let mut totals: BTreeMap<(DateTime<Utc>, String), AggregateSums> = BTreeMap::new();
+        let mut add = |at: DateTime<Utc>, metric_type: String, sums: AggregateSums| {
+            totals
+                .entry((group(at), metric_type))
+                .and_modify(|total| *total = total.merge(sums))
+                .or_insert(sums);
+        };
+
+        let rolled_up = self
+            .sum_rollups(
+                client_id,
+                tier.resolution_sec,
+                &since.to_rfc3339(),
+                metric_type,
+                bucket_sec,
+            )
+            .await?;
+        for row in rolled_up {
+            if let Some(at) = alerts::parse_timestamp(&row.bucket) {
+                add(at, row.metric_type, row.sums);
+            }
+        }
+
+        let tail_start = self
+            .get_rollup_progress(tier.resolution_sec)
+            .await?
+            .and_then(|done| alerts::parse_timestamp(&done))
+            .map_or(since, |done| done.max(since));
+        let tail = self
+            .get_metrics_since(client_id, &tail_start.to_rfc3339())
+            .await?;
+        let tail_buckets =
+            rollups::aggregate_buckets(&tail, bucket_sec.unwrap_or(tier.resolution_sec));
+        for (at, aggregates) in tail_buckets {
+            for (name, agg) in aggregates {
+                if metric_type.is_none_or(|t| t == name) {
+                    add(at, name, agg.into());
+                }
+            }
+        }
+
+        // Built-in metric types in their usual order, then reported series
+        let rank = |name: &str| {
+            METRIC_TYPES
+                .iter()
+                .position(|t| *t == name)
+                .unwrap_or(METRIC_TYPES.len())
+        };
+        let mut stats: Vec<Stats> = totals
+            .into_iter()
+            .map(|((at, metric_type), sums)| {
+                let agg = sums.aggregate();
+                Stats {
+                    client_id: client_id.to_string(),
+                    metric_type,
+                    bucket: bucket.map(|_| at.to_rfc3339()),
+                    min: agg.min,
+                    max: agg.max,
+                    avg: agg.avg,
+                    stddev: agg.stddev,
+                    p50: agg.p50,
+                    p90: agg.p90,
+                    p95: agg.p95,
+                    p99: agg.p99,
+                    count: agg.count,
+                }
+            })
+            .collect();
+        stats.sort_by(|a, b| {
+            (&a.bucket, rank(&a.metric_type), &a.metric_type).cmp(&(
+                &b.bucket,
+                rank(&b.metric_type),
+                &b.metric_type,
+            ))
+        });
+
+        Ok(stats)
+    }
+
+    /// Delete at most `chunk` of a client's samples older than `cutoff`, so
+    /// cleanup never holds the write lock for long
+    async fn delete_metrics_before(&self, client_id: &str, cutoff: &str, chunk: i64)
+        -> Result<u64>;
+
+    // Container operations
+
+    async fn get_containers(&self, client_id: &str) -> Result<Vec<Container>>;
+
+    async fn get_all_containers(&self) -> Result<Vec<Container>>;
+
+    // Process snapshot operations
+
+    /// A client's most recent process snapshots, newest first
+    async fn get_process_snapshots(
+        &self,
+        client_id: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<ProcessSnapshot>>;
+
+    // Rollup operations
+    async fn upsert_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        buckets: &BTreeMap<DateTime<Utc>, Vec<(String, Aggregate)>>,
+    ) -> Result<usize>;
+
+    async fn get_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        hours: i64,
+    ) -> Result<Vec<MetricRollup>>;
+
+    /// A client's rollups of `resolution` with buckets in `[from, to)`
+    async fn get_rollups_between(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<MetricRollup>>;
+
+    /// Sum a client's rollups of `resolution` since `since` per metric type,
+    /// and per `bucket_sec` bucket when given, in the database
+    async fn sum_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        since: &str,
+        metric_type: Option<&str>,
+        bucket_sec: Option<i64>,
+    ) -> Result<Vec<RollupSums>>;
+
+    async fn get_rollup_progress(&self, resolution: i64) -> Result<Option<String>>;
+
+    /// Newest sample id a tier had seen when it last ran, to tell samples
+    /// inserted since from ones already rolled up
+    async fn get_rollup_metric_id(&self, resolution: i64) -> Result<i64>;
+
+    async fn set_rollup_progress(
+        &self,
+        resolution: i64,
+        completed_until: &str,
+        metric_id: i64,
+    ) -> Result<()>;
+
+    async fn get_max_metric_id(&self) -> Result<i64>;
+
+    /// (client_id, timestamp) of the samples with ids in `(after_id, up_to_id]`
+    /// stamped before `before`: late arrivals whose bucket was already rolled up
+    async fn get_late_metrics(
+        &self,
+        after_id: i64,
+        up_to_id: i64,
+        before: &str,
+    ) -> Result<Vec<(String, String)>>;
+
+    async fn delete_rollups_before(&self, resolution: i64, cutoff: &str, chunk: i64)
+        -> Result<u64>;
+
+    // Alert rule operations
+    async fn get_alert_rules(&self) -> Result<Vec<AlertRule>>;
+
+    async fn get_alert_rule_by_id(&self, id: i64) -> Result<Option<AlertRule>>;
+
+    async fn get_alert_rules_for_client(&self, client_id: &str) -> Result<Vec<AlertRule>>;
+
+    async fn create_alert_rule(&self, rule: &AlertRuleInput) -> Result<AlertRule>;
+
+    async fn delete_alert_rule(&self, id: i64) -> Result<bool>;
+
+    // Notification channel operations
+    async fn get_notification_channels(&self) -> Result<Vec<NotificationChannel>>;
+
+    async fn get_notification_channel(&self, id: i64) -> Result<Option<NotificationChannel>>;
+
+    async fn create_notification_channel(
+        &self,
+        input: &NotificationChannelInput,
+    ) -> Result<NotificationChannel>;
+
+    async fn delete_notification_channel(&self, id: i64) -> Result<bool>;
+
+    /// Enabled channels attached to an alert rule
+    async fn get_channels_for_rule(&self, rule_id: i64) -> Result<Vec<NotificationChannel>>;
+
+    async fn get_rule_channel_ids(&self, rule_id: i64) -> Result<Vec<i64>>;
+
+    /// Replace the set of channels an alert rule notifies
+    async fn set_rule_channels(&self, rule_id: i64, channel_ids: &[i64]) -> Result<()>;
+
+    // API key operations
+    async fn get_api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>>;
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>>;
+
+    async fn count_api_keys(&self) -> Result<i64>;
+
+    async fn create_api_key(&self, name: &str, key_hash: &str, role: Role) -> Result<ApiKey>;
+
+    /// Create or replace the key registered under `name`
+    async fn upsert_api_key(&self, name: &str, key_hash: &str, role: Role) -> Result<()>;
+
+    async fn delete_api_key(&self, id: i64) -> Result<bool>;
+
+    // Incident operations
+
+    /// Open an incident for a firing rule (or a silent host when `rule_id` is
+    /// `None`). Returns `None` if one is already open for the same client and
+    /// rule, e.g. re-fired after a server restart.
+    async fn open_incident(
+        &self,
+        client_id: &str,
+        rule_id: Option<i64>,
+        metric_type: &str,
+        threshold: f64,
+        started_at: &str,
+        value: f64,
+    ) -> Result<Option<i64>>;
+
+    async fn resolve_incident(
+        &self,
+        client_id: &str,
+        rule_id: Option<i64>,
+        metric_type: &str,
+        ended_at: &str,
+        peak_value: f64,
+    ) -> Result<bool>;
+
+    async fn get_open_incidents(&self, metric_type: &str) -> Result<Vec<Incident>>;
+
+    /// Incidents overlapping the requested time range, newest first
+    async fn get_incidents(&self, query: &IncidentQuery) -> Result<Vec<Incident>>;
+
+    // Settings operations
+    async fn get_setting(&self, key: &str) -> Result<Option<String>>;
+
+    async fn get_all_settings(&self) -> Result<Vec<Setting>>;
+
+    async fn set_setting(&self, key: &str, value: &str) -> Result<()>;
+
+    async fn get_client_settings(&self, client_id: &str) -> Result<Vec<Setting>>;
+
+    /// Every client's override of one setting, as (client_id, value)
+    async fn get_client_overrides(&self, key: &str) -> Result<Vec<(String, String)>>;
+
+    async fn set_client_setting(&self, client_id: &str, key: &str, value: &str) -> Result<()>;
+
+    async fn delete_client_setting(&self, client_id: &str, key: &str) -> Result<bool>;
+
+    // Storage operations
+
+    /// Size of the database and how much of it is free for reuse, in bytes
+    async fn get_database_size(&self) -> Result<(i64, i64)>;
+
+    async fn get_client_storage(&self) -> Result<Vec<ClientStorage>>;
+
+    async fn vacuum(&self) -> Result<()>;
+}
+
+/// Open the database named by `database_url` without migrating it
+pub async fn connect(database_url: &str) -> Result<DbPool> {
+    if database_url.starts_with("sqlite:") {
+        Ok(Arc::new(SqliteStorage::connect(database_url).await?))
+    } else if database_url.starts_with("postgres:") || database_url.starts_with("postgresql:") {
+        Ok(Arc::new(PostgresStorage::connect(database_url).await?))
+    } else {
+        bail!("unsupported DATABASE_URL; expected sqlite:... or postgres://...")
+    }
+}
+
+/// Open the database and bring its schema up to date
+pub async fn init_db(database_url: &str) -> Result<DbPool> {
+    let pool = connect(database_url).await?;
+
+    // Run migrations
+    migrations::run(&pool).await?;
+
+    Ok(pool)
+}
+
+/// `database_url` with its password masked, for logging
+pub fn redact_url(database_url: &str) -> String {
+    let Some((scheme, rest)) = database_url.split_once("://") else {
+        return database_url.to_string();
+    };
+    let authority = rest.split('/').next().unwrap_or(rest);
+    match authority
+        .rsplit_once('@')
+        .and_then(|(userinfo, _)| userinfo.split_once(':'))
+    {
+        Some((user, password)) => format!(
+            "{}://{}:***{}",
+            scheme,
+            user,
+            &rest[user.len() + 1 + password.len()..]
+        ),
+        None => database_url.to_string(),
+    }
+}
+
+/// A migrated in-memory SQLite storage, for tests outside this module
+#[cfg(test)]
+pub async fn memory() -> DbPool {
+    // One connection, since every in-memory connection is its own database
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    let db: DbPool = Arc::new(SqliteStorage::new(pool));
+    migrations::run(&db).await.unwrap();
+    db
+}
+
+/// One suite for every backend. SQLite runs in memory; PostgreSQL runs when
+/// `TEST_POSTGRES_URL` points at a database the tests may create schemas in.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sqlx::{
+        postgres::{PgConnectOptions, PgPoolOptions},
+        Executor,
+    };
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    /// A migrated storage in `schema`, so runs don't see each other
+    async fn postgres(url: &str, schema: &str) -> DbPool {
+        let options = PgConnectOptions::from_str(url)
+            .unwrap()
+            .options([("search_path", schema)]);
+        let pool = PgPoolOptions::new().connect_with(options).await.unwrap();
+        let db: DbPool = Arc::new(PostgresStorage::new(pool));
+        migrations::run(&db).await.unwrap();
+        db
+    }
+
+    fn sample(secs: i64, cpu: f64) -> MetricInput {
+        serde_json::from_value(json!({
+            "cpu_usage": cpu,
+            "ram_usage": 50.0,
+            "disk_usage": 10.0,
+            "inode_usage": 1.0,
+            "docker_sz": 2048,
+            "gpu_usage": 80.0,
+            "cpu": {
+                "user": cpu, "system": 0.0, "iowait": 2.5, "steal": 0.0,
+                "load1": 1.0, "load5": 0.5, "load15": 0.25, "cores": [cpu, cpu]
+            },
+            "gpus": [{
+                "index": 0, "name": "A100", "uuid": null, "utilization": 80.0,
+                "memory_used": 1024, "memory_total": 4096, "temperature": null,
+                "power_watts": null, "fan_speed": null, "processes": 2
+            }],
+            "filesystems": [
+                {
+                    "mount_point": "/", "device": "sda1", "fs_type": "ext4",
+                    "total_bytes": 100, "used_bytes": 10, "disk_usage": 10.0, "inode_usage": 1.0
+                },
+                {
+                    "mount_point": "/data", "device": "sdb1", "fs_type": "xfs",
+                    "total_bytes": 200, "used_bytes": 150, "disk_usage": 75.0, "inode_usage": null
+                }
+            ],
+            "network": [{
+                "interface": "eth0", "rx_bytes": 1000.0, "tx_bytes": 500.0,
+                "rx_packets": 10.0, "tx_packets": 5.0, "rx_errors": 0.0,
+                "tx_errors": 0.0, "rx_drops": 0.0, "tx_drops": 0.0
+            }],
+            "disk_io": [{
+                "device": "sda", "read_bytes": 4096.0, "write_bytes": 8192.0,
+                "read_iops": 1.0, "write_iops": 2.0, "await_ms": 0.5, "util": 3.0
+            }],
+            "values": [{ "name": "queue_depth", "labels": { "queue": "mail" }, "value": 12.0 }],
+            "containers": [{
+                "id": "abc", "name": "web", "image": "nginx", "state": "running",
+                "health": null, "restart_count": 0, "cpu_percent": 1.5,
+                "memory_used": null, "memory_limit": null
+            }],
+            "processes": [],
+            "timestamp": DateTime::from_timestamp(1_700_000_000 + secs, 0).unwrap().to_rfc3339(),
+        }))
+        .unwrap()
+    }
+
+    async fn check_clients(db: &DbPool) {
+        let client = db.create_client("web-1").await.unwrap();
+        db.create_client("db-1").await.unwrap();
+
+        let found = db.get_client_by_token(&client.token).await.unwrap();
+        assert_eq!(found.unwrap().id, client.id);
+        db.update_client_last_seen(&client.id, Some("1.2.3"))
+            .await
+            .unwrap();
+        db.update_client_last_seen(&client.id, None).await.unwrap();
+        let found = db.get_client_by_id(&client.id).await.unwrap().unwrap();
+        assert_eq!(found.version.as_deref(), Some("1.2.3"));
+
+        let hostnames: Vec<String> = db
+            .get_all_clients()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| c.hostname)
+            .collect();
+        assert_eq!(hostnames, vec!["db-1", "web-1"]);
+
+        assert!(db.delete_client(&client.id).await.unwrap());
+        assert!(db.get_client_by_id(&client.id).await.unwrap().is_none());
+    }
+
+    async fn check_metrics(db: &DbPool) {
+        let client = db.create_client("metrics").await.unwrap();
+        let samples: Vec<MetricInput> = (0..3).map(|s| sample(s, s as f64 * 10.0)).collect();
+        let inserted = db.insert_metrics(&client.id, &samples).await.unwrap();
+        assert_eq!(inserted.len(), 3);
+        assert_eq!(db.get_max_metric_id().await.unwrap(), inserted[2].id);
+        assert_eq!(
+            db.get_late_metrics(inserted[0].id, inserted[2].id, &samples[2].timestamp)
+                .await
+                .unwrap(),
+            vec![(client.id.clone(), samples[1].timestamp.clone())]
+        );
+
+        // Detail survives the round trip
+        let latest = db.get_latest_metrics(&client.id, 1).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        let metric = &latest[0];
+        assert_eq!(metric.id, inserted[2].id);
+        assert_eq!(metric.timestamp, samples[2].timestamp);
+        assert_eq!(metric.value("cpu_iowait"), Some(2.5));
+        assert_eq!(metric.value("cpu_core_1"), Some(20.0));
+        assert_eq!(metric.gpus[0].memory_used, Some(1024));
+        assert_eq!(metric.filesystems.len(), 2);
+        assert_eq!(metric.network[0].rx_bytes, 1000.0);
+        assert_eq!(metric.disk_io[0].util, 3.0);
+        assert_eq!(
+            metric.value_for("queue_depth", Some("queue=mail")),
+            Some(12.0)
+        );
+        assert!(metric.memory.is_none());
+
+        // Built-in fields are stored as series and come back as fields
+        assert_eq!(
+            (
+                metric.cpu_usage,
+                metric.ram_usage,
+                metric.disk_usage,
+                metric.inode_usage
+            ),
+            (20.0, 50.0, 10.0, 1.0)
+        );
+        assert_eq!(
+            (metric.gpu_usage, metric.docker_sz),
+            (Some(80.0), Some(2048))
+        );
+        let names: Vec<&str> = metric.values.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["queue_depth"]);
+
+        let between = db
+            .get_metrics_between(&client.id, &samples[1].timestamp, &samples[2].timestamp)
+            .await
+            .unwrap();
+        assert_eq!(between.len(), 1);
+        assert_eq!(between[0].cpu_usage, 10.0);
+        let since = db
+            .get_metrics_since(&client.id, &samples[1].timestamp)
+            .await
+            .unwrap();
+        assert_eq!(since.len(), 2);
+        assert_eq!(
+            db.get_oldest_metric_timestamp().await.unwrap(),
+            Some(samples[0].timestamp.clone())
+        );
+        let per_client = db.get_latest_metric_per_client().await.unwrap();
+        assert_eq!(per_client.len(), 1);
+        assert_eq!(per_client[0].id, inserted[2].id);
+
+        // Samples from 2023 are outside any recent window
+        assert!(db
+            .get_metrics(&client.id, None, None)
+            .await
+            .unwrap()
+            .is_empty());
+        let hours = Some(24 * 365 * 100);
+        let all = db.get_metrics(&client.id, hours, Some(2)).await.unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].id, inserted[2].id);
+
+        let mounts = db
+            .get_filesystem_metrics(&client.id, hours, None, Some("/data"))
+            .await
+            .unwrap();
+        assert_eq!(mounts.len(), 3);
+        assert_eq!(mounts[0].filesystem.used_bytes, 150);
+        assert_eq!(mounts[0].timestamp, samples[0].timestamp);
+        let mounts = db
+            .get_filesystem_metrics(&client.id, hours, Some(3), None)
+            .await
+            .unwrap();
+        let points: Vec<&str> = mounts
+            .iter()
+            .map(|m| m.filesystem.mount_point.as_str())
+            .collect();
+        assert_eq!(points, vec!["/", "/data", "/"]);
+        let network = db
+            .get_network_metrics(&client.id, hours, None, Some("eth0"))
+            .await
+            .unwrap();
+        assert_eq!(network.len(), 3);
+        let disk_io = db
+            .get_disk_io_metrics(&client.id, hours, None, Some("sdb"))
+            .await
+            .unwrap();
+        assert!(disk_io.is_empty());
+        let gpus = db
+            .get_gpu_metrics(&client.id, hours, None, Some(0))
+            .await
+            .unwrap();
+        assert_eq!(gpus.len(), 3);
+        assert_eq!(gpus[0].gpu.name, "A100");
+
+        let containers = db.get_containers(&client.id).await.unwrap();
+        assert_eq!(containers.len(), 1);
+        assert_eq!(containers[0].updated_at, samples[2].timestamp);
+        assert_eq!(db.get_all_containers().await.unwrap().len(), 1);
+        let snapshots = db.get_process_snapshots(&client.id, None).await.unwrap();
+        assert_eq!(snapshots.len(), 3);
+        assert_eq!(snapshots[0].timestamp, samples[2].timestamp);
+
+        let storage = db.get_client_storage().await.unwrap();
+        let row = storage.iter().find(|s| s.client_id == client.id).unwrap();
+        assert_eq!(row.metric_rows, 3);
+        assert_eq!(
+            row.oldest_metric.as_deref(),
+            Some(samples[0].timestamp.as_str())
+        );
+
+        // Cleanup deletes in chunks
+        let cutoff = &samples[2].timestamp;
+        assert_eq!(
+            db.delete_metrics_before(&client.id, cutoff, 1)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            db.delete_metrics_before(&client.id, cutoff, 10)
+                .await
+                .unwrap(),
+            1
+        );
+        let left = db.get_metrics(&client.id, hours, None).await.unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].gpus.len(), 1);
+
+        let (size, free) = db.get_database_size().await.unwrap();
+        assert!(size > 0 && free >= 0);
+        db.vacuum().await.unwrap();
+    }
+
+    async fn check_rollups(db: &DbPool) {
+        let client = db.create_client("rollups").await.unwrap();
+        let agg = |avg: f64| Aggregate {
+            min: avg,
+            max: avg,
+            avg,
+            stddev: 0.0,
+            p50: avg,
+            p90: avg,
+            p95: avg,
+            p99: avg,
+            count: 60,
+        };
+        let bucket = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap();
+        let old = bucket(1_700_000_000);
+        let now = bucket(Utc::now().timestamp() - 60);
+
+        let buckets = BTreeMap::from([
+            (old, vec![("cpu".to_string(), agg(10.0))]),
+            (
+                now,
+                vec![
+                    ("cpu".to_string(), agg(20.0)),
+                    ("ram".to_string(), agg(5.0)),
+                ],
+            ),
+        ]);
+        assert_eq!(
+            db.upsert_rollups(&client.id, 60, &buckets).await.unwrap(),
+            2
+        );
+        // Re-aggregating a bucket replaces it
+        let buckets = BTreeMap::from([(now, vec![("cpu".to_string(), agg(30.0))])]);
+        db.upsert_rollups(&client.id, 60, &buckets).await.unwrap();
+
+        let recent = db.get_rollups(&client.id, 60, 1).await.unwrap();
+        let types: Vec<(&str, f64)> = recent
+            .iter()
+            .map(|r| (r.metric_type.as_str(), r.avg))
+            .collect();
+        assert_eq!(types, vec![("cpu", 30.0), ("ram", 5.0)]);
+        let between = db
+            .get_rollups_between(&client.id, 60, &old.to_rfc3339(), &now.to_rfc3339())
+            .await
+            .unwrap();
+        assert_eq!(between.len(), 1);
+        assert_eq!(between[0].avg, 10.0);
+
+        assert_eq!(db.get_rollup_progress(60).await.unwrap(), None);
+        assert_eq!(db.get_rollup_metric_id(60).await.unwrap(), 0);
+        db.set_rollup_progress(60, "a", 1).await.unwrap();
+        db.set_rollup_progress(60, "b", 2).await.unwrap();
+        assert_eq!(
+            db.get_rollup_progress(60).await.unwrap().as_deref(),
+            Some("b")
+        );
+        assert_eq!(db.get_rollup_metric_id(60).await.unwrap(), 2);
+
+        let cutoff = bucket(1_700_000_060).to_rfc3339();
+        assert_eq!(db.delete_rollups_before(60, &cutoff, 100).await.unwrap(), 1);
+    }
+
+    async fn check_stats(db: &DbPool) {
+        let client = db.create_client("stats").await.unwrap();
+        let at = |secs: i64| DateTime::from_timestamp(secs, 0).unwrap();
+        let base = bucket_start(Utc::now() - Duration::minutes(30), 60);
+
+        // Two rolled up minutes, then raw samples after the rollup progress
+        let buckets = BTreeMap::from([
+            (
+                base,
+                vec![(
+                    "cpu".to_string(),
+                    rollups::aggregate(&[10.0, 20.0, 30.0]).unwrap(),
+                )],
+            ),
+            (
+                base + Duration::minutes(1),
+                vec![("cpu".to_string(), rollups::aggregate(&[40.0]).unwrap())],
+            ),
+        ]);
+        db.upsert_rollups(&client.id, 60, &buckets).await.unwrap();
+        let progress = base + Duration::minutes(2);
+        db.set_rollup_progress(60, &progress.to_rfc3339(), 0)
+            .await
+            .unwrap();
+        let raw: Vec<MetricInput> = [50.0, 60.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &cpu)| MetricInput {
+                timestamp: at(progress.timestamp() + 30 + i as i64).to_rfc3339(),
+                ..sample(0, cpu)
+            })
+            .collect();
+        db.insert_metrics(&client.id, &raw).await.unwrap();
+
+        let stats = db.get_stats(&client.id, Some(1), None, None).await.unwrap();
+        assert_eq!(stats[0].metric_type, "cpu");
+        assert!(stats.iter().any(|s| s.metric_type == "ram" && s.count == 2));
+        let cpu = &stats[0];
+        assert_eq!((cpu.min, cpu.max, cpu.count), (10.0, 60.0, 6));
+        assert!((cpu.avg - 35.0).abs() < 1e-9);
+        assert!((cpu.stddev - 17.0783).abs() < 1e-3);
+        assert!(cpu.bucket.is_none());
+
+        let stats = db
+            .get_stats(&client.id, Some(1), Some("cpu"), None)
+            .await
+            .unwrap();
+        assert_eq!(stats.len(), 1);
+
+        let hourly = db
+            .get_stats(
+                &client.id,
+                Some(2),
+                Some("cpu"),
+                rollups::tier_by_name("1h"),
+            )
+            .await
+            .unwrap();
+        assert!(hourly.iter().all(|s| s.metric_type == "cpu"));
+        assert!(hourly
+            .iter()
+            .all(|s| s.bucket.as_deref().unwrap().ends_with(":00:00+00:00")));
+        assert_eq!(hourly.iter().map(|s| s.count).sum::<i64>(), 6);
+    }
+
+    async fn check_alerting(db: &DbPool) {
+        let client = db.create_client("alerts").await.unwrap();
+        let channel = db
+            .create_notification_channel(&NotificationChannelInput {
+                name: "ops".to_string(),
+                kind: "webhook".to_string(),
+                config: json!({ "url": "http://localhost" }),
+                enabled: None,
+            })
+            .await
+            .unwrap();
+        assert!(channel.enabled);
+
+        let rule = db
+            .create_alert_rule(&AlertRuleInput {
+                client_id: Some(client.id.clone()),
+                metric_type: "disk".to_string(),
+                threshold: 90.0,
+                duration_sec: None,
+                channel_ids: Some(vec![channel.id, channel.id]),
+                target: Some("/data".to_string()),
+            })
+            .await
+            .unwrap();
+        let found = db.get_alert_rule_by_id(rule.id).await.unwrap().unwrap();
+        assert_eq!(found.target.as_deref(), Some("/data"));
+        assert_eq!(found.duration_sec, 30);
+        assert_eq!(
+            db.get_alert_rules_for_client(&client.id)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(db
+            .get_alert_rules_for_client("other")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            db.get_rule_channel_ids(rule.id).await.unwrap(),
+            vec![channel.id]
+        );
+        assert_eq!(db.get_channels_for_rule(rule.id).await.unwrap().len(), 1);
+
+        // Rule and offline incidents (no rule) are told apart
+        let opened = db
+            .open_incident(
+                &client.id,
+                Some(rule.id),
+                "disk",
+                90.0,
+                "2023-11-14T22:00:00+00:00",
+                95.0,
+            )
+            .await
+            .unwrap();
+        assert!(opened.is_some());
+        let again = db
+            .open_incident(
+                &client.id,
+                Some(rule.id),
+                "disk",
+                90.0,
+                "2023-11-14T22:01:00+00:00",
+                96.0,
+            )
+            .await
+            .unwrap();
+        assert!(again.is_none());
+        let offline = db
+            .open_incident(
+                &client.id,
+                None,
+                "disk",
+                0.0,
+                "2023-11-14T22:02:00+00:00",
+                1.0,
+            )
+            .await
+            .unwrap();
+        assert!(offline.is_some());
+        assert_eq!(db.get_open_incidents("disk").await.unwrap().len(), 2);
+
+        assert!(db
+            .resolve_incident(&client.id, None, "disk", "2023-11-14T22:03:00+00:00", 0.5)
+            .await
+            .unwrap());
+        assert!(db
+            .resolve_incident(
+                &client.id,
+                Some(rule.id),
+                "disk",
+                "2023-11-14T22:05:00+00:00",
+                99.0
+            )
+            .await
+            .unwrap());
+        assert!(db.get_open_incidents("disk").await.unwrap().is_empty());
+
+        let incidents = db
+            .get_incidents(&IncidentQuery {
+                client_id: Some(client.id.clone()),
+                from: Some("2023-11-14T22:04:00+00:00".to_string()),
+                to: None,
+                limit: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(incidents.len(), 1);
+        assert_eq!(incidents[0].peak_value, 99.0);
+        assert_eq!(
+            db.get_incidents(&IncidentQuery {
+                client_id: None,
+                from: None,
+                to: None,
+                limit: None,
+            })
+            .await
+            .unwrap()
+            .len(),
+            2
+        );
+
+        db.set_rule_channels(rule.id, &[]).await.unwrap();
+        assert!(db.get_channels_for_rule(rule.id).await.unwrap().is_empty());
+        assert!(db.delete_alert_rule(rule.id).await.unwrap());
+        assert!(db.delete_notification_channel(channel.id).await.unwrap());
+        assert!(db.get_notification_channels().await.unwrap().is_empty());
+    }
+
+    async fn check_settings_and_keys(db: &DbPool) {
+        let client = db.create_client("settings").await.unwrap();
+
+        assert_eq!(db.get_setting("retention_days").await.unwrap(), None);
+        db.set_setting("retention_days", "30").await.unwrap();
+        db.set_setting("retention_days", "60").await.unwrap();
+        assert_eq!(
+            db.get_setting("retention_days").await.unwrap().as_deref(),
+            Some("60")
+        );
+        assert_eq!(db.get_all_settings().await.unwrap().len(), 1);
+
+        db.set_client_setting(&client.id, "retention_days", "7")
+            .await
+            .unwrap();
+        db.set_client_setting(&client.id, "retention_days", "14")
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_client_overrides("retention_days").await.unwrap(),
+            vec![(client.id.clone(), "14".to_string())]
+        );
+        assert_eq!(db.get_client_settings(&client.id).await.unwrap().len(), 1);
+        assert!(db
+            .delete_client_setting(&client.id, "retention_days")
+            .await
+            .unwrap());
+
+        assert_eq!(db.count_api_keys().await.unwrap(), 0);
+        let key = db
+            .create_api_key("noc", "hash1", Role::Readonly)
+            .await
+            .unwrap();
+        db.upsert_api_key("admin", "hash2", Role::Admin)
+            .await
+            .unwrap();
+        db.upsert_api_key("admin", "hash3", Role::Admin)
+            .await
+            .unwrap();
+        assert_eq!(db.count_api_keys().await.unwrap(), 2);
+        assert!(db.get_api_key_by_hash("hash2").await.unwrap().is_none());
+        assert_eq!(
+            db.get_api_key_by_hash("hash1").await.unwrap().unwrap().id,
+            key.id
+        );
+        assert!(db.delete_api_key(key.id).await.unwrap());
+        assert_eq!(db.get_api_keys().await.unwrap().len(), 1);
+    }
+
+    async fn check_storage(db: DbPool) {
+        check_clients(&db).await;
+        check_metrics(&db).await;
+        check_rollups(&db).await;
+        // After check_rollups, which expects no rollup progress yet
+        check_stats(&db).await;
+        check_alerting(&db).await;
+        check_settings_and_keys(&db).await;
+    }
+
+    #[test]
+    fn test_redact_url() {
+        assert_eq!(
+            redact_url("postgres://monitor:s3cret@db:5432/monitor"),
+            "postgres://monitor:***@db:5432/monitor"
+        );
+        assert_eq!(redact_url("postgres://db/monitor"), "postgres://db/monitor");
+        assert_eq!(
+            redact_url("sqlite:data/monitor.db"),
+            "sqlite:data/monitor.db"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_storage() {
+        check_storage(memory().await).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_batch_is_atomic() {
+        let db = memory().await;
+        let client = db.create_client("atomic").await.unwrap();
+
+        // The last sample's duplicate GPU index fails the batch after the
+        // samples themselves were written
+        let mut samples: Vec<MetricInput> = (0..3).map(|s| sample(s, 10.0)).collect();
+        let gpu = samples[2].gpus[0].clone();
+        samples[2].gpus.push(gpu);
+        assert!(db.insert_metrics(&client.id, &samples).await.is_err());
+
+        assert!(db
+            .get_latest_metrics(&client.id, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(db.get_containers(&client.id).await.unwrap().is_empty());
+        assert!(db
+            .get_process_snapshots(&client.id, None)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Sustained ingest into an on-disk database, with every client sending a
+    /// 10-sample batch per report interval as the client does. Run with
+    /// `cargo test --release bench_sqlite_ingest -- --ignored --nocapture`
+    #[tokio::test(flavor = "multi_thread")]
+    #[ignore]
+    async fn bench_sqlite_ingest() {
+        const CLIENTS: usize = 3000;
+        const ROUNDS: i64 = 5;
+        const REPORT_INTERVAL_SECS: f64 = 10.0;
+
+        let dir = std::env::temp_dir().join(format!("monitor-bench-{}", Uuid::new_v4()));
+        let url = format!("sqlite:{}", dir.join("monitor.db").display());
+        let db = init_db(&url).await.unwrap();
+
+        let mut clients = Vec::with_capacity(CLIENTS);
+        for i in 0..CLIENTS {
+            clients.push(db.create_client(&format!("host-{i}")).await.unwrap().id);
+        }
+
+        let started = std::time::Instant::now();
+        for round in 0..ROUNDS {
+            let batches = clients.iter().map(|client_id| {
+                let db = db.clone();
+                let client_id = client_id.clone();
+                tokio::spawn(async move {
+                    let samples: Vec<MetricInput> =
+                        (0..10).map(|s| sample(round * 10 + s, 25.0)).collect();
+                    db.insert_metrics(&client_id, &samples).await.unwrap();
+                })
+            });
+            for batch in batches.collect::<Vec<_>>() {
+                batch.await.unwrap();
+            }
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let samples = (CLIENTS as i64 * ROUNDS * 10) as f64;
+        let per_round = elapsed / ROUNDS as f64;
+        println!(
+            "{CLIENTS} clients: {samples} samples in {elapsed:.2}s ({:.0} samples/s), {per_round:.2}s per {REPORT_INTERVAL_SECS}s report interval",
+            samples / elapsed
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(per_round < REPORT_INTERVAL_SECS);
+    }
+
+    #[tokio::test]
+    async fn test_postgres_storage() {
+        let Ok(url) = std::env::var("TEST_POSTGRES_URL") else {
+            eprintln!("TEST_POSTGRES_URL not set, skipping");
+            return;
+        };
+
+        let schema = format!("test_{}", Uuid::new_v4().simple());
+        let admin = PgPoolOptions::new().connect(&url).await.unwrap();
+        admin
+            .execute(format!("CREATE SCHEMA {schema}").as_str())
+            .await
+            .unwrap();
+
+        check_storage(postgres(&url, &schema).await).await;
+
+        admin
+            .execute(format!("DROP SCHEMA {schema} CASCADE").as_str())
+            .await
+            .unwrap();
+    }
+}
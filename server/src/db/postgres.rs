@@ -0,0 +1,1300 @@
+//! PostgreSQL storage, for installations SQLite's single writer can't keep
+//! up with. Sample detail is stored as JSONB on the `metrics` row, and with
+//! TimescaleDB installed `metrics` is a hypertable (see the initial schema).
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{types::Json, Executor, PgConnection, PgPool, QueryBuilder};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+use super::{Storage, PROCESS_SNAPSHOTS_KEPT};
+use crate::alerts;
+use crate::auth::Role;
+use crate::migrations::{self, Migration};
+use crate::models::{
+    AlertRule, AlertRuleInput, ApiKey, Client, ClientStorage, Container, ContainerStats, DiskIo,
+    DiskIoSample, FilesystemSample, FilesystemUsage, GpuDetail, GpuSample, Incident, IncidentQuery,
+    Metric, MetricInput, MetricRollup, NetworkSample, NetworkUsage, NotificationChannel,
+    NotificationChannelInput, ProcessInfo, ProcessSnapshot, Setting,
+};
+use crate::rollups::{self, Aggregate, RollupSums};
+
+/// Most bind parameters PostgreSQL accepts in one statement
+const BIND_LIMIT: usize = 65535;
+
+const CREATE_VERSION_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version BIGINT PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        applied_at TEXT NOT NULL
+    )
+"#;
+
+/// Alert rules with their target attached, aliased `r`
+const SELECT_ALERT_RULES: &str =
+    "SELECT r.*, t.target FROM alert_rules r LEFT JOIN alert_rule_targets t ON t.rule_id = r.id";
+
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        let pool = PgPool::connect(database_url).await?;
+        Ok(Self::new(pool))
+    }
+}
+
+/// Samples are range-scanned on their `time` column, parsed from the RFC 3339
+/// timestamps clients report and the server computes
+fn parse_time(timestamp: &str) -> Result<DateTime<Utc>> {
+    alerts::parse_timestamp(timestamp).ok_or_else(|| anyhow!("invalid timestamp {}", timestamp))
+}
+
+fn since_hours(hours: Option<i64>) -> DateTime<Utc> {
+    Utc::now() - Duration::hours(hours.unwrap_or(24))
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    // Schema operations
+    fn migrations(&self) -> &'static [Migration] {
+        migrations::POSTGRES
+    }
+
+    async fn schema_version(&self) -> Result<i64> {
+        sqlx::raw_sql(CREATE_VERSION_TABLE)
+            .execute(&self.pool)
+            .await?;
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_migrations")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(version.unwrap_or(0))
+    }
+
+    async fn apply_migration(&self, migration: &Migration) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        // Without arguments this goes over the simple protocol, which runs
+        // every statement of the script
+        (&mut *tx).execute(migration.sql).await?;
+        sqlx::query(
+            "INSERT INTO schema_migrations (version, name, applied_at) VALUES ($1, $2, $3)",
+        )
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // Client operations
+    async fn get_client_by_token(&self, token: &str) -> Result<Option<Client>> {
+        let client = sqlx::query_as::<_, Client>("SELECT * FROM clients WHERE token = $1")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(client)
+    }
+
+    async fn get_client_by_id(&self, id: &str) -> Result<Option<Client>> {
+        let client = sqlx::query_as::<_, Client>("SELECT * FROM clients WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(client)
+    }
+
+    async fn get_all_clients(&self) -> Result<Vec<Client>> {
+        let clients = sqlx::query_as::<_, Client>("SELECT * FROM clients ORDER BY hostname")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(clients)
+    }
+
+    async fn create_client(&self, hostname: &str) -> Result<Client> {
+        let id = Uuid::new_v4().to_string();
+        let token = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+
+        sqlx::query("INSERT INTO clients (id, hostname, token, last_seen) VALUES ($1, $2, $3, $4)")
+            .bind(&id)
+            .bind(hostname)
+            .bind(&token)
+            .bind(&now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(Client {
+            id,
+            hostname: hostname.to_string(),
+            token,
+            last_seen: now,
+            version: None,
+            first_seen: None,
+        })
+    }
+
+    async fn update_client_last_seen(&self, client_id: &str, version: Option<&str>) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query(
+            "UPDATE clients SET last_seen = $1, version = COALESCE($2, version),
+                first_seen = COALESCE(first_seen, $1) WHERE id = $3",
+        )
+        .bind(&now)
+        .bind(version)
+        .bind(client_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_client(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM clients WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Metric operations
+    async fn insert_metrics(
+        &self,
+        client_id: &str,
+        metrics: &[MetricInput],
+    ) -> Result<Vec<Metric>> {
+        let times = metrics
+            .iter()
+            .map(|m| parse_time(&m.timestamp))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut tx = self.pool.begin().await?;
+
+        let mut ids = Vec::with_capacity(metrics.len());
+        for (chunk, times) in metrics
+            .chunks(BIND_LIMIT / 13)
+            .zip(times.chunks(BIND_LIMIT / 13))
+        {
+            let mut query = QueryBuilder::new(
+                r#"INSERT INTO metrics (client_id, cpu, memory, docker, systemd, sensors, gpus,
+                    filesystems, network, disk_io, "values", timestamp, time) "#,
+            );
+            query.push_values(chunk.iter().zip(times), |mut row, (m, time)| {
+                row.push_bind(client_id)
+                    .push_bind(m.cpu.as_ref().map(Json))
+                    .push_bind(m.memory.as_ref().map(Json))
+                    .push_bind(m.docker.as_ref().map(Json))
+                    .push_bind(m.systemd.as_ref().map(Json))
+                    .push_bind(m.sensors.as_ref().map(Json))
+                    .push_bind(Json(&m.gpus))
+                    .push_bind(Json(&m.filesystems))
+                    .push_bind(Json(&m.network))
+                    .push_bind(Json(&m.disk_io))
+                    .push_bind(Json(m.stored_series()))
+                    .push_bind(&m.timestamp)
+                    .push_bind(*time);
+            });
+            query.push(" RETURNING id");
+
+            // Identities follow VALUES order, whatever order RETURNING uses
+            let mut chunk_ids: Vec<i64> = query.build_query_scalar().fetch_all(&mut *tx).await?;
+            chunk_ids.sort_unstable();
+            ids.extend(chunk_ids);
+        }
+
+        // Only the batch's latest container list matters
+        if let Some(m) = metrics.iter().rev().find(|m| m.containers.is_some()) {
+            let containers = m.containers.as_deref().unwrap_or_default();
+            replace_containers(&mut tx, client_id, containers, &m.timestamp).await?;
+        }
+
+        let snapshots: Vec<_> = metrics
+            .iter()
+            .filter_map(|m| Some((&m.timestamp, m.processes.as_ref()?)))
+            .collect();
+        insert_process_snapshots(&mut tx, client_id, &snapshots).await?;
+
+        tx.commit().await?;
+
+        Ok(ids
+            .into_iter()
+            .zip(metrics)
+            .map(|(id, m)| Metric {
+                id,
+                client_id: client_id.to_string(),
+                cpu_usage: m.cpu_usage,
+                ram_usage: m.ram_usage,
+                disk_usage: m.disk_usage,
+                inode_usage: m.inode_usage,
+                docker_sz: m.docker_sz,
+                gpu_usage: m.gpu_usage,
+                cpu: m.cpu.clone(),
+                memory: m.memory.clone(),
+                gpus: m.gpus.clone(),
+                filesystems: m.filesystems.clone(),
+                network: m.network.clone(),
+                disk_io: m.disk_io.clone(),
+                docker: m.docker.clone(),
+                systemd: m.systemd.clone(),
+                sensors: m.sensors.clone(),
+                values: m.values.clone(),
+                timestamp: m.timestamp.clone(),
+            })
+            .collect())
+    }
+
+    async fn get_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(
+            r#"
+            SELECT * FROM metrics
+            WHERE client_id = $1 AND time >= $2
+            ORDER BY time DESC
+            LIMIT $3
+            "#,
+        )
+        .bind(client_id)
+        .bind(since_hours(hours))
+        .bind(limit.unwrap_or(1000))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_metrics_since(&self, client_id: &str, since: &str) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(
+            "SELECT * FROM metrics WHERE client_id = $1 AND time >= $2 ORDER BY time ASC",
+        )
+        .bind(client_id)
+        .bind(parse_time(since)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_metrics_between(
+        &self,
+        client_id: &str,
+        start: &str,
+        end: &str,
+    ) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(
+            r#"
+            SELECT * FROM metrics
+            WHERE client_id = $1 AND time >= $2 AND time < $3
+            ORDER BY time ASC
+            "#,
+        )
+        .bind(client_id)
+        .bind(parse_time(start)?)
+        .bind(parse_time(end)?)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_oldest_metric_timestamp(&self) -> Result<Option<String>> {
+        let oldest: Option<String> =
+            sqlx::query_scalar("SELECT timestamp FROM metrics ORDER BY time ASC LIMIT 1")
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(oldest)
+    }
+
+    async fn get_latest_metrics(&self, client_id: &str, count: i64) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(
+            "SELECT * FROM metrics WHERE client_id = $1 ORDER BY time DESC LIMIT $2",
+        )
+        .bind(client_id)
+        .bind(count)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_latest_metric_per_client(&self) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(
+            "SELECT DISTINCT ON (client_id) * FROM metrics ORDER BY client_id, time DESC, id DESC",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_filesystem_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        mount: Option<&str>,
+    ) -> Result<Vec<FilesystemSample>> {
+        let rows = sqlx::query_as::<_, (String, Json<FilesystemUsage>)>(
+            r#"
+            SELECT m.timestamp, f.detail FROM metrics m
+            CROSS JOIN LATERAL jsonb_array_elements(m.filesystems) AS f(detail)
+            WHERE m.client_id = $1 AND m.time >= $2
+              AND ($3::text IS NULL OR f.detail->>'mount_point' = $3)
+            ORDER BY m.time ASC, f.detail->>'mount_point' ASC
+            LIMIT $4
+            "#,
+        )
+        .bind(client_id)
+        .bind(since_hours(hours))
+        .bind(mount)
+        .bind(limit.unwrap_or(10000))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(timestamp, Json(filesystem))| FilesystemSample {
+                timestamp,
+                filesystem,
+            })
+            .collect())
+    }
+
+    async fn get_network_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        interface: Option<&str>,
+    ) -> Result<Vec<NetworkSample>> {
+        let rows = sqlx::query_as::<_, (String, Json<NetworkUsage>)>(
+            r#"
+            SELECT m.timestamp, n.detail FROM metrics m
+            CROSS JOIN LATERAL jsonb_array_elements(m.network) AS n(detail)
+            WHERE m.client_id = $1 AND m.time >= $2
+              AND ($3::text IS NULL OR n.detail->>'interface' = $3)
+            ORDER BY m.time ASC, n.detail->>'interface' ASC
+            LIMIT $4
+            "#,
+        )
+        .bind(client_id)
+        .bind(since_hours(hours))
+        .bind(interface)
+        .bind(limit.unwrap_or(10000))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(timestamp, Json(network))| NetworkSample { timestamp, network })
+            .collect())
+    }
+
+    async fn get_disk_io_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        device: Option<&str>,
+    ) -> Result<Vec<DiskIoSample>> {
+        let rows = sqlx::query_as::<_, (String, Json<DiskIo>)>(
+            r#"
+            SELECT m.timestamp, d.detail FROM metrics m
+            CROSS JOIN LATERAL jsonb_array_elements(m.disk_io) AS d(detail)
+            WHERE m.client_id = $1 AND m.time >= $2
+              AND ($3::text IS NULL OR d.detail->>'device' = $3)
+            ORDER BY m.time ASC, d.detail->>'device' ASC
+            LIMIT $4
+            "#,
+        )
+        .bind(client_id)
+        .bind(since_hours(hours))
+        .bind(device)
+        .bind(limit.unwrap_or(10000))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(timestamp, Json(disk_io))| DiskIoSample { timestamp, disk_io })
+            .collect())
+    }
+
+    async fn get_gpu_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        gpu: Option<i64>,
+    ) -> Result<Vec<GpuSample>> {
+        let rows = sqlx::query_as::<_, (String, Json<GpuDetail>)>(
+            r#"
+            SELECT m.timestamp, g.detail FROM metrics m
+            CROSS JOIN LATERAL jsonb_array_elements(m.gpus) AS g(detail)
+            WHERE m.client_id = $1 AND m.time >= $2
+              AND ($3::bigint IS NULL OR (g.detail->>'index')::bigint = $3)
+            ORDER BY m.time ASC, (g.detail->>'index')::bigint ASC
+            LIMIT $4
+            "#,
+        )
+        .bind(client_id)
+        .bind(since_hours(hours))
+        .bind(gpu)
+        .bind(limit.unwrap_or(10000))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(timestamp, Json(gpu))| GpuSample { timestamp, gpu })
+            .collect())
+    }
+
+    async fn delete_metrics_before(
+        &self,
+        client_id: &str,
+        cutoff: &str,
+        chunk: i64,
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM metrics WHERE (id, time) IN (
+                SELECT id, time FROM metrics
+                WHERE client_id = $1 AND time < $2
+                LIMIT $3
+            )
+            "#,
+        )
+        .bind(client_id)
+        .bind(parse_time(cutoff)?)
+        .bind(chunk)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    // Container operations
+    async fn get_containers(&self, client_id: &str) -> Result<Vec<Container>> {
+        let containers = sqlx::query_as::<_, Container>(
+            "SELECT * FROM containers WHERE client_id = $1 ORDER BY name",
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(containers)
+    }
+
+    async fn get_all_containers(&self) -> Result<Vec<Container>> {
+        let containers =
+            sqlx::query_as::<_, Container>("SELECT * FROM containers ORDER BY client_id, name")
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(containers)
+    }
+
+    // Process snapshot operations
+    async fn get_process_snapshots(
+        &self,
+        client_id: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<ProcessSnapshot>> {
+        let snapshots = sqlx::query_as::<_, ProcessSnapshot>(
+            r#"
+            SELECT timestamp, processes FROM process_snapshots
+            WHERE client_id = $1
+            ORDER BY timestamp DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(client_id)
+        .bind(limit.unwrap_or(PROCESS_SNAPSHOTS_KEPT))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(snapshots)
+    }
+
+    // Rollup operations
+    async fn upsert_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        buckets: &BTreeMap<DateTime<Utc>, Vec<(String, Aggregate)>>,
+    ) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let mut written = 0;
+
+        for (bucket, aggregates) in buckets {
+            let bucket = bucket.to_rfc3339();
+            for (metric_type, agg) in aggregates {
+                sqlx::query(
+                    r#"
+                    INSERT INTO metric_rollups
+                        (client_id, resolution, metric_type, bucket, min, max, avg, stddev, p50, p90, p95, p99, count)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+                    ON CONFLICT (client_id, resolution, metric_type, bucket) DO UPDATE SET
+                        min = EXCLUDED.min, max = EXCLUDED.max, avg = EXCLUDED.avg,
+                        stddev = EXCLUDED.stddev, p50 = EXCLUDED.p50, p90 = EXCLUDED.p90,
+                        p95 = EXCLUDED.p95, p99 = EXCLUDED.p99, count = EXCLUDED.count
+                    "#,
+                )
+                .bind(client_id)
+                .bind(resolution)
+                .bind(metric_type)
+                .bind(&bucket)
+                .bind(agg.min)
+                .bind(agg.max)
+                .bind(agg.avg)
+                .bind(agg.stddev)
+                .bind(agg.p50)
+                .bind(agg.p90)
+                .bind(agg.p95)
+                .bind(agg.p99)
+                .bind(agg.count)
+                .execute(&mut *tx)
+                .await?;
+            }
+            written += 1;
+        }
+
+        tx.commit().await?;
+        Ok(written)
+    }
+
+    async fn get_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        hours: i64,
+    ) -> Result<Vec<MetricRollup>> {
+        let since = (Utc::now() - Duration::hours(hours)).to_rfc3339();
+
+        let rollups = sqlx::query_as::<_, MetricRollup>(
+            r#"
+            SELECT * FROM metric_rollups
+            WHERE client_id = $1 AND resolution = $2 AND bucket >= $3
+            ORDER BY bucket DESC, metric_type
+            "#,
+        )
+        .bind(client_id)
+        .bind(resolution)
+        .bind(&since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rollups)
+    }
+
+    async fn get_rollups_between(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<MetricRollup>> {
+        let rollups = sqlx::query_as::<_, MetricRollup>(
+            r#"
+            SELECT * FROM metric_rollups
+            WHERE client_id = $1 AND resolution = $2 AND bucket >= $3 AND bucket < $4
+            ORDER BY bucket, metric_type
+            "#,
+        )
+        .bind(client_id)
+        .bind(resolution)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rollups)
+    }
+
+    async fn sum_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        since: &str,
+        metric_type: Option<&str>,
+        bucket_sec: Option<i64>,
+    ) -> Result<Vec<RollupSums>> {
+        let sums = sqlx::query_as::<_, RollupSums>(
+            r#"
+            SELECT MIN(bucket) AS bucket, metric_type,
+                MIN(min) AS min, MAX(max) AS max, SUM(count)::BIGINT AS count,
+                SUM(count * avg) AS sum,
+                SUM(count * (stddev * stddev + avg * avg)) AS sum_sq,
+                SUM(count * p50) AS p50_sum, MAX(p90) AS p90_max,
+                MAX(p95) AS p95_max, MAX(p99) AS p99_max
+            FROM metric_rollups
+            WHERE client_id = $1 AND resolution = $2 AND bucket >= $3
+                AND ($4::TEXT IS NULL OR metric_type = $4)
+            GROUP BY substr(bucket, 1, $5), metric_type
+            ORDER BY MIN(bucket), metric_type
+            "#,
+        )
+        .bind(client_id)
+        .bind(resolution)
+        .bind(since)
+        .bind(metric_type)
+        .bind(rollups::bucket_prefix_len(bucket_sec) as i32)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sums)
+    }
+
+    async fn get_rollup_progress(&self, resolution: i64) -> Result<Option<String>> {
+        let done: Option<String> =
+            sqlx::query_scalar("SELECT completed_until FROM rollup_progress WHERE resolution = $1")
+                .bind(resolution)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(done)
+    }
+
+    async fn get_rollup_metric_id(&self, resolution: i64) -> Result<i64> {
+        let id: Option<i64> =
+            sqlx::query_scalar("SELECT metric_id FROM rollup_progress WHERE resolution = $1")
+                .bind(resolution)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(id.unwrap_or(0))
+    }
+
+    async fn set_rollup_progress(
+        &self,
+        resolution: i64,
+        completed_until: &str,
+        metric_id: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO rollup_progress (resolution, completed_until, metric_id)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (resolution) DO UPDATE
+            SET completed_until = EXCLUDED.completed_until, metric_id = EXCLUDED.metric_id
+            "#,
+        )
+        .bind(resolution)
+        .bind(completed_until)
+        .bind(metric_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_max_metric_id(&self) -> Result<i64> {
+        let id: Option<i64> = sqlx::query_scalar("SELECT MAX(id) FROM metrics")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(id.unwrap_or(0))
+    }
+
+    async fn get_late_metrics(
+        &self,
+        after_id: i64,
+        up_to_id: i64,
+        before: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let late = sqlx::query_as(
+            r#"
+            SELECT DISTINCT client_id, timestamp FROM metrics
+            WHERE id > $1 AND id <= $2 AND time < $3
+            "#,
+        )
+        .bind(after_id)
+        .bind(up_to_id)
+        .bind(parse_time(before)?)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(late)
+    }
+
+    async fn delete_rollups_before(
+        &self,
+        resolution: i64,
+        cutoff: &str,
+        chunk: i64,
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM metric_rollups WHERE ctid = ANY(ARRAY(
+                SELECT ctid FROM metric_rollups
+                WHERE resolution = $1 AND bucket < $2
+                LIMIT $3
+            ))
+            "#,
+        )
+        .bind(resolution)
+        .bind(cutoff)
+        .bind(chunk)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    // Alert rule operations
+    async fn get_alert_rules(&self) -> Result<Vec<AlertRule>> {
+        let rules = sqlx::query_as::<_, AlertRule>(SELECT_ALERT_RULES)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rules)
+    }
+
+    async fn get_alert_rule_by_id(&self, id: i64) -> Result<Option<AlertRule>> {
+        let rule = sqlx::query_as::<_, AlertRule>(&format!("{SELECT_ALERT_RULES} WHERE r.id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(rule)
+    }
+
+    async fn get_alert_rules_for_client(&self, client_id: &str) -> Result<Vec<AlertRule>> {
+        let rules = sqlx::query_as::<_, AlertRule>(&format!(
+            "{SELECT_ALERT_RULES} WHERE r.client_id IS NULL OR r.client_id = $1"
+        ))
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rules)
+    }
+
+    async fn create_alert_rule(&self, rule: &AlertRuleInput) -> Result<AlertRule> {
+        let duration_sec = rule.duration_sec.unwrap_or(30);
+        let id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO alert_rules (client_id, metric_type, threshold, duration_sec)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(&rule.client_id)
+        .bind(&rule.metric_type)
+        .bind(rule.threshold)
+        .bind(duration_sec)
+        .fetch_one(&self.pool)
+        .await?;
+
+        if let Some(channel_ids) = &rule.channel_ids {
+            self.set_rule_channels(id, channel_ids).await?;
+        }
+        if let Some(target) = &rule.target {
+            sqlx::query("INSERT INTO alert_rule_targets (rule_id, target) VALUES ($1, $2)")
+                .bind(id)
+                .bind(target)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(AlertRule {
+            id,
+            client_id: rule.client_id.clone(),
+            metric_type: rule.metric_type.clone(),
+            threshold: rule.threshold,
+            duration_sec,
+            target: rule.target.clone(),
+        })
+    }
+
+    async fn delete_alert_rule(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM alert_rules WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Notification channel operations
+    async fn get_notification_channels(&self) -> Result<Vec<NotificationChannel>> {
+        let channels = sqlx::query_as::<_, NotificationChannel>(
+            "SELECT * FROM notification_channels ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(channels)
+    }
+
+    async fn get_notification_channel(&self, id: i64) -> Result<Option<NotificationChannel>> {
+        let channel = sqlx::query_as::<_, NotificationChannel>(
+            "SELECT * FROM notification_channels WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(channel)
+    }
+
+    async fn create_notification_channel(
+        &self,
+        input: &NotificationChannelInput,
+    ) -> Result<NotificationChannel> {
+        let config = input.config.to_string();
+        let enabled = input.enabled.unwrap_or(true);
+        let id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO notification_channels (name, kind, config, enabled)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(&input.name)
+        .bind(&input.kind)
+        .bind(&config)
+        .bind(enabled)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(NotificationChannel {
+            id,
+            name: input.name.clone(),
+            kind: input.kind.clone(),
+            config,
+            enabled,
+        })
+    }
+
+    async fn delete_notification_channel(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM notification_channels WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_channels_for_rule(&self, rule_id: i64) -> Result<Vec<NotificationChannel>> {
+        let channels = sqlx::query_as::<_, NotificationChannel>(
+            r#"
+            SELECT c.* FROM notification_channels c
+            JOIN alert_rule_channels rc ON rc.channel_id = c.id
+            WHERE rc.rule_id = $1 AND c.enabled
+            "#,
+        )
+        .bind(rule_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(channels)
+    }
+
+    async fn get_rule_channel_ids(&self, rule_id: i64) -> Result<Vec<i64>> {
+        let ids: Vec<(i64,)> = sqlx::query_as(
+            "SELECT channel_id FROM alert_rule_channels WHERE rule_id = $1 ORDER BY channel_id",
+        )
+        .bind(rule_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    async fn set_rule_channels(&self, rule_id: i64, channel_ids: &[i64]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM alert_rule_channels WHERE rule_id = $1")
+            .bind(rule_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for channel_id in channel_ids {
+            sqlx::query(
+                r#"
+                INSERT INTO alert_rule_channels (rule_id, channel_id) VALUES ($1, $2)
+                ON CONFLICT DO NOTHING
+                "#,
+            )
+            .bind(rule_id)
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // API key operations
+    async fn get_api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let key = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE key_hash = $1")
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(key)
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let keys = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(keys)
+    }
+
+    async fn count_api_keys(&self) -> Result<i64> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM api_keys")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    async fn create_api_key(&self, name: &str, key_hash: &str, role: Role) -> Result<ApiKey> {
+        let now = Utc::now().to_rfc3339();
+        let id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO api_keys (name, key_hash, role, created_at) VALUES ($1, $2, $3, $4)
+            RETURNING id
+            "#,
+        )
+        .bind(name)
+        .bind(key_hash)
+        .bind(role.as_str())
+        .bind(&now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(ApiKey {
+            id,
+            name: name.to_string(),
+            key_hash: key_hash.to_string(),
+            role: role.as_str().to_string(),
+            created_at: now,
+        })
+    }
+
+    async fn upsert_api_key(&self, name: &str, key_hash: &str, role: Role) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO api_keys (name, key_hash, role, created_at) VALUES ($1, $2, $3, $4)
+            ON CONFLICT (name) DO UPDATE SET key_hash = EXCLUDED.key_hash, role = EXCLUDED.role
+            "#,
+        )
+        .bind(name)
+        .bind(key_hash)
+        .bind(role.as_str())
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_api_key(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Incident operations
+    async fn open_incident(
+        &self,
+        client_id: &str,
+        rule_id: Option<i64>,
+        metric_type: &str,
+        threshold: f64,
+        started_at: &str,
+        value: f64,
+    ) -> Result<Option<i64>> {
+        let existing: Option<(i64,)> = sqlx::query_as(
+            r#"
+            SELECT id FROM incidents
+            WHERE client_id = $1 AND rule_id IS NOT DISTINCT FROM $2 AND metric_type = $3
+              AND ended_at IS NULL
+            "#,
+        )
+        .bind(client_id)
+        .bind(rule_id)
+        .bind(metric_type)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if existing.is_some() {
+            return Ok(None);
+        }
+
+        let id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO incidents (client_id, rule_id, metric_type, threshold, started_at, peak_value)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING id
+            "#,
+        )
+        .bind(client_id)
+        .bind(rule_id)
+        .bind(metric_type)
+        .bind(threshold)
+        .bind(started_at)
+        .bind(value)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(Some(id))
+    }
+
+    async fn resolve_incident(
+        &self,
+        client_id: &str,
+        rule_id: Option<i64>,
+        metric_type: &str,
+        ended_at: &str,
+        peak_value: f64,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE incidents SET ended_at = $1, peak_value = GREATEST(peak_value, $2)
+            WHERE client_id = $3 AND rule_id IS NOT DISTINCT FROM $4 AND metric_type = $5
+              AND ended_at IS NULL
+            "#,
+        )
+        .bind(ended_at)
+        .bind(peak_value)
+        .bind(client_id)
+        .bind(rule_id)
+        .bind(metric_type)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_open_incidents(&self, metric_type: &str) -> Result<Vec<Incident>> {
+        let incidents = sqlx::query_as::<_, Incident>(
+            "SELECT * FROM incidents WHERE metric_type = $1 AND ended_at IS NULL",
+        )
+        .bind(metric_type)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(incidents)
+    }
+
+    async fn get_incidents(&self, query: &IncidentQuery) -> Result<Vec<Incident>> {
+        let incidents = sqlx::query_as::<_, Incident>(
+            r#"
+            SELECT * FROM incidents
+            WHERE ($1::text IS NULL OR client_id = $1)
+              AND ($2::text IS NULL OR started_at <= $2)
+              AND ($3::text IS NULL OR ended_at IS NULL OR ended_at >= $3)
+            ORDER BY started_at DESC
+            LIMIT $4
+            "#,
+        )
+        .bind(&query.client_id)
+        .bind(&query.to)
+        .bind(&query.from)
+        .bind(query.limit.unwrap_or(100))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(incidents)
+    }
+
+    // Settings operations
+    async fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let setting = sqlx::query_as::<_, Setting>("SELECT * FROM settings WHERE key = $1")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(setting.map(|s| s.value))
+    }
+
+    async fn get_all_settings(&self) -> Result<Vec<Setting>> {
+        let settings = sqlx::query_as::<_, Setting>("SELECT * FROM settings")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(settings)
+    }
+
+    async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO settings (key, value) VALUES ($1, $2)
+            ON CONFLICT (key) DO UPDATE SET value = EXCLUDED.value
+            "#,
+        )
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_client_settings(&self, client_id: &str) -> Result<Vec<Setting>> {
+        let settings = sqlx::query_as::<_, Setting>(
+            "SELECT key, value FROM client_settings WHERE client_id = $1",
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(settings)
+    }
+
+    async fn get_client_overrides(&self, key: &str) -> Result<Vec<(String, String)>> {
+        let overrides = sqlx::query_as::<_, (String, String)>(
+            "SELECT client_id, value FROM client_settings WHERE key = $1",
+        )
+        .bind(key)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(overrides)
+    }
+
+    async fn set_client_setting(&self, client_id: &str, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO client_settings (client_id, key, value) VALUES ($1, $2, $3)
+            ON CONFLICT (client_id, key) DO UPDATE SET value = EXCLUDED.value
+            "#,
+        )
+        .bind(client_id)
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_client_setting(&self, client_id: &str, key: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM client_settings WHERE client_id = $1 AND key = $2")
+            .bind(client_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Storage operations
+    async fn get_database_size(&self) -> Result<(i64, i64)> {
+        // Space freed by deletes is reused by PostgreSQL itself and isn't
+        // cheaply measurable, so none is reported as free
+        let size: i64 = sqlx::query_scalar("SELECT pg_database_size(current_database())")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok((size, 0))
+    }
+
+    async fn get_client_storage(&self) -> Result<Vec<ClientStorage>> {
+        let storage = sqlx::query_as::<_, ClientStorage>(
+            r#"
+            SELECT
+                c.id AS client_id,
+                c.hostname,
+                (SELECT COUNT(*) FROM metrics m WHERE m.client_id = c.id) AS metric_rows,
+                (SELECT COUNT(*) FROM metric_rollups r WHERE r.client_id = c.id) AS rollup_rows,
+                (SELECT timestamp FROM metrics m WHERE m.client_id = c.id ORDER BY time ASC LIMIT 1) AS oldest_metric,
+                (SELECT timestamp FROM metrics m WHERE m.client_id = c.id ORDER BY time DESC LIMIT 1) AS newest_metric
+            FROM clients c
+            ORDER BY metric_rows DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(storage)
+    }
+
+    async fn vacuum(&self) -> Result<()> {
+        // VACUUM can't run inside the transaction the extended protocol opens
+        sqlx::raw_sql("VACUUM (ANALYZE)")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Replace a client's container list with the one from its latest poll
+async fn replace_containers(
+    conn: &mut PgConnection,
+    client_id: &str,
+    containers: &[ContainerStats],
+    updated_at: &str,
+) -> Result<()> {
+    sqlx::query("DELETE FROM containers WHERE client_id = $1")
+        .bind(client_id)
+        .execute(&mut *conn)
+        .await?;
+
+    // A poll listing the same container twice keeps the last entry
+    let containers: BTreeMap<&str, &ContainerStats> =
+        containers.iter().map(|c| (c.id.as_str(), c)).collect();
+    let containers: Vec<_> = containers.into_values().collect();
+
+    for chunk in containers.chunks(BIND_LIMIT / 11) {
+        let mut query = QueryBuilder::new(
+            "INSERT INTO containers (client_id, container_id, name, image, state, health, restart_count, cpu_percent, memory_used, memory_limit, updated_at) ",
+        );
+        query.push_values(chunk, |mut row, c| {
+            row.push_bind(client_id)
+                .push_bind(&c.id)
+                .push_bind(&c.name)
+                .push_bind(&c.image)
+                .push_bind(&c.state)
+                .push_bind(&c.health)
+                .push_bind(c.restart_count)
+                .push_bind(c.cpu_percent)
+                .push_bind(c.memory_used)
+                .push_bind(c.memory_limit)
+                .push_bind(updated_at);
+        });
+        query.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Store snapshots, keeping only the client's most recent ones
+async fn insert_process_snapshots(
+    conn: &mut PgConnection,
+    client_id: &str,
+    snapshots: &[(&String, &Vec<ProcessInfo>)],
+) -> Result<()> {
+    if snapshots.is_empty() {
+        return Ok(());
+    }
+
+    for chunk in snapshots.chunks(BIND_LIMIT / 3) {
+        let mut query =
+            QueryBuilder::new("INSERT INTO process_snapshots (client_id, timestamp, processes) ");
+        query.push_values(chunk, |mut row, (timestamp, processes)| {
+            row.push_bind(client_id)
+                .push_bind(*timestamp)
+                .push_bind(Json(*processes));
+        });
+        query.build().execute(&mut *conn).await?;
+    }
+
+    sqlx::query(
+        r#"
+        DELETE FROM process_snapshots
+        WHERE client_id = $1 AND id NOT IN (
+            SELECT id FROM process_snapshots WHERE client_id = $1
+            ORDER BY timestamp DESC LIMIT $2
+        )
+        "#,
+    )
+    .bind(client_id)
+    .bind(PROCESS_SNAPSHOTS_KEPT)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,1508 @@
+//! SQLite storage, the default. Sample detail lives in side tables keyed by
+//! `metrics.id` and is reassembled as JSON by `SELECT_METRICS`.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::{
+    query_builder::Separated,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous},
+    types::Json,
+    Executor, QueryBuilder, Sqlite, SqliteConnection, SqlitePool,
+};
+use std::{collections::BTreeMap, path::Path, str::FromStr};
+use uuid::Uuid;
+
+use super::{Storage, PROCESS_SNAPSHOTS_KEPT};
+use crate::auth::Role;
+use crate::migrations::{self, Migration};
+use crate::models::{
+    AlertRule, AlertRuleInput, ApiKey, Client, ClientStorage, Container, ContainerStats,
+    DiskIoSample, FilesystemSample, GpuSample, Incident, IncidentQuery, Metric, MetricInput,
+    MetricRollup, NetworkSample, NotificationChannel, NotificationChannelInput, ProcessInfo,
+    ProcessSnapshot, Setting,
+};
+use crate::rollups::{self, Aggregate, RollupSums};
+
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Open the database, creating its directory if needed
+    pub async fn connect(database_url: &str) -> Result<Self> {
+        // Create database file if it doesn't exist
+        let path = database_url.trim_start_matches("sqlite:");
+        if let Some(parent) = Path::new(path).parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        // WAL lets the dashboard read while a batch is being written, and with
+        // it synchronous=NORMAL only fsyncs at checkpoints
+        let options = SqliteConnectOptions::from_str(database_url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .busy_timeout(std::time::Duration::from_secs(5))
+            .pragma("temp_store", "memory")
+            .pragma("cache_size", "-16000");
+
+        let pool = SqlitePool::connect_with(options).await?;
+        Ok(Self::new(pool))
+    }
+}
+
+/// Most bind parameters SQLite accepts in one statement
+const BIND_LIMIT: usize = 32766;
+
+/// Databases created before versioning have the initial schema (001) but
+/// no record of it; that file only uses `IF NOT EXISTS`, so such databases
+/// are brought under versioning by applying every migration from 001 on.
+const CREATE_VERSION_TABLE: &str = r#"
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version INTEGER PRIMARY KEY NOT NULL,
+        name TEXT NOT NULL,
+        applied_at TEXT NOT NULL
+    )
+"#;
+
+/// Samples with their CPU, memory, Docker, systemd and sensor detail and per-GPU, per-mount,
+/// per-interface and per-block-device readings and reported series attached,
+/// aliased `m`
+const SELECT_METRICS: &str = r#"
+    SELECT m.*, c.detail AS cpu, mem.detail AS memory, dk.detail AS docker, sd.detail AS systemd,
+        se.detail AS sensors, (
+        SELECT json_group_array(json_object(
+            'index', g.gpu_index, 'name', g.name, 'uuid', g.uuid,
+            'utilization', g.utilization, 'memory_used', g.memory_used,
+            'memory_total', g.memory_total, 'temperature', g.temperature,
+            'power_watts', g.power_watts, 'fan_speed', g.fan_speed,
+            'processes', g.processes
+        ))
+        FROM gpu_metrics g WHERE g.metric_id = m.id
+    ) AS gpus, (
+        SELECT json_group_array(json_object(
+            'mount_point', f.mount_point, 'device', f.device, 'fs_type', f.fs_type,
+            'total_bytes', f.total_bytes, 'used_bytes', f.used_bytes,
+            'disk_usage', f.disk_usage, 'inode_usage', f.inode_usage
+        ))
+        FROM filesystem_metrics f WHERE f.metric_id = m.id
+    ) AS filesystems, (
+        SELECT json_group_array(json_object(
+            'interface', n.interface, 'rx_bytes', n.rx_bytes, 'tx_bytes', n.tx_bytes,
+            'rx_packets', n.rx_packets, 'tx_packets', n.tx_packets,
+            'rx_errors', n.rx_errors, 'tx_errors', n.tx_errors,
+            'rx_drops', n.rx_drops, 'tx_drops', n.tx_drops
+        ))
+        FROM network_metrics n WHERE n.metric_id = m.id
+    ) AS network, (
+        SELECT json_group_array(json_object(
+            'device', d.device, 'read_bytes', d.read_bytes, 'write_bytes', d.write_bytes,
+            'read_iops', d.read_iops, 'write_iops', d.write_iops,
+            'await_ms', d.await_ms, 'util', d.util
+        ))
+        FROM disk_io_metrics d WHERE d.metric_id = m.id
+    ) AS disk_io, (
+        SELECT json_group_array(json_object(
+            'name', v.name, 'labels', json(v.labels), 'value', v.value
+        ))
+        FROM metric_values v WHERE v.metric_id = m.id
+    ) AS "values"
+    FROM metrics m
+    LEFT JOIN cpu_metrics c ON c.metric_id = m.id
+    LEFT JOIN memory_metrics mem ON mem.metric_id = m.id
+    LEFT JOIN docker_metrics dk ON dk.metric_id = m.id
+    LEFT JOIN systemd_metrics sd ON sd.metric_id = m.id
+    LEFT JOIN sensor_metrics se ON se.metric_id = m.id
+"#;
+
+/// Alert rules with their target attached, aliased `r`
+const SELECT_ALERT_RULES: &str =
+    "SELECT r.*, t.target FROM alert_rules r LEFT JOIN alert_rule_targets t ON t.rule_id = r.id";
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    // Schema operations
+    fn migrations(&self) -> &'static [Migration] {
+        migrations::SQLITE
+    }
+
+    async fn schema_version(&self) -> Result<i64> {
+        sqlx::raw_sql(CREATE_VERSION_TABLE)
+            .execute(&self.pool)
+            .await?;
+        let version: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM schema_migrations")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(version.unwrap_or(0))
+    }
+
+    async fn apply_migration(&self, migration: &Migration) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        (&mut *tx).execute(migration.sql).await?;
+        sqlx::query("INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // Client operations
+    async fn get_client_by_token(&self, token: &str) -> Result<Option<Client>> {
+        let client = sqlx::query_as::<_, Client>("SELECT * FROM clients WHERE token = ?")
+            .bind(token)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(client)
+    }
+
+    async fn get_client_by_id(&self, id: &str) -> Result<Option<Client>> {
+        let client = sqlx::query_as::<_, Client>("SELECT * FROM clients WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(client)
+    }
+
+    async fn get_all_clients(&self) -> Result<Vec<Client>> {
+        let clients = sqlx::query_as::<_, Client>("SELECT * FROM clients ORDER BY hostname")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(clients)
+    }
+
+    async fn create_client(&self, hostname: &str) -> Result<Client> {
+        let id = Uuid::new_v4().to_string();
+        let token = Uuid::new_v4().to_string();
+        let now = Utc::now().to_rfc3339();
+
+        sqlx::query("INSERT INTO clients (id, hostname, token, last_seen) VALUES (?, ?, ?, ?)")
+            .bind(&id)
+            .bind(hostname)
+            .bind(&token)
+            .bind(&now)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(Client {
+            id,
+            hostname: hostname.to_string(),
+            token,
+            last_seen: now,
+            version: None,
+            first_seen: None,
+        })
+    }
+
+    async fn update_client_last_seen(&self, client_id: &str, version: Option<&str>) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query(
+            "UPDATE clients SET last_seen = ?, version = COALESCE(?, version),
+                first_seen = COALESCE(first_seen, ?) WHERE id = ?",
+        )
+        .bind(&now)
+        .bind(version)
+        .bind(&now)
+        .bind(client_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_client(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM clients WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Metric operations
+    async fn insert_metrics(
+        &self,
+        client_id: &str,
+        metrics: &[MetricInput],
+    ) -> Result<Vec<Metric>> {
+        let mut tx = self.pool.begin().await?;
+
+        let mut ids = Vec::with_capacity(metrics.len());
+        for chunk in metrics.chunks(BIND_LIMIT / 2) {
+            let mut query = QueryBuilder::new("INSERT INTO metrics (client_id, timestamp) ");
+            query.push_values(chunk, |mut row, m| {
+                row.push_bind(client_id).push_bind(&m.timestamp);
+            });
+            query.push(" RETURNING id");
+
+            // Rowids follow VALUES order, whatever order RETURNING uses
+            let mut chunk_ids: Vec<i64> = query.build_query_scalar().fetch_all(&mut *tx).await?;
+            chunk_ids.sort_unstable();
+            ids.extend(chunk_ids);
+        }
+        let samples: Vec<(i64, &MetricInput)> = ids.into_iter().zip(metrics).collect();
+
+        let cpu: Vec<_> = samples
+            .iter()
+            .filter_map(|(id, m)| Some((*id, m.cpu.as_ref()?)))
+            .collect();
+        insert_detail(&mut tx, "cpu_metrics", &cpu).await?;
+        let memory: Vec<_> = samples
+            .iter()
+            .filter_map(|(id, m)| Some((*id, m.memory.as_ref()?)))
+            .collect();
+        insert_detail(&mut tx, "memory_metrics", &memory).await?;
+        let docker: Vec<_> = samples
+            .iter()
+            .filter_map(|(id, m)| Some((*id, m.docker.as_ref()?)))
+            .collect();
+        insert_detail(&mut tx, "docker_metrics", &docker).await?;
+        let systemd: Vec<_> = samples
+            .iter()
+            .filter_map(|(id, m)| Some((*id, m.systemd.as_ref()?)))
+            .collect();
+        insert_detail(&mut tx, "systemd_metrics", &systemd).await?;
+        let sensors: Vec<_> = samples
+            .iter()
+            .filter_map(|(id, m)| Some((*id, m.sensors.as_ref()?)))
+            .collect();
+        insert_detail(&mut tx, "sensor_metrics", &sensors).await?;
+
+        let gpus: Vec<_> = samples
+            .iter()
+            .flat_map(|(id, m)| m.gpus.iter().map(move |gpu| (*id, gpu)))
+            .collect();
+        insert_rows(
+            &mut tx,
+            "INSERT INTO gpu_metrics (metric_id, gpu_index, name, uuid, utilization, memory_used, memory_total, temperature, power_watts, fan_speed, processes) ",
+            11,
+            &gpus,
+            |mut row, (id, gpu)| {
+                row.push_bind(*id)
+                    .push_bind(gpu.index)
+                    .push_bind(&gpu.name)
+                    .push_bind(&gpu.uuid)
+                    .push_bind(gpu.utilization)
+                    .push_bind(gpu.memory_used)
+                    .push_bind(gpu.memory_total)
+                    .push_bind(gpu.temperature)
+                    .push_bind(gpu.power_watts)
+                    .push_bind(gpu.fan_speed)
+                    .push_bind(gpu.processes);
+            },
+        )
+        .await?;
+
+        let filesystems: Vec<_> = samples
+            .iter()
+            .flat_map(|(id, m)| m.filesystems.iter().map(move |fs| (*id, fs)))
+            .collect();
+        insert_rows(
+            &mut tx,
+            "INSERT OR REPLACE INTO filesystem_metrics (metric_id, mount_point, device, fs_type, total_bytes, used_bytes, disk_usage, inode_usage) ",
+            8,
+            &filesystems,
+            |mut row, (id, fs)| {
+                row.push_bind(*id)
+                    .push_bind(&fs.mount_point)
+                    .push_bind(&fs.device)
+                    .push_bind(&fs.fs_type)
+                    .push_bind(fs.total_bytes)
+                    .push_bind(fs.used_bytes)
+                    .push_bind(fs.disk_usage)
+                    .push_bind(fs.inode_usage);
+            },
+        )
+        .await?;
+
+        let network: Vec<_> = samples
+            .iter()
+            .flat_map(|(id, m)| m.network.iter().map(move |net| (*id, net)))
+            .collect();
+        insert_rows(
+            &mut tx,
+            "INSERT OR REPLACE INTO network_metrics (metric_id, interface, rx_bytes, tx_bytes, rx_packets, tx_packets, rx_errors, tx_errors, rx_drops, tx_drops) ",
+            10,
+            &network,
+            |mut row, (id, net)| {
+                row.push_bind(*id)
+                    .push_bind(&net.interface)
+                    .push_bind(net.rx_bytes)
+                    .push_bind(net.tx_bytes)
+                    .push_bind(net.rx_packets)
+                    .push_bind(net.tx_packets)
+                    .push_bind(net.rx_errors)
+                    .push_bind(net.tx_errors)
+                    .push_bind(net.rx_drops)
+                    .push_bind(net.tx_drops);
+            },
+        )
+        .await?;
+
+        let disk_io: Vec<_> = samples
+            .iter()
+            .flat_map(|(id, m)| m.disk_io.iter().map(move |io| (*id, io)))
+            .collect();
+        insert_rows(
+            &mut tx,
+            "INSERT OR REPLACE INTO disk_io_metrics (metric_id, device, read_bytes, write_bytes, read_iops, write_iops, await_ms, util) ",
+            8,
+            &disk_io,
+            |mut row, (id, io)| {
+                row.push_bind(*id)
+                    .push_bind(&io.device)
+                    .push_bind(io.read_bytes)
+                    .push_bind(io.write_bytes)
+                    .push_bind(io.read_iops)
+                    .push_bind(io.write_iops)
+                    .push_bind(io.await_ms)
+                    .push_bind(io.util);
+            },
+        )
+        .await?;
+
+        let values: Vec<_> = samples
+            .iter()
+            .flat_map(|(id, m)| m.stored_series().into_iter().map(move |value| (*id, value)))
+            .collect();
+        insert_rows(
+            &mut tx,
+            "INSERT OR REPLACE INTO metric_values (metric_id, name, labels, value) ",
+            4,
+            &values,
+            |mut row, (id, value)| {
+                row.push_bind(*id)
+                    .push_bind(&value.name)
+                    .push_bind(Json(&value.labels))
+                    .push_bind(value.value);
+            },
+        )
+        .await?;
+
+        // Only the batch's latest container list matters
+        if let Some(m) = metrics.iter().rev().find(|m| m.containers.is_some()) {
+            let containers = m.containers.as_deref().unwrap_or_default();
+            replace_containers(&mut tx, client_id, containers, &m.timestamp).await?;
+        }
+
+        let snapshots: Vec<_> = metrics
+            .iter()
+            .filter_map(|m| Some((&m.timestamp, m.processes.as_ref()?)))
+            .collect();
+        insert_process_snapshots(&mut tx, client_id, &snapshots).await?;
+
+        tx.commit().await?;
+
+        Ok(samples
+            .into_iter()
+            .map(|(id, m)| Metric {
+                id,
+                client_id: client_id.to_string(),
+                cpu_usage: m.cpu_usage,
+                ram_usage: m.ram_usage,
+                disk_usage: m.disk_usage,
+                inode_usage: m.inode_usage,
+                docker_sz: m.docker_sz,
+                gpu_usage: m.gpu_usage,
+                cpu: m.cpu.clone(),
+                memory: m.memory.clone(),
+                gpus: m.gpus.clone(),
+                filesystems: m.filesystems.clone(),
+                network: m.network.clone(),
+                disk_io: m.disk_io.clone(),
+                docker: m.docker.clone(),
+                systemd: m.systemd.clone(),
+                sensors: m.sensors.clone(),
+                values: m.values.clone(),
+                timestamp: m.timestamp.clone(),
+            })
+            .collect())
+    }
+
+    async fn get_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+    ) -> Result<Vec<Metric>> {
+        let hours = hours.unwrap_or(24);
+        let limit = limit.unwrap_or(1000);
+        let since = (Utc::now() - Duration::hours(hours)).to_rfc3339();
+
+        let metrics = sqlx::query_as::<_, Metric>(&format!(
+            r#"
+            {SELECT_METRICS}
+            WHERE m.client_id = ? AND m.timestamp >= ?
+            ORDER BY m.timestamp DESC
+            LIMIT ?
+            "#
+        ))
+        .bind(client_id)
+        .bind(&since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_metrics_since(&self, client_id: &str, since: &str) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(&format!(
+            r#"
+            {SELECT_METRICS}
+            WHERE m.client_id = ? AND m.timestamp >= ?
+            ORDER BY m.timestamp ASC
+            "#
+        ))
+        .bind(client_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_metrics_between(
+        &self,
+        client_id: &str,
+        start: &str,
+        end: &str,
+    ) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(&format!(
+            r#"
+            {SELECT_METRICS}
+            WHERE m.client_id = ? AND m.timestamp >= ? AND m.timestamp < ?
+            ORDER BY m.timestamp ASC
+            "#
+        ))
+        .bind(client_id)
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_oldest_metric_timestamp(&self) -> Result<Option<String>> {
+        let oldest: Option<String> = sqlx::query_scalar("SELECT MIN(timestamp) FROM metrics")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(oldest)
+    }
+
+    async fn get_latest_metrics(&self, client_id: &str, count: i64) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(&format!(
+            r#"
+            {SELECT_METRICS}
+            WHERE m.client_id = ?
+            ORDER BY m.timestamp DESC
+            LIMIT ?
+            "#
+        ))
+        .bind(client_id)
+        .bind(count)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_latest_metric_per_client(&self) -> Result<Vec<Metric>> {
+        let metrics = sqlx::query_as::<_, Metric>(&format!(
+            r#"
+            {SELECT_METRICS}
+            JOIN (
+                SELECT client_id, MAX(timestamp) AS timestamp FROM metrics GROUP BY client_id
+            ) latest ON m.client_id = latest.client_id AND m.timestamp = latest.timestamp
+            ORDER BY m.client_id, m.id DESC
+            "#
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(metrics
+            .into_iter()
+            .map(Metric::with_builtin_fields)
+            .collect())
+    }
+
+    async fn get_filesystem_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        mount: Option<&str>,
+    ) -> Result<Vec<FilesystemSample>> {
+        let hours = hours.unwrap_or(24);
+        let limit = limit.unwrap_or(10000);
+        let since = (Utc::now() - Duration::hours(hours)).to_rfc3339();
+
+        let samples = sqlx::query_as::<_, FilesystemSample>(
+            r#"
+            SELECT m.timestamp, f.* FROM filesystem_metrics f
+            JOIN metrics m ON m.id = f.metric_id
+            WHERE m.client_id = ? AND m.timestamp >= ? AND (? IS NULL OR f.mount_point = ?)
+            ORDER BY m.timestamp ASC, f.mount_point ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(client_id)
+        .bind(&since)
+        .bind(mount)
+        .bind(mount)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(samples)
+    }
+
+    async fn get_network_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        interface: Option<&str>,
+    ) -> Result<Vec<NetworkSample>> {
+        let hours = hours.unwrap_or(24);
+        let limit = limit.unwrap_or(10000);
+        let since = (Utc::now() - Duration::hours(hours)).to_rfc3339();
+
+        let samples = sqlx::query_as::<_, NetworkSample>(
+            r#"
+            SELECT m.timestamp, n.* FROM network_metrics n
+            JOIN metrics m ON m.id = n.metric_id
+            WHERE m.client_id = ? AND m.timestamp >= ? AND (? IS NULL OR n.interface = ?)
+            ORDER BY m.timestamp ASC, n.interface ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(client_id)
+        .bind(&since)
+        .bind(interface)
+        .bind(interface)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(samples)
+    }
+
+    async fn get_disk_io_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        device: Option<&str>,
+    ) -> Result<Vec<DiskIoSample>> {
+        let hours = hours.unwrap_or(24);
+        let limit = limit.unwrap_or(10000);
+        let since = (Utc::now() - Duration::hours(hours)).to_rfc3339();
+
+        let samples = sqlx::query_as::<_, DiskIoSample>(
+            r#"
+            SELECT m.timestamp, d.* FROM disk_io_metrics d
+            JOIN metrics m ON m.id = d.metric_id
+            WHERE m.client_id = ? AND m.timestamp >= ? AND (? IS NULL OR d.device = ?)
+            ORDER BY m.timestamp ASC, d.device ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(client_id)
+        .bind(&since)
+        .bind(device)
+        .bind(device)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(samples)
+    }
+
+    async fn get_gpu_metrics(
+        &self,
+        client_id: &str,
+        hours: Option<i64>,
+        limit: Option<i64>,
+        gpu: Option<i64>,
+    ) -> Result<Vec<GpuSample>> {
+        let hours = hours.unwrap_or(24);
+        let limit = limit.unwrap_or(10000);
+        let since = (Utc::now() - Duration::hours(hours)).to_rfc3339();
+
+        let samples = sqlx::query_as::<_, GpuSample>(
+            r#"
+            SELECT m.timestamp, g.* FROM gpu_metrics g
+            JOIN metrics m ON m.id = g.metric_id
+            WHERE m.client_id = ? AND m.timestamp >= ? AND (? IS NULL OR g.gpu_index = ?)
+            ORDER BY m.timestamp ASC, g.gpu_index ASC
+            LIMIT ?
+            "#,
+        )
+        .bind(client_id)
+        .bind(&since)
+        .bind(gpu)
+        .bind(gpu)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(samples)
+    }
+
+    async fn delete_metrics_before(
+        &self,
+        client_id: &str,
+        cutoff: &str,
+        chunk: i64,
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM metrics WHERE id IN (
+                SELECT id FROM metrics
+                WHERE client_id = ? AND timestamp < ?
+                LIMIT ?
+            )
+            "#,
+        )
+        .bind(client_id)
+        .bind(cutoff)
+        .bind(chunk)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    // Container operations
+    async fn get_containers(&self, client_id: &str) -> Result<Vec<Container>> {
+        let containers = sqlx::query_as::<_, Container>(
+            "SELECT * FROM containers WHERE client_id = ? ORDER BY name",
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(containers)
+    }
+
+    async fn get_all_containers(&self) -> Result<Vec<Container>> {
+        let containers =
+            sqlx::query_as::<_, Container>("SELECT * FROM containers ORDER BY client_id, name")
+                .fetch_all(&self.pool)
+                .await?;
+
+        Ok(containers)
+    }
+
+    // Process snapshot operations
+    async fn get_process_snapshots(
+        &self,
+        client_id: &str,
+        limit: Option<i64>,
+    ) -> Result<Vec<ProcessSnapshot>> {
+        let snapshots = sqlx::query_as::<_, ProcessSnapshot>(
+            r#"
+            SELECT timestamp, processes FROM process_snapshots
+            WHERE client_id = ?
+            ORDER BY timestamp DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(client_id)
+        .bind(limit.unwrap_or(PROCESS_SNAPSHOTS_KEPT))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(snapshots)
+    }
+
+    // Rollup operations
+    async fn upsert_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        buckets: &BTreeMap<DateTime<Utc>, Vec<(String, Aggregate)>>,
+    ) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+        let mut written = 0;
+
+        for (bucket, aggregates) in buckets {
+            let bucket = bucket.to_rfc3339();
+            for (metric_type, agg) in aggregates {
+                sqlx::query(
+                    r#"
+                    INSERT OR REPLACE INTO metric_rollups
+                        (client_id, resolution, metric_type, bucket, min, max, avg, stddev, p50, p90, p95, p99, count)
+                    VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                    "#,
+                )
+                .bind(client_id)
+                .bind(resolution)
+                .bind(metric_type)
+                .bind(&bucket)
+                .bind(agg.min)
+                .bind(agg.max)
+                .bind(agg.avg)
+                .bind(agg.stddev)
+                .bind(agg.p50)
+                .bind(agg.p90)
+                .bind(agg.p95)
+                .bind(agg.p99)
+                .bind(agg.count)
+                .execute(&mut *tx)
+                .await?;
+            }
+            written += 1;
+        }
+
+        tx.commit().await?;
+        Ok(written)
+    }
+
+    async fn get_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        hours: i64,
+    ) -> Result<Vec<MetricRollup>> {
+        let since = (Utc::now() - Duration::hours(hours)).to_rfc3339();
+
+        let rollups = sqlx::query_as::<_, MetricRollup>(
+            r#"
+            SELECT * FROM metric_rollups
+            WHERE client_id = ? AND resolution = ? AND bucket >= ?
+            ORDER BY bucket DESC, metric_type
+            "#,
+        )
+        .bind(client_id)
+        .bind(resolution)
+        .bind(&since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rollups)
+    }
+
+    async fn get_rollups_between(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        from: &str,
+        to: &str,
+    ) -> Result<Vec<MetricRollup>> {
+        let rollups = sqlx::query_as::<_, MetricRollup>(
+            r#"
+            SELECT * FROM metric_rollups
+            WHERE client_id = ? AND resolution = ? AND bucket >= ? AND bucket < ?
+            ORDER BY bucket, metric_type
+            "#,
+        )
+        .bind(client_id)
+        .bind(resolution)
+        .bind(from)
+        .bind(to)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rollups)
+    }
+
+    async fn sum_rollups(
+        &self,
+        client_id: &str,
+        resolution: i64,
+        since: &str,
+        metric_type: Option<&str>,
+        bucket_sec: Option<i64>,
+    ) -> Result<Vec<RollupSums>> {
+        let sums = sqlx::query_as::<_, RollupSums>(
+            r#"
+            SELECT MIN(bucket) AS bucket, metric_type,
+                MIN(min) AS min, MAX(max) AS max, SUM(count) AS count,
+                SUM(count * avg) AS sum,
+                SUM(count * (stddev * stddev + avg * avg)) AS sum_sq,
+                SUM(count * p50) AS p50_sum, MAX(p90) AS p90_max,
+                MAX(p95) AS p95_max, MAX(p99) AS p99_max
+            FROM metric_rollups
+            WHERE client_id = ? AND resolution = ? AND bucket >= ?
+                AND (? IS NULL OR metric_type = ?)
+            GROUP BY substr(bucket, 1, ?), metric_type
+            ORDER BY MIN(bucket), metric_type
+            "#,
+        )
+        .bind(client_id)
+        .bind(resolution)
+        .bind(since)
+        .bind(metric_type)
+        .bind(metric_type)
+        .bind(rollups::bucket_prefix_len(bucket_sec))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(sums)
+    }
+
+    async fn get_rollup_progress(&self, resolution: i64) -> Result<Option<String>> {
+        let done: Option<String> =
+            sqlx::query_scalar("SELECT completed_until FROM rollup_progress WHERE resolution = ?")
+                .bind(resolution)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(done)
+    }
+
+    async fn get_rollup_metric_id(&self, resolution: i64) -> Result<i64> {
+        let id: Option<i64> =
+            sqlx::query_scalar("SELECT metric_id FROM rollup_progress WHERE resolution = ?")
+                .bind(resolution)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(id.unwrap_or(0))
+    }
+
+    async fn set_rollup_progress(
+        &self,
+        resolution: i64,
+        completed_until: &str,
+        metric_id: i64,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO rollup_progress (resolution, completed_until, metric_id) VALUES (?, ?, ?)
+            ON CONFLICT(resolution) DO UPDATE
+            SET completed_until = excluded.completed_until, metric_id = excluded.metric_id
+            "#,
+        )
+        .bind(resolution)
+        .bind(completed_until)
+        .bind(metric_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_max_metric_id(&self) -> Result<i64> {
+        let id: Option<i64> = sqlx::query_scalar("SELECT MAX(id) FROM metrics")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(id.unwrap_or(0))
+    }
+
+    async fn get_late_metrics(
+        &self,
+        after_id: i64,
+        up_to_id: i64,
+        before: &str,
+    ) -> Result<Vec<(String, String)>> {
+        let late = sqlx::query_as(
+            r#"
+            SELECT DISTINCT client_id, timestamp FROM metrics
+            WHERE id > ? AND id <= ? AND timestamp < ?
+            "#,
+        )
+        .bind(after_id)
+        .bind(up_to_id)
+        .bind(before)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(late)
+    }
+
+    async fn delete_rollups_before(
+        &self,
+        resolution: i64,
+        cutoff: &str,
+        chunk: i64,
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM metric_rollups WHERE rowid IN (
+                SELECT rowid FROM metric_rollups
+                WHERE resolution = ? AND bucket < ?
+                LIMIT ?
+            )
+            "#,
+        )
+        .bind(resolution)
+        .bind(cutoff)
+        .bind(chunk)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    // Alert rule operations
+    async fn get_alert_rules(&self) -> Result<Vec<AlertRule>> {
+        let rules = sqlx::query_as::<_, AlertRule>(SELECT_ALERT_RULES)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rules)
+    }
+
+    async fn get_alert_rule_by_id(&self, id: i64) -> Result<Option<AlertRule>> {
+        let rule = sqlx::query_as::<_, AlertRule>(&format!("{SELECT_ALERT_RULES} WHERE r.id = ?"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(rule)
+    }
+
+    async fn get_alert_rules_for_client(&self, client_id: &str) -> Result<Vec<AlertRule>> {
+        let rules = sqlx::query_as::<_, AlertRule>(&format!(
+            "{SELECT_ALERT_RULES} WHERE r.client_id IS NULL OR r.client_id = ?"
+        ))
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rules)
+    }
+
+    async fn create_alert_rule(&self, rule: &AlertRuleInput) -> Result<AlertRule> {
+        let duration_sec = rule.duration_sec.unwrap_or(30);
+        let result = sqlx::query(
+            "INSERT INTO alert_rules (client_id, metric_type, threshold, duration_sec) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&rule.client_id)
+        .bind(&rule.metric_type)
+        .bind(rule.threshold)
+        .bind(duration_sec)
+        .execute(&self.pool)
+        .await?;
+
+        let id = result.last_insert_rowid();
+        if let Some(channel_ids) = &rule.channel_ids {
+            self.set_rule_channels(id, channel_ids).await?;
+        }
+        if let Some(target) = &rule.target {
+            sqlx::query("INSERT INTO alert_rule_targets (rule_id, target) VALUES (?, ?)")
+                .bind(id)
+                .bind(target)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(AlertRule {
+            id,
+            client_id: rule.client_id.clone(),
+            metric_type: rule.metric_type.clone(),
+            threshold: rule.threshold,
+            duration_sec,
+            target: rule.target.clone(),
+        })
+    }
+
+    async fn delete_alert_rule(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM alert_rules WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Notification channel operations
+    async fn get_notification_channels(&self) -> Result<Vec<NotificationChannel>> {
+        let channels = sqlx::query_as::<_, NotificationChannel>(
+            "SELECT * FROM notification_channels ORDER BY name",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(channels)
+    }
+
+    async fn get_notification_channel(&self, id: i64) -> Result<Option<NotificationChannel>> {
+        let channel = sqlx::query_as::<_, NotificationChannel>(
+            "SELECT * FROM notification_channels WHERE id = ?",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(channel)
+    }
+
+    async fn create_notification_channel(
+        &self,
+        input: &NotificationChannelInput,
+    ) -> Result<NotificationChannel> {
+        let config = input.config.to_string();
+        let enabled = input.enabled.unwrap_or(true);
+        let result = sqlx::query(
+            "INSERT INTO notification_channels (name, kind, config, enabled) VALUES (?, ?, ?, ?)",
+        )
+        .bind(&input.name)
+        .bind(&input.kind)
+        .bind(&config)
+        .bind(enabled)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(NotificationChannel {
+            id: result.last_insert_rowid(),
+            name: input.name.clone(),
+            kind: input.kind.clone(),
+            config,
+            enabled,
+        })
+    }
+
+    async fn delete_notification_channel(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM notification_channels WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_channels_for_rule(&self, rule_id: i64) -> Result<Vec<NotificationChannel>> {
+        let channels = sqlx::query_as::<_, NotificationChannel>(
+            r#"
+            SELECT c.* FROM notification_channels c
+            JOIN alert_rule_channels rc ON rc.channel_id = c.id
+            WHERE rc.rule_id = ? AND c.enabled
+            "#,
+        )
+        .bind(rule_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(channels)
+    }
+
+    async fn get_rule_channel_ids(&self, rule_id: i64) -> Result<Vec<i64>> {
+        let ids: Vec<(i64,)> = sqlx::query_as(
+            "SELECT channel_id FROM alert_rule_channels WHERE rule_id = ? ORDER BY channel_id",
+        )
+        .bind(rule_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+
+    async fn set_rule_channels(&self, rule_id: i64, channel_ids: &[i64]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM alert_rule_channels WHERE rule_id = ?")
+            .bind(rule_id)
+            .execute(&mut *tx)
+            .await?;
+
+        for channel_id in channel_ids {
+            sqlx::query(
+                "INSERT OR IGNORE INTO alert_rule_channels (rule_id, channel_id) VALUES (?, ?)",
+            )
+            .bind(rule_id)
+            .bind(channel_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // API key operations
+    async fn get_api_key_by_hash(&self, key_hash: &str) -> Result<Option<ApiKey>> {
+        let key = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys WHERE key_hash = ?")
+            .bind(key_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(key)
+    }
+
+    async fn get_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let keys = sqlx::query_as::<_, ApiKey>("SELECT * FROM api_keys ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(keys)
+    }
+
+    async fn count_api_keys(&self) -> Result<i64> {
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM api_keys")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    async fn create_api_key(&self, name: &str, key_hash: &str, role: Role) -> Result<ApiKey> {
+        let now = Utc::now().to_rfc3339();
+        let result = sqlx::query(
+            "INSERT INTO api_keys (name, key_hash, role, created_at) VALUES (?, ?, ?, ?)",
+        )
+        .bind(name)
+        .bind(key_hash)
+        .bind(role.as_str())
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(ApiKey {
+            id: result.last_insert_rowid(),
+            name: name.to_string(),
+            key_hash: key_hash.to_string(),
+            role: role.as_str().to_string(),
+            created_at: now,
+        })
+    }
+
+    async fn upsert_api_key(&self, name: &str, key_hash: &str, role: Role) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO api_keys (name, key_hash, role, created_at) VALUES (?, ?, ?, ?)
+            ON CONFLICT(name) DO UPDATE SET key_hash = excluded.key_hash, role = excluded.role
+            "#,
+        )
+        .bind(name)
+        .bind(key_hash)
+        .bind(role.as_str())
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_api_key(&self, id: i64) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Incident operations
+    async fn open_incident(
+        &self,
+        client_id: &str,
+        rule_id: Option<i64>,
+        metric_type: &str,
+        threshold: f64,
+        started_at: &str,
+        value: f64,
+    ) -> Result<Option<i64>> {
+        let existing: Option<(i64,)> = sqlx::query_as(
+            r#"
+            SELECT id FROM incidents
+            WHERE client_id = ? AND rule_id IS ? AND metric_type = ? AND ended_at IS NULL
+            "#,
+        )
+        .bind(client_id)
+        .bind(rule_id)
+        .bind(metric_type)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if existing.is_some() {
+            return Ok(None);
+        }
+
+        let result = sqlx::query(
+            r#"
+            INSERT INTO incidents (client_id, rule_id, metric_type, threshold, started_at, peak_value)
+            VALUES (?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(client_id)
+        .bind(rule_id)
+        .bind(metric_type)
+        .bind(threshold)
+        .bind(started_at)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(Some(result.last_insert_rowid()))
+    }
+
+    async fn resolve_incident(
+        &self,
+        client_id: &str,
+        rule_id: Option<i64>,
+        metric_type: &str,
+        ended_at: &str,
+        peak_value: f64,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE incidents SET ended_at = ?, peak_value = MAX(peak_value, ?)
+            WHERE client_id = ? AND rule_id IS ? AND metric_type = ? AND ended_at IS NULL
+            "#,
+        )
+        .bind(ended_at)
+        .bind(peak_value)
+        .bind(client_id)
+        .bind(rule_id)
+        .bind(metric_type)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_open_incidents(&self, metric_type: &str) -> Result<Vec<Incident>> {
+        let incidents = sqlx::query_as::<_, Incident>(
+            "SELECT * FROM incidents WHERE metric_type = ? AND ended_at IS NULL",
+        )
+        .bind(metric_type)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(incidents)
+    }
+
+    async fn get_incidents(&self, query: &IncidentQuery) -> Result<Vec<Incident>> {
+        let incidents = sqlx::query_as::<_, Incident>(
+            r#"
+            SELECT * FROM incidents
+            WHERE (? IS NULL OR client_id = ?)
+              AND (? IS NULL OR started_at <= ?)
+              AND (? IS NULL OR ended_at IS NULL OR ended_at >= ?)
+            ORDER BY started_at DESC
+            LIMIT ?
+            "#,
+        )
+        .bind(&query.client_id)
+        .bind(&query.client_id)
+        .bind(&query.to)
+        .bind(&query.to)
+        .bind(&query.from)
+        .bind(&query.from)
+        .bind(query.limit.unwrap_or(100))
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(incidents)
+    }
+
+    // Settings operations
+    async fn get_setting(&self, key: &str) -> Result<Option<String>> {
+        let setting = sqlx::query_as::<_, Setting>("SELECT * FROM settings WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(setting.map(|s| s.value))
+    }
+
+    async fn get_all_settings(&self) -> Result<Vec<Setting>> {
+        let settings = sqlx::query_as::<_, Setting>("SELECT * FROM settings")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(settings)
+    }
+
+    async fn set_setting(&self, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO settings (key, value) VALUES (?, ?) ON CONFLICT(key) DO UPDATE SET value = ?",
+        )
+        .bind(key)
+        .bind(value)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_client_settings(&self, client_id: &str) -> Result<Vec<Setting>> {
+        let settings = sqlx::query_as::<_, Setting>(
+            "SELECT key, value FROM client_settings WHERE client_id = ?",
+        )
+        .bind(client_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(settings)
+    }
+
+    async fn get_client_overrides(&self, key: &str) -> Result<Vec<(String, String)>> {
+        let overrides = sqlx::query_as::<_, (String, String)>(
+            "SELECT client_id, value FROM client_settings WHERE key = ?",
+        )
+        .bind(key)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(overrides)
+    }
+
+    async fn set_client_setting(&self, client_id: &str, key: &str, value: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO client_settings (client_id, key, value) VALUES (?, ?, ?)
+            ON CONFLICT(client_id, key) DO UPDATE SET value = excluded.value
+            "#,
+        )
+        .bind(client_id)
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_client_setting(&self, client_id: &str, key: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM client_settings WHERE client_id = ? AND key = ?")
+            .bind(client_id)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // Storage operations
+    async fn get_database_size(&self) -> Result<(i64, i64)> {
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+            .fetch_one(&self.pool)
+            .await?;
+        let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+            .fetch_one(&self.pool)
+            .await?;
+        let free_pages: i64 = sqlx::query_scalar("PRAGMA freelist_count")
+            .fetch_one(&self.pool)
+            .await?;
+        Ok((page_size * page_count, page_size * free_pages))
+    }
+
+    async fn get_client_storage(&self) -> Result<Vec<ClientStorage>> {
+        let storage = sqlx::query_as::<_, ClientStorage>(
+            r#"
+            SELECT
+                c.id AS client_id,
+                c.hostname,
+                (SELECT COUNT(*) FROM metrics m WHERE m.client_id = c.id) AS metric_rows,
+                (SELECT COUNT(*) FROM metric_rollups r WHERE r.client_id = c.id) AS rollup_rows,
+                (SELECT MIN(timestamp) FROM metrics m WHERE m.client_id = c.id) AS oldest_metric,
+                (SELECT MAX(timestamp) FROM metrics m WHERE m.client_id = c.id) AS newest_metric
+            FROM clients c
+            ORDER BY metric_rows DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(storage)
+    }
+
+    async fn vacuum(&self) -> Result<()> {
+        sqlx::query("VACUUM").execute(&self.pool).await?;
+        Ok(())
+    }
+}
+
+/// Insert `rows` with as few multi-row statements as the bind parameter
+/// limit allows; `insert` is everything before `VALUES`
+async fn insert_rows<'a, T, F>(
+    conn: &mut SqliteConnection,
+    insert: &str,
+    columns: usize,
+    rows: &'a [T],
+    mut push_row: F,
+) -> Result<()>
+where
+    T: Sync,
+    F: FnMut(Separated<'_, 'a, Sqlite, &'static str>, &'a T) + Send,
+{
+    for chunk in rows.chunks(BIND_LIMIT / columns) {
+        let mut query = QueryBuilder::new(insert);
+        query.push_values(chunk, &mut push_row);
+        query.build().execute(&mut *conn).await?;
+    }
+
+    Ok(())
+}
+
+/// Insert per-sample JSON detail into one of the `*_metrics` side tables
+async fn insert_detail<T: Serialize + Sync>(
+    conn: &mut SqliteConnection,
+    table: &str,
+    rows: &[(i64, &T)],
+) -> Result<()> {
+    let insert = format!("INSERT INTO {table} (metric_id, detail) ");
+    insert_rows(conn, &insert, 2, rows, |mut row, (id, detail)| {
+        row.push_bind(*id).push_bind(Json(*detail));
+    })
+    .await
+}
+
+/// Replace a client's container list with the one from its latest poll
+async fn replace_containers(
+    conn: &mut SqliteConnection,
+    client_id: &str,
+    containers: &[ContainerStats],
+    updated_at: &str,
+) -> Result<()> {
+    sqlx::query("DELETE FROM containers WHERE client_id = ?")
+        .bind(client_id)
+        .execute(&mut *conn)
+        .await?;
+
+    insert_rows(
+        conn,
+        "INSERT OR REPLACE INTO containers (client_id, container_id, name, image, state, health, restart_count, cpu_percent, memory_used, memory_limit, updated_at) ",
+        11,
+        containers,
+        |mut row, c| {
+            row.push_bind(client_id)
+                .push_bind(&c.id)
+                .push_bind(&c.name)
+                .push_bind(&c.image)
+                .push_bind(&c.state)
+                .push_bind(&c.health)
+                .push_bind(c.restart_count)
+                .push_bind(c.cpu_percent)
+                .push_bind(c.memory_used)
+                .push_bind(c.memory_limit)
+                .push_bind(updated_at);
+        },
+    )
+    .await
+}
+
+/// Store snapshots, keeping only the client's most recent ones
+async fn insert_process_snapshots(
+    conn: &mut SqliteConnection,
+    client_id: &str,
+    snapshots: &[(&String, &Vec<ProcessInfo>)],
+) -> Result<()> {
+    if snapshots.is_empty() {
+        return Ok(());
+    }
+
+    insert_rows(
+        conn,
+        "INSERT INTO process_snapshots (client_id, timestamp, processes) ",
+        3,
+        snapshots,
+        |mut row, (timestamp, processes)| {
+            row.push_bind(client_id)
+                .push_bind(*timestamp)
+                .push_bind(Json(*processes));
+        },
+    )
+    .await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM process_snapshots
+        WHERE client_id = ? AND id NOT IN (
+            SELECT id FROM process_snapshots WHERE client_id = ?
+            ORDER BY timestamp DESC LIMIT ?
+        )
+        "#,
+    )
+    .bind(client_id)
+    .bind(client_id)
+    .bind(PROCESS_SNAPSHOTS_KEPT)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,390 @@
+//! Ingestion of metrics pushed by third-party agents (Prometheus
+//! remote_write, OpenTelemetry OTLP). Known series are folded into frames,
+//! one per scrape, which are then turned into the same `MetricInput`
+//! samples our own agent reports.
+
+mod otlp;
+mod remote_write;
+
+use std::collections::BTreeMap;
+
+use chrono::DateTime;
+
+use crate::models::MetricInput;
+
+pub use otlp::decode_otlp;
+pub use remote_write::decode_remote_write;
+
+/// Points this close together are considered part of the same scrape
+const FRAME_TOLERANCE_MS: i64 = 1000;
+
+/// Free and total amounts; usage is `1 - free / total`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Ratio {
+    pub free: f64,
+    pub total: f64,
+}
+
+impl Ratio {
+    pub fn add(&mut self, value: f64, is_free: bool) {
+        self.total += value;
+        if is_free {
+            self.free += value;
+        }
+    }
+
+    fn used_percent(&self) -> Option<f64> {
+        if self.total > 0.0 {
+            Some(((self.total - self.free) / self.total * 100.0).clamp(0.0, 100.0))
+        } else {
+            None
+        }
+    }
+}
+
+/// Everything known about a host at one scrape
+#[derive(Debug, Default, Clone)]
+pub struct Frame {
+    /// Instantaneous idle vs. all CPU utilization
+    pub cpu_util: Option<Ratio>,
+    /// Cumulative idle vs. all CPU seconds, turned into usage between frames
+    pub cpu_time: Option<Ratio>,
+    pub memory: Option<Ratio>,
+    pub disk: Option<Ratio>,
+    pub inodes: Option<Ratio>,
+    pub gpu: Vec<f64>,
+    /// Points folded into this frame, to tell when all of a scrape has arrived
+    pub points: usize,
+}
+
+impl Frame {
+    pub fn cpu_util_mut(&mut self) -> &mut Ratio {
+        self.cpu_util.get_or_insert_with(Ratio::default)
+    }
+
+    pub fn cpu_time_mut(&mut self) -> &mut Ratio {
+        self.cpu_time.get_or_insert_with(Ratio::default)
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Ratio {
+        self.memory.get_or_insert_with(Ratio::default)
+    }
+
+    pub fn disk_mut(&mut self) -> &mut Ratio {
+        self.disk.get_or_insert_with(Ratio::default)
+    }
+
+    pub fn inodes_mut(&mut self) -> &mut Ratio {
+        self.inodes.get_or_insert_with(Ratio::default)
+    }
+
+    /// Fold in the part of the same scrape that came in another request
+    fn merge(&mut self, other: Frame) {
+        fn add(into: &mut Option<Ratio>, other: Option<Ratio>) {
+            if let Some(other) = other {
+                let ratio = into.get_or_insert_with(Ratio::default);
+                ratio.free += other.free;
+                ratio.total += other.total;
+            }
+        }
+
+        add(&mut self.cpu_util, other.cpu_util);
+        add(&mut self.cpu_time, other.cpu_time);
+        add(&mut self.memory, other.memory);
+        add(&mut self.disk, other.disk);
+        add(&mut self.inodes, other.inodes);
+        self.gpu.extend(other.gpu);
+        self.points += other.points;
+    }
+}
+
+/// Frames keyed by timestamp in milliseconds
+#[derive(Debug, Default, Clone)]
+pub struct Frames(BTreeMap<i64, Frame>);
+
+impl Frames {
+    /// Frame for a point, reusing one from the same scrape if there is one
+    pub fn at(&mut self, timestamp_ms: i64) -> &mut Frame {
+        let frame = self.slot(timestamp_ms);
+        frame.points += 1;
+        frame
+    }
+
+    fn slot(&mut self, timestamp_ms: i64) -> &mut Frame {
+        let key = self.key(timestamp_ms).unwrap_or(timestamp_ms);
+        self.0.entry(key).or_default()
+    }
+
+    /// Key of the frame from the same scrape as `timestamp_ms`, if any
+    fn key(&self, timestamp_ms: i64) -> Option<i64> {
+        self.0
+            .range(timestamp_ms - FRAME_TOLERANCE_MS..=timestamp_ms + FRAME_TOLERANCE_MS)
+            .map(|(k, _)| *k)
+            .min_by_key(|k| (k - timestamp_ms).abs())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Per-client state carried between pushes
+#[derive(Debug, Default, Clone)]
+pub struct IngestState {
+    last_cpu_time: Option<Ratio>,
+    last: Option<MetricInput>,
+    /// Frames whose scrape may still be arriving in other requests
+    pending: Frames,
+    /// Timestamp and point count of the last frame turned into a sample
+    last_frame: Option<(i64, usize)>,
+}
+
+impl IngestState {
+    /// Fold a push into the pending frames and turn the finished ones into
+    /// samples. Prometheus splits a scrape's series across concurrent
+    /// requests, so a frame is only finished once it has as many points as
+    /// the previous one, or once a later request brings a newer timestamp.
+    pub fn samples_from(&mut self, frames: Frames) -> Vec<MetricInput> {
+        let newest = frames.0.keys().next_back().copied();
+        let overdue: Vec<i64> = self
+            .pending
+            .0
+            .keys()
+            .copied()
+            .filter(|&k| newest.is_some_and(|n| n > k + FRAME_TOLERANCE_MS))
+            .collect();
+
+        for (timestamp_ms, frame) in frames.0 {
+            // Stragglers of a scrape that was already turned into a sample
+            if self
+                .last_frame
+                .is_some_and(|(last, _)| timestamp_ms <= last + FRAME_TOLERANCE_MS)
+            {
+                continue;
+            }
+            self.pending.slot(timestamp_ms).merge(frame);
+        }
+
+        let mut finished = vec![];
+        while let Some((&timestamp_ms, frame)) = self.pending.0.first_key_value() {
+            let complete = self
+                .last_frame
+                .is_some_and(|(_, points)| frame.points >= points);
+            if !complete && !overdue.contains(&timestamp_ms) {
+                break;
+            }
+            let frame = self.pending.0.remove(&timestamp_ms).expect("first key");
+            self.last_frame = Some((timestamp_ms, frame.points));
+            finished.push((timestamp_ms, frame));
+        }
+
+        self.samples_from_frames(finished)
+    }
+
+    /// Turn finished frames into samples. Fields a frame lacks are carried
+    /// over from the previous sample (or 0); frames are dropped until both
+    /// CPU and memory usage are known, since CPU counters need two scrapes.
+    fn samples_from_frames(&mut self, frames: Vec<(i64, Frame)>) -> Vec<MetricInput> {
+        let mut metrics = vec![];
+
+        for (timestamp_ms, frame) in frames {
+            let cpu_from_time = frame.cpu_time.and_then(|now| {
+                let prev = self.last_cpu_time.replace(now)?;
+                let delta = Ratio {
+                    free: now.free - prev.free,
+                    total: now.total - prev.total,
+                };
+                // Counter reset (host reboot) or no time passed
+                if delta.total <= 0.0 || delta.free < 0.0 {
+                    return None;
+                }
+                delta.used_percent()
+            });
+            let cpu = frame
+                .cpu_util
+                .and_then(|r| r.used_percent())
+                .or(cpu_from_time);
+
+            let last = self.last.as_ref();
+            let (cpu, ram) = match (
+                cpu.or(last.map(|m| m.cpu_usage)),
+                frame
+                    .memory
+                    .and_then(|r| r.used_percent())
+                    .or(last.map(|m| m.ram_usage)),
+            ) {
+                (Some(cpu), Some(ram)) => (cpu, ram),
+                _ => continue,
+            };
+
+            let timestamp = match DateTime::from_timestamp_millis(timestamp_ms) {
+                Some(t) => t.to_rfc3339(),
+                None => continue,
+            };
+
+            let metric = MetricInput {
+                cpu_usage: cpu,
+                ram_usage: ram,
+                disk_usage: frame
+                    .disk
+                    .and_then(|r| r.used_percent())
+                    .or(last.map(|m| m.disk_usage))
+                    .unwrap_or(0.0),
+                inode_usage: frame
+                    .inodes
+                    .and_then(|r| r.used_percent())
+                    .or(last.map(|m| m.inode_usage))
+                    .unwrap_or(0.0),
+                docker_sz: None,
+                gpu_usage: if frame.gpu.is_empty() {
+                    last.and_then(|m| m.gpu_usage)
+                } else {
+                    Some(frame.gpu.iter().sum::<f64>() / frame.gpu.len() as f64)
+                },
+                cpu: None,
+                memory: None,
+                gpus: vec![],
+                filesystems: vec![],
+                network: vec![],
+                disk_io: vec![],
+                docker: None,
+                containers: None,
+                systemd: None,
+                sensors: None,
+                values: vec![],
+                processes: None,
+                timestamp,
+            };
+
+            self.last = Some(metric.clone());
+            metrics.push(metric);
+        }
+
+        metrics
+    }
+}
+
+/// Pick which of the hosts found in a push belongs to this client. A push
+/// from a single host is taken as is; one mixing several hosts (a shared
+/// Prometheus or collector) only contributes the host named like the client.
+pub fn select_host<'a>(
+    hosts: impl IntoIterator<Item = &'a str>,
+    hostname: &str,
+) -> Option<&'a str> {
+    let mut hosts: Vec<&str> = hosts.into_iter().collect();
+    hosts.sort_unstable();
+    hosts.dedup();
+
+    match hosts.as_slice() {
+        [only] => Some(only),
+        _ => hosts.into_iter().find(|h| {
+            let host = h.rsplit_once(':').map(|(host, _)| host).unwrap_or(h);
+            host.eq_ignore_ascii_case(hostname)
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(cpu_time: (f64, f64), mem_free: f64) -> Frame {
+        Frame {
+            points: 3,
+            cpu_time: Some(Ratio {
+                free: cpu_time.0,
+                total: cpu_time.1,
+            }),
+            memory: Some(Ratio {
+                free: mem_free,
+                total: 100.0,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cpu_counters_need_two_frames() {
+        let mut state = IngestState::default();
+        let mut frames = Frames::default();
+        *frames.at(1_700_000_000_000) = frame((100.0, 200.0), 75.0);
+        *frames.at(1_700_000_015_000) = frame((109.0, 212.0), 60.0);
+
+        // Nothing tells yet whether the first push's frames are complete
+        assert!(state.samples_from(frames).is_empty());
+
+        // A later push with a newer scrape finishes them
+        let mut frames = Frames::default();
+        *frames.at(1_700_000_030_000) = frame((118.0, 224.0), 60.0);
+        let metrics = state.samples_from(frames);
+        assert_eq!(metrics.len(), 2);
+        assert_eq!(metrics[0].cpu_usage, 25.0);
+        assert_eq!(metrics[0].ram_usage, 40.0);
+        assert_eq!(metrics[0].disk_usage, 0.0);
+        assert_eq!(metrics[0].timestamp, "2023-11-14T22:13:35+00:00");
+
+        // From then on a frame with as many points as the last is complete,
+        // and counters carry over between pushes
+        let mut frames = Frames::default();
+        *frames.at(1_700_000_045_000) = frame((127.0, 236.0), 60.0);
+        let metrics = state.samples_from(frames);
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].cpu_usage, 25.0);
+    }
+
+    #[test]
+    fn test_incomplete_frame_waits_for_the_rest() {
+        let mut state = IngestState::default();
+        let mut frames = Frames::default();
+        *frames.at(1_700_000_000_000) = frame((100.0, 200.0), 75.0);
+        state.samples_from(frames);
+        let mut frames = Frames::default();
+        *frames.at(1_700_000_015_000) = frame((109.0, 212.0), 60.0);
+        state.samples_from(frames);
+
+        // Half of the next scrape is held back until the other half arrives
+        let mut half = frame((59.0, 112.0), 60.0);
+        half.points = 2;
+        let mut frames = Frames::default();
+        *frames.at(1_700_000_030_000) = half.clone();
+        assert!(state.samples_from(frames).is_empty());
+
+        let mut frames = Frames::default();
+        *frames.at(1_700_000_030_200) = Frame {
+            memory: None,
+            points: 1,
+            ..frame((59.0, 112.0), 0.0)
+        };
+        let metrics = state.samples_from(frames);
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].cpu_usage, 25.0);
+
+        // Parts of a scrape that was already turned into a sample are dropped
+        let mut frames = Frames::default();
+        *frames.at(1_700_000_030_000) = half;
+        assert!(state.samples_from(frames).is_empty());
+    }
+
+    #[test]
+    fn test_points_of_one_scrape_share_a_frame() {
+        let mut frames = Frames::default();
+        frames.at(1_000_000).gpu.push(10.0);
+        frames.at(1_000_400).gpu.push(30.0);
+        frames.at(1_015_000).gpu.push(50.0);
+        assert_eq!(frames.0.len(), 2);
+        assert_eq!(frames.0[&1_000_000].gpu, vec![10.0, 30.0]);
+    }
+
+    #[test]
+    fn test_select_host() {
+        assert_eq!(
+            select_host(["10.0.0.5:9100"], "web-1"),
+            Some("10.0.0.5:9100")
+        );
+        assert_eq!(
+            select_host(["db-1:9100", "web-1:9100", "db-1:9100"], "WEB-1"),
+            Some("web-1:9100")
+        );
+        assert_eq!(select_host(["db-1:9100", "db-2:9100"], "web-1"), None);
+    }
+}
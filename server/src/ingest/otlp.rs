@@ -0,0 +1,273 @@
+//! OTLP/HTTP metrics (protobuf `ExportMetricsServiceRequest`), mapping the
+//! collector's hostmetrics receiver series.
+
+use anyhow::Result;
+use prost::Message;
+
+use super::{select_host, Frames};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ExportMetricsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ResourceMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    pub attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ScopeMetrics {
+    #[prost(message, repeated, tag = "2")]
+    pub metrics: Vec<Metric>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Metric {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(message, optional, tag = "5")]
+    pub gauge: Option<Gauge>,
+    #[prost(message, optional, tag = "7")]
+    pub sum: Option<Gauge>,
+}
+
+/// Gauge, or Sum with its temporality fields left out: both start with
+/// their data points
+#[derive(Clone, PartialEq, Message)]
+pub struct Gauge {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct NumberDataPoint {
+    #[prost(message, repeated, tag = "7")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(fixed64, tag = "3")]
+    pub time_unix_nano: u64,
+    #[prost(oneof = "NumberValue", tags = "4, 6")]
+    pub value: Option<NumberValue>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum NumberValue {
+    #[prost(double, tag = "4")]
+    AsDouble(f64),
+    #[prost(sfixed64, tag = "6")]
+    AsInt(i64),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AnyValue {
+    #[prost(oneof = "AnyValueKind", tags = "1")]
+    pub value: Option<AnyValueKind>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum AnyValueKind {
+    #[prost(string, tag = "1")]
+    StringValue(String),
+}
+
+fn attribute<'a>(attributes: &'a [KeyValue], key: &str) -> Option<&'a str> {
+    attributes.iter().find(|kv| kv.key == key).and_then(|kv| {
+        match kv.value.as_ref()?.value.as_ref()? {
+            AnyValueKind::StringValue(s) => Some(s.as_str()),
+        }
+    })
+}
+
+impl NumberDataPoint {
+    fn as_f64(&self) -> Option<f64> {
+        match self.value.as_ref()? {
+            NumberValue::AsDouble(v) => Some(*v),
+            NumberValue::AsInt(v) => Some(*v as f64),
+        }
+    }
+}
+
+fn host_name(resource: &ResourceMetrics) -> Option<&str> {
+    resource
+        .resource
+        .as_ref()
+        .and_then(|r| attribute(&r.attributes, "host.name"))
+}
+
+pub fn decode_otlp(body: &[u8], hostname: &str) -> Result<Frames> {
+    let request = ExportMetricsServiceRequest::decode(body)?;
+    Ok(map_metrics(&request, hostname))
+}
+
+fn map_metrics(request: &ExportMetricsServiceRequest, hostname: &str) -> Frames {
+    let host = select_host(
+        request.resource_metrics.iter().filter_map(host_name),
+        hostname,
+    );
+    let mut frames = Frames::default();
+
+    for resource in &request.resource_metrics {
+        if let Some(name) = host_name(resource) {
+            if Some(name) != host {
+                continue;
+            }
+        }
+
+        let metrics = resource.scope_metrics.iter().flat_map(|s| &s.metrics);
+        for metric in metrics {
+            let points = match metric.gauge.as_ref().or(metric.sum.as_ref()) {
+                Some(data) => &data.data_points,
+                None => continue,
+            };
+
+            for point in points {
+                let value = match point.as_f64() {
+                    Some(v) if !v.is_nan() => v,
+                    _ => continue,
+                };
+                let at = (point.time_unix_nano / 1_000_000) as i64;
+                let state = attribute(&point.attributes, "state").unwrap_or_default();
+                let root_fs = attribute(&point.attributes, "mountpoint") == Some("/");
+
+                match metric.name.as_str() {
+                    "system.cpu.time" => frames
+                        .at(at)
+                        .cpu_time_mut()
+                        .add(value, matches!(state, "idle" | "wait")),
+                    "system.cpu.utilization" => frames
+                        .at(at)
+                        .cpu_util_mut()
+                        .add(value, matches!(state, "idle" | "wait")),
+                    "system.memory.usage" | "system.memory.utilization" => {
+                        frames.at(at).memory_mut().add(
+                            value,
+                            matches!(state, "free" | "buffered" | "cached" | "slab_reclaimable"),
+                        )
+                    }
+                    "system.filesystem.usage" if root_fs => {
+                        frames.at(at).disk_mut().add(value, state == "free")
+                    }
+                    "system.filesystem.inodes.usage" if root_fs => {
+                        frames.at(at).inodes_mut().add(value, state == "free")
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::IngestState;
+
+    fn kv(key: &str, value: &str) -> KeyValue {
+        KeyValue {
+            key: key.to_string(),
+            value: Some(AnyValue {
+                value: Some(AnyValueKind::StringValue(value.to_string())),
+            }),
+        }
+    }
+
+    fn point(attributes: &[(&str, &str)], value: f64) -> NumberDataPoint {
+        NumberDataPoint {
+            attributes: attributes.iter().map(|(k, v)| kv(k, v)).collect(),
+            time_unix_nano: 1_700_000_000_000_000_000,
+            value: Some(NumberValue::AsDouble(value)),
+        }
+    }
+
+    fn gauge(name: &str, data_points: Vec<NumberDataPoint>) -> Metric {
+        Metric {
+            name: name.to_string(),
+            gauge: Some(Gauge { data_points }),
+            sum: None,
+        }
+    }
+
+    fn sum(name: &str, data_points: Vec<NumberDataPoint>) -> Metric {
+        Metric {
+            name: name.to_string(),
+            gauge: None,
+            sum: Some(Gauge { data_points }),
+        }
+    }
+
+    #[test]
+    fn test_decode_hostmetrics() {
+        let request = ExportMetricsServiceRequest {
+            resource_metrics: vec![ResourceMetrics {
+                resource: Some(Resource {
+                    attributes: vec![kv("host.name", "web-1")],
+                }),
+                scope_metrics: vec![ScopeMetrics {
+                    metrics: vec![
+                        gauge(
+                            "system.cpu.utilization",
+                            vec![
+                                point(&[("cpu", "cpu0"), ("state", "idle")], 0.5),
+                                point(&[("cpu", "cpu0"), ("state", "user")], 0.5),
+                                point(&[("cpu", "cpu1"), ("state", "idle")], 0.3),
+                                point(&[("cpu", "cpu1"), ("state", "system")], 0.7),
+                            ],
+                        ),
+                        sum(
+                            "system.memory.usage",
+                            vec![
+                                point(&[("state", "used")], 600.0),
+                                point(&[("state", "cached")], 300.0),
+                                point(&[("state", "free")], 100.0),
+                            ],
+                        ),
+                        sum(
+                            "system.filesystem.usage",
+                            vec![
+                                point(&[("mountpoint", "/"), ("state", "used")], 20.0),
+                                point(&[("mountpoint", "/"), ("state", "free")], 80.0),
+                                point(&[("mountpoint", "/data"), ("state", "free")], 1.0),
+                            ],
+                        ),
+                    ],
+                }],
+            }],
+        };
+
+        let body = request.encode_to_vec();
+        let frames = decode_otlp(&body, "web-1").unwrap();
+        let mut state = IngestState::default();
+        assert!(state.samples_from(frames).is_empty());
+
+        // The next scrape tells the pushed one is complete
+        let mut next = Frames::default();
+        next.at(1_700_000_015_000);
+        let metrics = state.samples_from(next);
+
+        assert_eq!(metrics.len(), 1);
+        assert!((metrics[0].cpu_usage - 60.0).abs() < 1e-9);
+        assert_eq!(metrics[0].ram_usage, 60.0);
+        assert_eq!(metrics[0].disk_usage, 20.0);
+        assert_eq!(metrics[0].timestamp, "2023-11-14T22:13:20+00:00");
+    }
+}
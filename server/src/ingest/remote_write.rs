@@ -0,0 +1,287 @@
+//! Prometheus remote_write (snappy-compressed protobuf `WriteRequest`),
+//! mapping node_exporter and DCGM series.
+
+use anyhow::Result;
+use prost::Message;
+
+use super::{select_host, Frames};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    /// Milliseconds since the epoch
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
+
+impl TimeSeries {
+    fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|l| l.name == name)
+            .map(|l| l.value.as_str())
+    }
+}
+
+pub fn decode_remote_write(body: &[u8], hostname: &str) -> Result<Frames> {
+    let raw = snap::raw::Decoder::new().decompress_vec(body)?;
+    let request = WriteRequest::decode(raw.as_slice())?;
+    Ok(map_series(&request, hostname))
+}
+
+fn map_series(request: &WriteRequest, hostname: &str) -> Frames {
+    let host = select_host(
+        request
+            .timeseries
+            .iter()
+            .filter_map(|s| s.label("instance")),
+        hostname,
+    );
+    let mut frames = Frames::default();
+
+    for series in &request.timeseries {
+        if let Some(instance) = series.label("instance") {
+            if Some(instance) != host {
+                continue;
+            }
+        }
+
+        let name = series.label("__name__").unwrap_or_default();
+        let root_fs = series.label("mountpoint") == Some("/");
+
+        for sample in &series.samples {
+            let (at, value) = (sample.timestamp, sample.value);
+            if value.is_nan() {
+                continue;
+            }
+
+            match name {
+                "node_cpu_seconds_total" => {
+                    let idle = matches!(series.label("mode"), Some("idle" | "iowait"));
+                    frames.at(at).cpu_time_mut().add(value, idle);
+                }
+                "node_memory_MemTotal_bytes" => {
+                    frames.at(at).memory_mut().total += value;
+                }
+                "node_memory_MemAvailable_bytes" => {
+                    frames.at(at).memory_mut().free += value;
+                }
+                "node_filesystem_size_bytes" if root_fs => {
+                    frames.at(at).disk_mut().total += value;
+                }
+                "node_filesystem_avail_bytes" if root_fs => {
+                    frames.at(at).disk_mut().free += value;
+                }
+                "node_filesystem_files" if root_fs => {
+                    frames.at(at).inodes_mut().total += value;
+                }
+                "node_filesystem_files_free" if root_fs => {
+                    frames.at(at).inodes_mut().free += value;
+                }
+                "DCGM_FI_DEV_GPU_UTIL" => frames.at(at).gpu.push(value),
+                "nvidia_smi_utilization_gpu_ratio" => frames.at(at).gpu.push(value * 100.0),
+                _ => {}
+            }
+        }
+    }
+
+    frames
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingest::IngestState;
+
+    fn series(labels: &[(&str, &str)], samples: &[(i64, f64)]) -> TimeSeries {
+        TimeSeries {
+            labels: labels
+                .iter()
+                .map(|(name, value)| Label {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            samples: samples
+                .iter()
+                .map(|(timestamp, value)| Sample {
+                    value: *value,
+                    timestamp: *timestamp,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_decode_node_exporter_series() {
+        let request = WriteRequest {
+            timeseries: vec![
+                series(
+                    &[
+                        ("__name__", "node_cpu_seconds_total"),
+                        ("instance", "web-1:9100"),
+                        ("cpu", "0"),
+                        ("mode", "idle"),
+                    ],
+                    &[(1_700_000_000_000, 100.0), (1_700_000_015_000, 106.0)],
+                ),
+                series(
+                    &[
+                        ("__name__", "node_cpu_seconds_total"),
+                        ("instance", "web-1:9100"),
+                        ("cpu", "0"),
+                        ("mode", "user"),
+                    ],
+                    &[(1_700_000_000_000, 50.0), (1_700_000_015_000, 59.0)],
+                ),
+                series(
+                    &[
+                        ("__name__", "node_memory_MemTotal_bytes"),
+                        ("instance", "web-1:9100"),
+                    ],
+                    &[(1_700_000_000_000, 8e9), (1_700_000_015_000, 8e9)],
+                ),
+                series(
+                    &[
+                        ("__name__", "node_memory_MemAvailable_bytes"),
+                        ("instance", "web-1:9100"),
+                    ],
+                    &[(1_700_000_000_000, 6e9), (1_700_000_015_000, 2e9)],
+                ),
+                series(
+                    &[
+                        ("__name__", "node_filesystem_size_bytes"),
+                        ("instance", "web-1:9100"),
+                        ("mountpoint", "/"),
+                    ],
+                    &[(1_700_000_015_000, 100.0)],
+                ),
+                series(
+                    &[
+                        ("__name__", "node_filesystem_avail_bytes"),
+                        ("instance", "web-1:9100"),
+                        ("mountpoint", "/"),
+                    ],
+                    &[(1_700_000_015_000, 90.0)],
+                ),
+                series(
+                    &[
+                        ("__name__", "node_filesystem_avail_bytes"),
+                        ("instance", "web-1:9100"),
+                        ("mountpoint", "/boot"),
+                    ],
+                    &[(1_700_000_015_000, 1.0)],
+                ),
+                // Another host scraped by the same Prometheus
+                series(
+                    &[
+                        ("__name__", "node_memory_MemTotal_bytes"),
+                        ("instance", "db-1:9100"),
+                    ],
+                    &[(1_700_000_015_000, 1.0)],
+                ),
+            ],
+        };
+
+        let mut body = vec![];
+        request.encode(&mut body).unwrap();
+        let compressed = snap::raw::Encoder::new().compress_vec(&body).unwrap();
+
+        let frames = decode_remote_write(&compressed, "web-1").unwrap();
+        let mut state = IngestState::default();
+        assert!(state.samples_from(frames).is_empty());
+
+        // The next scrape tells the pushed one is complete
+        let mut next = Frames::default();
+        next.at(1_700_000_030_000);
+        let metrics = state.samples_from(next);
+
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].cpu_usage, 60.0);
+        assert_eq!(metrics[0].ram_usage, 75.0);
+        assert_eq!(metrics[0].disk_usage, 10.0);
+    }
+
+    #[test]
+    fn test_scrape_split_across_requests() {
+        let cpu = |cpu: &'static str, mode: &'static str, values: [f64; 3]| {
+            series(
+                &[
+                    ("__name__", "node_cpu_seconds_total"),
+                    ("instance", "web-1:9100"),
+                    ("cpu", cpu),
+                    ("mode", mode),
+                ],
+                &[
+                    (1_700_000_000_000, values[0]),
+                    (1_700_000_015_000, values[1]),
+                    (1_700_000_030_000, values[2]),
+                ],
+            )
+        };
+        let memory = |name: &'static str, value: f64| {
+            series(
+                &[("__name__", name), ("instance", "web-1:9100")],
+                &[
+                    (1_700_000_000_000, value),
+                    (1_700_000_015_000, value),
+                    (1_700_000_030_000, value),
+                ],
+            )
+        };
+        // Each request holds half of every scrape, as remote_write shards do
+        let first = WriteRequest {
+            timeseries: vec![
+                cpu("0", "idle", [100.0, 106.0, 112.0]),
+                cpu("0", "user", [50.0, 59.0, 68.0]),
+                memory("node_memory_MemTotal_bytes", 8e9),
+            ],
+        };
+        let second = WriteRequest {
+            timeseries: vec![
+                cpu("1", "idle", [200.0, 203.0, 206.0]),
+                cpu("1", "user", [20.0, 32.0, 44.0]),
+                memory("node_memory_MemAvailable_bytes", 2e9),
+            ],
+        };
+
+        let mut state = IngestState::default();
+        assert!(state.samples_from(map_series(&first, "web-1")).is_empty());
+        let metrics = state.samples_from(map_series(&second, "web-1"));
+
+        // Across both CPUs 9 of 30 seconds were idle
+        assert_eq!(metrics.len(), 2);
+        for metric in &metrics {
+            assert_eq!(metric.cpu_usage, 70.0);
+            assert_eq!(metric.ram_usage, 75.0);
+        }
+    }
+
+    #[test]
+    fn test_rejects_uncompressed_body() {
+        assert!(decode_remote_write(b"not snappy", "web-1").is_err());
+    }
+}
@@ -1,31 +1,49 @@
+mod alerts;
+mod auth;
 mod db;
+mod ingest;
+mod migrations;
 mod models;
+mod notify;
+mod retention;
+mod rollups;
 mod routes;
 mod services;
+mod telemetry;
 
-use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Instant};
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 
 use axum::{
-    routing::{delete, get, post},
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::{broadcast, mpsc, Mutex};
 use tower_http::{
     cors::{Any, CorsLayer},
+    decompression::RequestDecompressionLayer,
     trace::TraceLayer,
 };
 use tracing::info;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-use crate::models::AlertRule;
+use crate::{
+    alerts::{AlertEvent, AlertTracker},
+    ingest::IngestState,
+    models::Metric,
+    telemetry::Telemetry,
+};
 
 pub type DbPool = db::DbPool;
 
 #[derive(Clone)]
 pub struct AppState {
     pub db: DbPool,
-    pub alert_tx: mpsc::Sender<(String, AlertRule, f64)>,
-    pub alert_debounce: Arc<Mutex<HashMap<String, Instant>>>,
+    pub alert_tx: mpsc::Sender<AlertEvent>,
+    pub alert_states: Arc<Mutex<HashMap<(String, i64), AlertTracker>>>,
+    pub live_tx: broadcast::Sender<Metric>,
+    pub telemetry: Arc<Telemetry>,
+    pub ingest_states: Arc<Mutex<HashMap<String, Arc<Mutex<IngestState>>>>>,
 }
 
 #[tokio::main]
@@ -45,44 +63,118 @@ async fn main() -> anyhow::Result<()> {
     // Database setup
     let database_url =
         std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:data/monitor.db".to_string());
-    let pool = db::init_db(&database_url).await?;
-    info!("Database initialized at {}", database_url);
+
+    // `server migrate` upgrades the schema and exits
+    match std::env::args().nth(1).as_deref() {
+        Some("migrate") => return migrate(&database_url).await,
+        Some(other) => anyhow::bail!("unknown command `{}`; usage: server [migrate]", other),
+        None => {}
+    }
+
+    // With AUTO_MIGRATE=false upgrades only happen through `server migrate`
+    let pool = if std::env::var("AUTO_MIGRATE").as_deref() == Ok("false") {
+        let pool = db::connect(&database_url).await?;
+        let pending = migrations::pending(&pool).await?;
+        if !pending.is_empty() {
+            anyhow::bail!(
+                "{} pending migrations; run `server migrate` first",
+                pending.len()
+            );
+        }
+        pool
+    } else {
+        db::init_db(&database_url).await?
+    };
+    info!("Database initialized at {}", db::redact_url(&database_url));
+    auth::bootstrap_admin_key(&pool).await?;
 
     // Create alert channel
-    let (alert_tx, alert_rx) = mpsc::channel::<(String, AlertRule, f64)>(100);
+    let (alert_tx, alert_rx) = mpsc::channel::<AlertEvent>(100);
+    let offline_alert_tx = alert_tx.clone();
+
+    // Create live feed channel for WebSocket subscribers
+    let (live_tx, _) = broadcast::channel::<Metric>(1024);
 
     // Create app state
     let state = AppState {
         db: pool.clone(),
         alert_tx,
-        alert_debounce: Arc::new(Mutex::new(HashMap::new())),
+        alert_states: Arc::new(Mutex::new(HashMap::new())),
+        live_tx,
+        telemetry: Arc::new(Telemetry::default()),
+        ingest_states: Arc::new(Mutex::new(HashMap::new())),
     };
 
     // Start background services
     tokio::spawn(services::start_cleanup_task(pool.clone()));
+    tokio::spawn(services::start_rollup_task(pool.clone()));
+    tokio::spawn(services::start_offline_monitor(pool.clone(), offline_alert_tx));
     tokio::spawn(services::start_alert_worker(pool.clone(), alert_rx));
 
-    // Build router
-    let app = Router::new()
+    // Management API, guarded by admin/read-only API keys
+    let api = Router::new()
         // Client management
         .route("/api/clients", get(routes::clients::list_clients))
         .route("/api/clients", post(routes::clients::create_client))
-        .route("/api/clients/{id}", get(routes::clients::get_client))
-        .route("/api/clients/{id}", delete(routes::clients::delete_client))
+        .route("/api/clients/:id", get(routes::clients::get_client))
+        .route("/api/clients/:id", delete(routes::clients::delete_client))
+        .route("/api/clients/:id/containers", get(routes::clients::get_containers))
+        .route("/api/clients/:id/processes", get(routes::clients::get_processes))
+        .route("/api/clients/:id/settings", get(routes::settings::get_client_settings))
+        .route("/api/clients/:id/settings", post(routes::settings::update_client_settings))
         // Metrics
-        .route("/api/report", post(routes::metrics::report_metrics))
-        .route("/api/metrics/{id}", get(routes::metrics::get_metrics))
-        .route("/api/metrics/{id}/latest", get(routes::metrics::get_latest_metrics))
-        .route("/api/stats/{id}", get(routes::metrics::get_stats))
+        .route("/api/metrics/:id", get(routes::metrics::get_metrics))
+        .route("/api/metrics/:id/latest", get(routes::metrics::get_latest_metrics))
+        .route("/api/metrics/:id/rollups", get(routes::metrics::get_rollups))
+        .route("/api/metrics/:id/gpus", get(routes::metrics::get_gpu_metrics))
+        .route("/api/metrics/:id/filesystems", get(routes::metrics::get_filesystem_metrics))
+        .route("/api/metrics/:id/network", get(routes::metrics::get_network_metrics))
+        .route("/api/metrics/:id/disk-io", get(routes::metrics::get_disk_io_metrics))
+        .route("/api/metrics/:id/series", get(routes::metrics::get_series))
+        .route("/api/stats/:id", get(routes::metrics::get_stats))
         // Settings & Alert Rules
         .route("/api/settings", get(routes::settings::get_settings))
         .route("/api/settings", post(routes::settings::update_settings))
         .route("/api/alerts", get(routes::settings::get_alert_rules))
         .route("/api/alerts", post(routes::settings::create_alert_rule))
-        .route("/api/alerts/{id}", delete(routes::settings::delete_alert_rule))
+        .route("/api/alerts/:id", delete(routes::settings::delete_alert_rule))
+        .route("/api/alerts/:id/channels", get(routes::channels::get_rule_channels))
+        .route("/api/alerts/:id/channels", put(routes::channels::set_rule_channels))
+        // Notification channels
+        .route("/api/channels", get(routes::channels::list_channels))
+        .route("/api/channels", post(routes::channels::create_channel))
+        .route("/api/channels/:id", delete(routes::channels::delete_channel))
+        .route("/api/channels/:id/test", post(routes::channels::test_channel))
+        // Incidents
+        .route("/api/incidents", get(routes::incidents::list_incidents))
+        // API keys
+        .route("/api/auth/me", get(routes::keys::whoami))
+        .route("/api/keys", get(routes::keys::list_keys))
+        .route("/api/keys", post(routes::keys::create_key))
+        .route("/api/keys/:id", delete(routes::keys::delete_key))
+        // Storage administration
+        .route("/api/admin/storage", get(routes::admin::get_storage))
+        .route("/api/admin/cleanup", post(routes::admin::run_cleanup))
+        // Prometheus scrape target
+        .route("/metrics", get(routes::prometheus::metrics))
+        // Live feed
+        .route("/ws/live", get(routes::live::live_feed))
+        .route_layer(middleware::from_fn_with_state(state.clone(), routes::auth_middleware));
+
+    // Build router
+    let app = Router::new()
+        .route("/api/health", get(routes::health))
+        // Metric ingestion, authenticated per client token
+        .route("/api/report", post(routes::metrics::report_metrics))
+        .route("/api/v1/write", post(routes::ingest::remote_write))
+        .route(
+            "/v1/metrics",
+            post(routes::ingest::otlp_metrics).layer(RequestDecompressionLayer::new()),
+        )
+        .merge(api)
         // Middleware
         .layer(CorsLayer::new().allow_origin(Any).allow_methods(Any).allow_headers(Any))
-        .layer(TraceLayer::new_for_http())
+        .layer(TraceLayer::new_for_http().make_span_with(routes::request_span))
         .with_state(state);
 
     // Start server
@@ -94,3 +186,26 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Apply pending migrations and report the resulting schema version
+async fn migrate(database_url: &str) -> anyhow::Result<()> {
+    let pool = db::connect(database_url).await?;
+    let from = migrations::current_version(&pool).await?;
+
+    if migrations::run(&pool).await?.is_empty() {
+        info!(
+            "Database {} is up to date at version {}",
+            db::redact_url(database_url),
+            from
+        );
+    } else {
+        info!(
+            "Migrated {} from version {} to {}",
+            db::redact_url(database_url),
+            from,
+            migrations::latest_version(&pool)
+        );
+    }
+
+    Ok(())
+}
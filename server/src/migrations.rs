@@ -0,0 +1,197 @@
+//! Versioned schema migrations. Each backend has its own files under
+//! `migrations/<backend>/`; each file is applied once, in its own
+//! transaction, and recorded in `schema_migrations`.
+
+use anyhow::{bail, Context, Result};
+use tracing::info;
+
+use crate::DbPool;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+macro_rules! migration {
+    ($backend:literal, $version:literal, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../migrations/", $backend, "/", $name, ".sql")),
+        }
+    };
+}
+
+/// Every SQLite migration, oldest first. Versions are the file name prefixes
+/// and must keep increasing; never edit a migration once it has shipped.
+pub const SQLITE: &[Migration] = &[
+    migration!("sqlite", 1, "001_initial_schema"),
+    migration!("sqlite", 2, "002_incidents"),
+    migration!("sqlite", 3, "003_notification_channels"),
+    migration!("sqlite", 4, "004_api_keys"),
+    migration!("sqlite", 5, "005_rollups"),
+    migration!("sqlite", 6, "006_client_settings"),
+    migration!("sqlite", 7, "007_cpu_detail"),
+    migration!("sqlite", 8, "008_gpu_metrics"),
+    migration!("sqlite", 9, "009_filesystem_metrics"),
+    migration!("sqlite", 10, "010_network_metrics"),
+    migration!("sqlite", 11, "011_disk_io_metrics"),
+    migration!("sqlite", 12, "012_docker"),
+    migration!("sqlite", 13, "013_systemd"),
+    migration!("sqlite", 14, "014_process_snapshots"),
+    migration!("sqlite", 15, "015_memory_detail"),
+    migration!("sqlite", 16, "016_sensors"),
+    migration!("sqlite", 17, "017_metric_values"),
+    migration!("sqlite", 18, "018_rollup_percentiles"),
+    migration!("sqlite", 19, "019_rollup_metric_id"),
+    migration!("sqlite", 20, "020_builtin_series"),
+    migration!("sqlite", 21, "021_client_first_seen"),
+];
+
+/// Every PostgreSQL migration, oldest first. The backend started out with
+/// the schema SQLite had reached by then, so its versions don't line up.
+pub const POSTGRES: &[Migration] = &[
+    migration!("postgres", 1, "001_initial_schema"),
+    migration!("postgres", 2, "002_rollup_percentiles"),
+    migration!("postgres", 3, "003_rollup_metric_id"),
+    migration!("postgres", 4, "004_builtin_series"),
+    migration!("postgres", 5, "005_client_first_seen"),
+];
+
+pub fn latest_version(pool: &DbPool) -> i64 {
+    pool.migrations().last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Highest applied version; 0 for a new (or pre-versioning) database
+pub async fn current_version(pool: &DbPool) -> Result<i64> {
+    pool.schema_version().await
+}
+
+/// Migrations not applied yet. Fails if the database was migrated by a newer
+/// server, since this one would misread or damage its schema.
+pub async fn pending(pool: &DbPool) -> Result<Vec<&'static Migration>> {
+    let current = current_version(pool).await?;
+    if current > latest_version(pool) {
+        bail!(
+            "database schema is at version {}, but this server only knows up to {}; \
+             upgrade the server instead",
+            current,
+            latest_version(pool)
+        );
+    }
+    Ok(pool
+        .migrations()
+        .iter()
+        .filter(|m| m.version > current)
+        .collect())
+}
+
+/// Apply pending migrations in order, each in its own transaction, so a
+/// failing one leaves the database at the previous version
+pub async fn run(pool: &DbPool) -> Result<Vec<&'static Migration>> {
+    let pending = pending(pool).await?;
+
+    for migration in &pending {
+        pool.apply_migration(migration)
+            .await
+            .with_context(|| format!("applying migration {}", migration.name))?;
+        info!("Applied migration {}", migration.name);
+    }
+
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::SqliteStorage;
+    use chrono::Utc;
+    use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+    use std::sync::Arc;
+
+    /// An in-memory SQLite storage, along with its pool for poking at the schema
+    async fn memory_pool() -> (DbPool, SqlitePool) {
+        // One connection, since every in-memory connection is its own database
+        let sqlite = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        (Arc::new(SqliteStorage::new(sqlite.clone())), sqlite)
+    }
+
+    #[test]
+    fn test_versions_match_file_names() {
+        for migrations in [SQLITE, POSTGRES] {
+            for (i, migration) in migrations.iter().enumerate() {
+                assert_eq!(migration.version, i as i64 + 1);
+                assert!(migration
+                    .name
+                    .starts_with(&format!("{:03}_", migration.version)));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_applies_each_migration_once() {
+        let (pool, _) = memory_pool().await;
+
+        assert_eq!(run(&pool).await.unwrap().len(), SQLITE.len());
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version(&pool));
+        assert!(run(&pool).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_run_adopts_pre_versioning_database() {
+        // Before versioning the server only ever ran the initial schema, with
+        // no record of it
+        let (pool, sqlite) = memory_pool().await;
+        sqlx::raw_sql(SQLITE[0].sql).execute(&sqlite).await.unwrap();
+        sqlx::raw_sql(
+            "INSERT INTO clients (id, hostname, token, last_seen) VALUES ('c1', 'web-1', 't', '');
+             INSERT INTO clients (id, hostname, token, last_seen) VALUES ('c2', 'db-1', 'u', '');
+             INSERT INTO metrics (client_id, cpu_usage, ram_usage, disk_usage, inode_usage, timestamp)
+                 VALUES ('c1', 12.5, 50.0, 10.0, 1.0, '2023-11-14T22:13:20+00:00');",
+        )
+        .execute(&sqlite)
+        .await
+        .unwrap();
+
+        let applied: Vec<i64> = run(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|m| m.version)
+            .collect();
+        let all: Vec<i64> = SQLITE.iter().map(|m| m.version).collect();
+        assert_eq!(applied, all);
+        assert_eq!(current_version(&pool).await.unwrap(), latest_version(&pool));
+
+        // Existing samples survive every migration on top of it
+        let latest = pool.get_latest_metrics("c1", 1).await.unwrap();
+        assert_eq!(latest.len(), 1);
+        assert_eq!((latest[0].cpu_usage, latest[0].ram_usage), (12.5, 50.0));
+
+        // Only the client with samples has reported
+        let reported = pool.get_client_by_id("c1").await.unwrap().unwrap();
+        let pending = pool.get_client_by_id("c2").await.unwrap().unwrap();
+        assert!(reported.first_seen.is_some());
+        assert!(pending.first_seen.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_refuses_newer_schema() {
+        let (pool, sqlite) = memory_pool().await;
+        run(&pool).await.unwrap();
+
+        sqlx::query("INSERT INTO schema_migrations (version, name, applied_at) VALUES (?, ?, ?)")
+            .bind(latest_version(&pool) + 1)
+            .bind("999_from_the_future")
+            .bind(Utc::now().to_rfc3339())
+            .execute(&sqlite)
+            .await
+            .unwrap();
+        assert!(run(&pool).await.is_err());
+    }
+}
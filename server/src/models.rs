@@ -1,6 +1,10 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+use crate::{alerts::OFFLINE_METRIC, auth::Role};
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Client {
     pub id: String,
@@ -8,6 +12,17 @@ pub struct Client {
     pub token: String,
     pub last_seen: String,
     pub version: Option<String>,
+    /// When the client first reported; `None` until it does
+    pub first_seen: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientStatus {
+    /// Registered but hasn't reported yet
+    Pending,
+    Online,
+    Offline,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,11 +31,15 @@ pub struct ClientResponse {
     pub hostname: String,
     pub last_seen: String,
     pub version: Option<String>,
+    pub status: ClientStatus,
+    pub offline_since: Option<String>,
 }
 
-impl From<Client> for ClientResponse {
-    fn from(c: Client) -> Self {
+impl ClientResponse {
+    pub fn new(c: Client, status: ClientStatus, offline_since: Option<String>) -> Self {
         Self {
+            status,
+            offline_since,
             id: c.id,
             hostname: c.hostname,
             last_seen: c.last_seen,
@@ -29,19 +48,838 @@ impl From<Client> for ClientResponse {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, FromRow)]
 pub struct Metric {
     pub id: i64,
     pub client_id: String,
+    // Stored as series, see `with_builtin_fields`
+    #[sqlx(skip)]
     pub cpu_usage: f64,
+    #[sqlx(skip)]
     pub ram_usage: f64,
+    #[sqlx(skip)]
     pub disk_usage: f64,
+    #[sqlx(skip)]
     pub inode_usage: f64,
+    #[sqlx(skip)]
     pub docker_sz: Option<i64>,
+    #[sqlx(skip)]
     pub gpu_usage: Option<f64>,
+    #[sqlx(default, json(nullable))]
+    pub cpu: Option<CpuDetail>,
+    #[sqlx(default, json(nullable))]
+    pub memory: Option<MemoryDetail>,
+    #[sqlx(default, json)]
+    pub gpus: Vec<GpuDetail>,
+    #[sqlx(default, json)]
+    pub filesystems: Vec<FilesystemUsage>,
+    #[sqlx(default, json)]
+    pub network: Vec<NetworkUsage>,
+    #[sqlx(default, json)]
+    pub disk_io: Vec<DiskIo>,
+    #[sqlx(default, json(nullable))]
+    pub docker: Option<DockerSummary>,
+    #[sqlx(default, json(nullable))]
+    pub systemd: Option<SystemdStatus>,
+    #[sqlx(default, json(nullable))]
+    pub sensors: Option<SensorReadings>,
+    #[sqlx(default, json)]
+    pub values: Vec<SeriesValue>,
     pub timestamp: String,
 }
 
+/// One value of a named series, e.g. `queue_depth{queue="mail"} 12`. Agents
+/// report any series they like this way; the built-in fields are exposed in
+/// the same shape by `Metric::series`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SeriesValue {
+    pub name: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+impl SeriesValue {
+    /// Identifies the series in stats and rollups: the bare name, or the name
+    /// followed by its labels as a JSON object
+    pub fn key(&self) -> String {
+        if self.labels.is_empty() {
+            self.name.clone()
+        } else {
+            let labels = serde_json::to_string(&self.labels).unwrap_or_default();
+            format!("{}{}", self.name, labels)
+        }
+    }
+
+    /// Inverse of `key`
+    pub fn from_key(key: &str, value: f64) -> Option<Self> {
+        let (name, labels) = match key.find('{') {
+            Some(at) => (&key[..at], serde_json::from_str(&key[at..]).ok()?),
+            None => (key, BTreeMap::new()),
+        };
+        Some(Self {
+            name: name.to_string(),
+            labels,
+            value,
+        })
+    }
+
+    /// Whether the series carries every label of a selector
+    pub fn matches(&self, selector: &[(&str, &str)]) -> bool {
+        selector
+            .iter()
+            .all(|(k, v)| self.labels.get(*k).map(String::as_str) == Some(*v))
+    }
+
+    /// Agents may not shadow built-in metric types or the labels every
+    /// exported series already carries
+    pub fn is_valid(&self) -> bool {
+        is_valid_name(&self.name)
+            && !is_builtin(&self.name)
+            && self.value.is_finite()
+            && self
+                .labels
+                .keys()
+                .all(|k| is_valid_name(k) && k != "client_id" && k != "hostname")
+    }
+}
+
+/// CPU breakdown reported by agents that can read /proc/stat
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CpuDetail {
+    pub user: f64,
+    pub system: f64,
+    pub iowait: f64,
+    pub steal: f64,
+    pub load1: f64,
+    pub load5: f64,
+    pub load15: f64,
+    /// Utilization of each core
+    #[serde(default)]
+    pub cores: Vec<f64>,
+}
+
+/// Swap, available memory, pressure stall and OOM-kill figures reported by
+/// agents that can read /proc/meminfo
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MemoryDetail {
+    pub total_bytes: i64,
+    /// Memory the kernel could hand out without swapping
+    pub available_bytes: i64,
+    /// Page cache and reclaimable slab
+    #[serde(default)]
+    pub cached_bytes: i64,
+    pub swap_total_bytes: i64,
+    pub swap_used_bytes: i64,
+    /// Absent on kernels without PSI
+    #[serde(default)]
+    pub pressure: Option<Pressure>,
+    /// OOM kills since boot
+    #[serde(default)]
+    pub oom_kills_total: Option<i64>,
+    /// OOM kills since the previous sample
+    #[serde(default)]
+    pub oom_kills: Option<i64>,
+}
+
+/// Pressure stall information from /proc/pressure
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pressure {
+    pub cpu: PsiResource,
+    pub memory: PsiResource,
+    pub io: PsiResource,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PsiResource {
+    /// Time at least one task was stalled
+    pub some: PsiAverages,
+    /// Time all non-idle tasks were stalled at once
+    #[serde(default)]
+    pub full: Option<PsiAverages>,
+}
+
+/// Stall percentages over the last 10, 60 and 300 seconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PsiAverages {
+    pub avg10: f64,
+    pub avg60: f64,
+    pub avg300: f64,
+}
+
+/// State of one GPU in a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct GpuDetail {
+    #[sqlx(rename = "gpu_index")]
+    pub index: i64,
+    pub name: String,
+    pub uuid: Option<String>,
+    pub utilization: Option<f64>,
+    pub memory_used: Option<i64>,
+    pub memory_total: Option<i64>,
+    pub temperature: Option<f64>,
+    pub power_watts: Option<f64>,
+    pub fan_speed: Option<f64>,
+    pub processes: Option<i64>,
+}
+
+/// Space and inode usage of one mounted filesystem in a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct FilesystemUsage {
+    pub mount_point: String,
+    pub device: String,
+    pub fs_type: String,
+    pub total_bytes: i64,
+    pub used_bytes: i64,
+    pub disk_usage: f64,
+    pub inode_usage: Option<f64>,
+}
+
+/// Per-second rates of one network interface in a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct NetworkUsage {
+    pub interface: String,
+    pub rx_bytes: f64,
+    pub tx_bytes: f64,
+    pub rx_packets: f64,
+    pub tx_packets: f64,
+    pub rx_errors: f64,
+    pub tx_errors: f64,
+    pub rx_drops: f64,
+    pub tx_drops: f64,
+}
+
+impl NetworkUsage {
+    pub fn value(&self, metric_type: &str) -> Option<f64> {
+        match metric_type {
+            "net_rx_mbps" => Some(self.rx_bytes * 8.0 / 1_000_000.0),
+            "net_tx_mbps" => Some(self.tx_bytes * 8.0 / 1_000_000.0),
+            "net_errors" => Some(self.rx_errors + self.tx_errors),
+            "net_drops" => Some(self.rx_drops + self.tx_drops),
+            _ => None,
+        }
+    }
+}
+
+/// Throughput, IOPS and latency of one block device in a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct DiskIo {
+    pub device: String,
+    pub read_bytes: f64,
+    pub write_bytes: f64,
+    pub read_iops: f64,
+    pub write_iops: f64,
+    /// Average request latency in ms
+    pub await_ms: f64,
+    /// Percent of the interval the device was busy
+    pub util: f64,
+}
+
+impl DiskIo {
+    pub fn value(&self, metric_type: &str) -> Option<f64> {
+        match metric_type {
+            "io_read_mbs" => Some(self.read_bytes / 1_000_000.0),
+            "io_write_mbs" => Some(self.write_bytes / 1_000_000.0),
+            "io_iops" => Some(self.read_iops + self.write_iops),
+            "io_await" => Some(self.await_ms),
+            "io_util" => Some(self.util),
+            _ => None,
+        }
+    }
+}
+
+/// One process in a top-N snapshot
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessInfo {
+    pub pid: i64,
+    pub name: String,
+    pub user: Option<String>,
+    /// Percent of one core
+    pub cpu_percent: f64,
+    pub rss_bytes: i64,
+}
+
+/// A client's busiest and largest processes at one point in time
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ProcessSnapshot {
+    pub timestamp: String,
+    #[sqlx(json)]
+    pub processes: Vec<ProcessInfo>,
+}
+
+impl ProcessSnapshot {
+    /// The `n` processes most likely behind an alert on `metric_type`: the
+    /// largest by memory for memory alerts, the busiest by CPU otherwise
+    pub fn top_offenders(&self, metric_type: &str, n: usize) -> Vec<ProcessInfo> {
+        let mut processes = self.processes.clone();
+        if is_memory_type(metric_type) {
+            processes.sort_by_key(|p| std::cmp::Reverse(p.rss_bytes));
+        } else {
+            processes.sort_by(|a, b| b.cpu_percent.total_cmp(&a.cpu_percent));
+        }
+        processes.truncate(n);
+        processes
+    }
+}
+
+/// Alert kinds best explained by memory hogs rather than CPU hogs
+fn is_memory_type(metric_type: &str) -> bool {
+    matches!(
+        metric_type,
+        "ram" | "mem_used" | "swap" | "psi_memory" | "psi_memory_full" | "oom_kills"
+    )
+}
+
+/// Failed systemd units and the state of the agent's watched units
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SystemdStatus {
+    pub failed: Vec<String>,
+    pub watched: Vec<UnitState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UnitState {
+    pub name: String,
+    pub active_state: String,
+    pub sub_state: String,
+}
+
+impl SystemdStatus {
+    /// A watched unit by name; `nginx` finds `nginx.service`
+    pub fn unit(&self, name: &str) -> Option<&UnitState> {
+        self.watched
+            .iter()
+            .find(|u| u.name == name || u.name.strip_suffix(".service") == Some(name))
+    }
+}
+
+impl UnitState {
+    pub fn is_failed(&self) -> bool {
+        self.active_state == "failed"
+    }
+}
+
+/// hwmon and thermal zone readings reported with a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SensorReadings {
+    pub temperatures: Vec<Temperature>,
+    pub fans: Vec<Fan>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Temperature {
+    /// Driver name, e.g. coretemp or nvme
+    pub chip: String,
+    /// Sensor label, e.g. "Package id 0"
+    pub label: String,
+    pub celsius: f64,
+    #[serde(default)]
+    pub high: Option<f64>,
+    #[serde(default)]
+    pub critical: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fan {
+    pub chip: String,
+    pub label: String,
+    pub rpm: f64,
+}
+
+impl SensorReadings {
+    /// A temperature sensor by label, or by `chip/label` where labels repeat
+    /// across chips (e.g. `nvme/Composite`)
+    pub fn temperature(&self, name: &str) -> Option<&Temperature> {
+        self.temperatures.iter().find(|t| {
+            t.label == name
+                || name
+                    .split_once('/')
+                    .is_some_and(|(chip, label)| t.chip == chip && t.label == label)
+        })
+    }
+}
+
+impl Temperature {
+    /// Package, core or die sensors of the CPU
+    pub fn is_cpu(&self) -> bool {
+        matches!(
+            self.chip.as_str(),
+            "coretemp" | "k10temp" | "zenpower" | "x86_pkg_temp" | "cpu_thermal" | "cpu-thermal"
+        )
+    }
+}
+
+/// Container counts and Docker disk usage reported with a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DockerSummary {
+    pub running: i64,
+    pub stopped: i64,
+    pub unhealthy: i64,
+    /// Refreshed less often than the counts; absent until the first poll
+    #[serde(default)]
+    pub disk: Option<DockerDiskUsage>,
+}
+
+/// Space used by Docker, as `docker system df` reports it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DockerDiskUsage {
+    pub images_bytes: i64,
+    pub containers_bytes: i64,
+    pub volumes_bytes: i64,
+    pub build_cache_bytes: i64,
+}
+
+/// State and resource usage of one container
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, FromRow)]
+pub struct ContainerStats {
+    #[sqlx(rename = "container_id")]
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    pub state: String,
+    pub health: Option<String>,
+    pub restart_count: i64,
+    pub cpu_percent: Option<f64>,
+    pub memory_used: Option<i64>,
+    pub memory_limit: Option<i64>,
+}
+
+/// One entry of a client's latest container list
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Container {
+    pub client_id: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub stats: ContainerStats,
+    pub updated_at: String,
+}
+
+/// One value of a series, as returned by the generic series endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesSample {
+    pub timestamp: String,
+    #[serde(flatten)]
+    pub series: SeriesValue,
+}
+
+/// One block device's reading, as returned by the per-device I/O endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DiskIoSample {
+    pub timestamp: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub disk_io: DiskIo,
+}
+
+/// One interface's reading, as returned by the per-interface series endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NetworkSample {
+    pub timestamp: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub network: NetworkUsage,
+}
+
+/// One mount's reading, as returned by the per-filesystem series endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct FilesystemSample {
+    pub timestamp: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub filesystem: FilesystemUsage,
+}
+
+/// One device's reading, as returned by the per-GPU series endpoint
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct GpuSample {
+    pub timestamp: String,
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub gpu: GpuDetail,
+}
+
+/// Metric types carried by samples, as used by alert rules, stats and rollups.
+/// Per-core utilization is also available as `cpu_core_<n>`.
+pub const METRIC_TYPES: &[&str] = &[
+    "cpu",
+    "ram",
+    "disk",
+    "inode",
+    "gpu",
+    "docker",
+    "gpu_memory",
+    "gpu_temp",
+    "gpu_power",
+    "net_rx_mbps",
+    "net_tx_mbps",
+    "net_errors",
+    "net_drops",
+    "io_read_mbs",
+    "io_write_mbs",
+    "io_iops",
+    "io_await",
+    "io_util",
+    "docker_running",
+    "docker_stopped",
+    "docker_unhealthy",
+    "systemd_failed",
+    "unit_failed",
+    "cpu_user",
+    "cpu_system",
+    "cpu_iowait",
+    "cpu_steal",
+    "load1",
+    "load5",
+    "load15",
+    "mem_used",
+    "swap",
+    "psi_cpu",
+    "psi_memory",
+    "psi_memory_full",
+    "psi_io",
+    "oom_kills",
+    "temp",
+    "cpu_temp",
+];
+
+impl Metric {
+    /// Rebuild a stored sample, whose headline fields were stored as the
+    /// unlabelled series of the same metric types (see
+    /// `MetricInput::stored_series`)
+    pub fn with_builtin_fields(mut self) -> Self {
+        for series in std::mem::take(&mut self.values) {
+            match series.name.as_str() {
+                _ if !series.labels.is_empty() => self.values.push(series),
+                "cpu" => self.cpu_usage = series.value,
+                "ram" => self.ram_usage = series.value,
+                "disk" => self.disk_usage = series.value,
+                "inode" => self.inode_usage = series.value,
+                "gpu" => self.gpu_usage = Some(series.value),
+                "docker" => self.docker_sz = Some(series.value as i64),
+                _ => self.values.push(series),
+            }
+        }
+        self
+    }
+
+    /// Every value in this sample as a named series: the built-in metric
+    /// types without labels, followed by the series reported by name
+    pub fn series(&self) -> Vec<SeriesValue> {
+        METRIC_TYPES
+            .iter()
+            .filter_map(|&name| {
+                Some(SeriesValue {
+                    name: name.to_string(),
+                    labels: BTreeMap::new(),
+                    value: self.value(name)?,
+                })
+            })
+            .chain(self.values.iter().cloned())
+            .collect()
+    }
+
+    /// Keys of the reported series, for stats and rollups
+    pub fn series_keys(&self) -> impl Iterator<Item = String> + '_ {
+        self.values.iter().map(SeriesValue::key)
+    }
+
+    /// Value of a metric type in this sample, if present
+    pub fn value(&self, metric_type: &str) -> Option<f64> {
+        match metric_type {
+            "cpu" => Some(self.cpu_usage),
+            "ram" => Some(self.ram_usage),
+            "disk" => Some(self.disk_usage),
+            "inode" => Some(self.inode_usage),
+            "gpu" => self.gpu_usage,
+            "docker" => self.docker_sz.map(|v| v as f64),
+            "docker_running" => self.docker.as_ref().map(|d| d.running as f64),
+            "docker_stopped" => self.docker.as_ref().map(|d| d.stopped as f64),
+            "docker_unhealthy" => self.docker.as_ref().map(|d| d.unhealthy as f64),
+            // Any failed unit on the host, and just the watched ones
+            "systemd_failed" => self.systemd.as_ref().map(|s| s.failed.len() as f64),
+            "unit_failed" => self
+                .systemd
+                .as_ref()
+                .map(|s| s.watched.iter().filter(|u| u.is_failed()).count() as f64),
+            // The hottest sensor, and the hottest one on the CPU
+            "temp" => self
+                .sensors
+                .as_ref()?
+                .temperatures
+                .iter()
+                .map(|t| t.celsius)
+                .reduce(f64::max),
+            "cpu_temp" => self
+                .sensors
+                .as_ref()?
+                .temperatures
+                .iter()
+                .filter(|t| t.is_cpu())
+                .map(|t| t.celsius)
+                .reduce(f64::max),
+            // Across all GPUs: the fullest and hottest device, and total draw
+            "gpu_memory" => self
+                .gpus
+                .iter()
+                .filter_map(|g| g.memory_percent())
+                .reduce(f64::max),
+            "gpu_temp" => self
+                .gpus
+                .iter()
+                .filter_map(|g| g.temperature)
+                .reduce(f64::max),
+            "gpu_power" => self
+                .gpus
+                .iter()
+                .filter_map(|g| g.power_watts)
+                .reduce(|a, b| a + b),
+            // Summed over all reported interfaces
+            "net_rx_mbps" | "net_tx_mbps" | "net_errors" | "net_drops" => self
+                .network
+                .iter()
+                .filter_map(|n| n.value(metric_type))
+                .reduce(|a, b| a + b),
+            // Throughput summed over devices; latency and busy time of the worst
+            "io_read_mbs" | "io_write_mbs" | "io_iops" => self
+                .disk_io
+                .iter()
+                .filter_map(|d| d.value(metric_type))
+                .reduce(|a, b| a + b),
+            "io_await" | "io_util" => self
+                .disk_io
+                .iter()
+                .filter_map(|d| d.value(metric_type))
+                .reduce(f64::max),
+            "mem_used" | "swap" | "psi_cpu" | "psi_memory" | "psi_memory_full" | "psi_io"
+            | "oom_kills" => self.memory.as_ref()?.value(metric_type),
+            _ if is_builtin(metric_type) => self.cpu.as_ref()?.value(metric_type),
+            // Reported series: an exact key, or the highest across label sets
+            key if key.contains('{') => {
+                self.values.iter().find(|v| v.key() == key).map(|v| v.value)
+            }
+            name => self
+                .values
+                .iter()
+                .filter(|v| v.name == name)
+                .map(|v| v.value)
+                .reduce(f64::max),
+        }
+    }
+
+    /// Value for an alert rule: metric types that accept a target (see
+    /// `accepts_target`) read it from the matching mount, interface, block
+    /// device, systemd unit or temperature sensor instead, and reported
+    /// series from the ones matching a label selector
+    pub fn value_for(&self, metric_type: &str, target: Option<&str>) -> Option<f64> {
+        let target = match target {
+            Some(t) => t,
+            None => return self.value(metric_type),
+        };
+        match metric_type {
+            "disk" | "inode" => {
+                let fs = self.filesystems.iter().find(|f| f.mount_point == target)?;
+                if metric_type == "disk" {
+                    Some(fs.disk_usage)
+                } else {
+                    fs.inode_usage
+                }
+            }
+            "net_rx_mbps" | "net_tx_mbps" | "net_errors" | "net_drops" => self
+                .network
+                .iter()
+                .find(|n| n.interface == target)?
+                .value(metric_type),
+            "io_read_mbs" | "io_write_mbs" | "io_iops" | "io_await" | "io_util" => self
+                .disk_io
+                .iter()
+                .find(|d| d.device == target)?
+                .value(metric_type),
+            "unit_failed" => {
+                let unit = self.systemd.as_ref()?.unit(target)?;
+                Some(if unit.is_failed() { 1.0 } else { 0.0 })
+            }
+            "temp" => Some(self.sensors.as_ref()?.temperature(target)?.celsius),
+            // Reported series, narrowed by a label selector
+            name if !is_builtin(name) => {
+                let selector = parse_selector(target)?;
+                self.values
+                    .iter()
+                    .filter(|v| v.name == name && v.matches(&selector))
+                    .map(|v| v.value)
+                    .reduce(f64::max)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Whether alert rules on a metric type can name a target, such as a mount
+/// point for `disk` and `inode`, an interface for `net_*`, a block device
+/// for `io_*`, a watched systemd unit for `unit_failed`, a sensor label
+/// for `temp` or a label selector such as `queue=mail,env=prod` for series
+/// reported by agents
+pub fn accepts_target(metric_type: &str) -> bool {
+    !is_builtin(metric_type)
+        || matches!(
+            metric_type,
+            "disk"
+                | "inode"
+                | "net_rx_mbps"
+                | "net_tx_mbps"
+                | "net_errors"
+                | "net_drops"
+                | "io_read_mbs"
+                | "io_write_mbs"
+                | "io_iops"
+                | "io_await"
+                | "io_util"
+                | "unit_failed"
+                | "temp"
+        )
+}
+
+/// Metric types the server computes itself, as opposed to series reported
+/// by name
+pub fn is_builtin(metric_type: &str) -> bool {
+    METRIC_TYPES.contains(&metric_type)
+        || metric_type == OFFLINE_METRIC
+        || metric_type
+            .strip_prefix("cpu_core_")
+            .is_some_and(|n| n.parse::<usize>().is_ok())
+}
+
+/// Prometheus-style metric and label names: `[a-zA-Z_:][a-zA-Z0-9_:]*`
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == ':')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+}
+
+/// `key=value` pairs separated by commas, e.g. `queue=mail,env=prod`
+pub fn parse_selector(target: &str) -> Option<Vec<(&str, &str)>> {
+    target
+        .split(',')
+        .map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let key = key.trim();
+            is_valid_name(key).then_some((key, value.trim()))
+        })
+        .collect()
+}
+
+/// Unit a metric type's values are shown in
+pub fn unit(metric_type: &str) -> &'static str {
+    match metric_type {
+        _ if !is_builtin(metric_type) => "",
+        "docker" => " bytes",
+        "gpu_temp" | "temp" | "cpu_temp" => "°C",
+        "gpu_power" => " W",
+        "net_rx_mbps" | "net_tx_mbps" => " Mbit/s",
+        "net_errors" | "net_drops" => "/s",
+        "io_read_mbs" | "io_write_mbs" => " MB/s",
+        "io_iops" => " IOPS",
+        "io_await" => " ms",
+        "load1" | "load5" | "load15" => "",
+        "docker_running" | "docker_stopped" | "docker_unhealthy" => " containers",
+        "systemd_failed" | "unit_failed" => " units",
+        "oom_kills" => " kills",
+        _ => "%",
+    }
+}
+
+impl GpuDetail {
+    pub fn memory_percent(&self) -> Option<f64> {
+        match (self.memory_used, self.memory_total) {
+            (Some(used), Some(total)) if total > 0 => Some(used as f64 / total as f64 * 100.0),
+            _ => None,
+        }
+    }
+}
+
+impl MemoryDetail {
+    /// `mem_used` counts page cache as free, unlike the headline `ram`; the
+    /// `psi_*` types are 10-second averages of the `some` line
+    pub fn value(&self, metric_type: &str) -> Option<f64> {
+        let pressure = self.pressure.as_ref();
+        match metric_type {
+            "mem_used" if self.total_bytes > 0 => {
+                let used = self.total_bytes - self.available_bytes;
+                Some(used as f64 / self.total_bytes as f64 * 100.0)
+            }
+            "swap" if self.swap_total_bytes > 0 => {
+                Some(self.swap_used_bytes as f64 / self.swap_total_bytes as f64 * 100.0)
+            }
+            "psi_cpu" => Some(pressure?.cpu.some.avg10),
+            "psi_memory" => Some(pressure?.memory.some.avg10),
+            "psi_memory_full" => Some(pressure?.memory.full.as_ref()?.avg10),
+            "psi_io" => Some(pressure?.io.some.avg10),
+            "oom_kills" => self.oom_kills.map(|v| v as f64),
+            _ => None,
+        }
+    }
+}
+
+impl CpuDetail {
+    pub fn value(&self, metric_type: &str) -> Option<f64> {
+        match metric_type {
+            "cpu_user" => Some(self.user),
+            "cpu_system" => Some(self.system),
+            "cpu_iowait" => Some(self.iowait),
+            "cpu_steal" => Some(self.steal),
+            "load1" => Some(self.load1),
+            "load5" => Some(self.load5),
+            "load15" => Some(self.load15),
+            _ => {
+                let core: usize = metric_type.strip_prefix("cpu_core_")?.parse().ok()?;
+                self.cores.get(core).copied()
+            }
+        }
+    }
+
+    /// Set a field from its metric type, growing `cores` as needed
+    pub fn set(&mut self, metric_type: &str, value: f64) {
+        match metric_type {
+            "cpu_user" => self.user = value,
+            "cpu_system" => self.system = value,
+            "cpu_iowait" => self.iowait = value,
+            "cpu_steal" => self.steal = value,
+            "load1" => self.load1 = value,
+            "load5" => self.load5 = value,
+            "load15" => self.load15 = value,
+            _ => {
+                if let Some(core) = metric_type
+                    .strip_prefix("cpu_core_")
+                    .and_then(|n| n.parse::<usize>().ok())
+                {
+                    if self.cores.len() <= core {
+                        self.cores.resize(core + 1, 0.0);
+                    }
+                    self.cores[core] = value;
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct MetricRollup {
+    pub client_id: String,
+    pub resolution: i64,
+    pub metric_type: String,
+    pub bucket: String,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub stddev: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub count: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricInput {
     pub cpu_usage: f64,
@@ -50,9 +888,62 @@ pub struct MetricInput {
     pub inode_usage: f64,
     pub docker_sz: Option<i64>,
     pub gpu_usage: Option<f64>,
+    #[serde(default)]
+    pub cpu: Option<CpuDetail>,
+    #[serde(default)]
+    pub memory: Option<MemoryDetail>,
+    #[serde(default)]
+    pub gpus: Vec<GpuDetail>,
+    #[serde(default)]
+    pub filesystems: Vec<FilesystemUsage>,
+    #[serde(default)]
+    pub network: Vec<NetworkUsage>,
+    #[serde(default)]
+    pub disk_io: Vec<DiskIo>,
+    #[serde(default)]
+    pub docker: Option<DockerSummary>,
+    /// Full container list, sent after each container poll
+    #[serde(default)]
+    pub containers: Option<Vec<ContainerStats>>,
+    #[serde(default)]
+    pub systemd: Option<SystemdStatus>,
+    #[serde(default)]
+    pub sensors: Option<SensorReadings>,
+    /// Named series beyond the built-in fields
+    #[serde(default)]
+    pub values: Vec<SeriesValue>,
+    /// Top-N process snapshot, sent every few samples
+    #[serde(default)]
+    pub processes: Option<Vec<ProcessInfo>>,
     pub timestamp: String,
 }
 
+impl MetricInput {
+    /// Everything the sample stores: the headline fields as unlabelled
+    /// series named after their metric types, then the reported series
+    pub fn stored_series(&self) -> Vec<SeriesValue> {
+        let fields = [
+            ("cpu", Some(self.cpu_usage)),
+            ("ram", Some(self.ram_usage)),
+            ("disk", Some(self.disk_usage)),
+            ("inode", Some(self.inode_usage)),
+            ("gpu", self.gpu_usage),
+            ("docker", self.docker_sz.map(|v| v as f64)),
+        ];
+        fields
+            .into_iter()
+            .filter_map(|(name, value)| {
+                Some(SeriesValue {
+                    name: name.to_string(),
+                    labels: BTreeMap::new(),
+                    value: value?,
+                })
+            })
+            .chain(self.values.iter().cloned())
+            .collect()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricBatch {
     pub hostname: String,
@@ -67,6 +958,19 @@ pub struct AlertRule {
     pub metric_type: String,
     pub threshold: f64,
     pub duration_sec: i64,
+    /// Mount point (or other instance) the rule watches, if not the default
+    #[sqlx(default)]
+    pub target: Option<String>,
+}
+
+impl AlertRule {
+    /// Metric type and target for notifications, e.g. "DISK /data"
+    pub fn label(&self) -> String {
+        match &self.target {
+            Some(target) => format!("{} {}", self.metric_type.to_uppercase(), target),
+            None => self.metric_type.to_uppercase(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -75,6 +979,80 @@ pub struct AlertRuleInput {
     pub metric_type: String,
     pub threshold: f64,
     pub duration_sec: Option<i64>,
+    pub channel_ids: Option<Vec<i64>>,
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationChannel {
+    pub id: i64,
+    pub name: String,
+    pub kind: String,
+    pub config: String,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationChannelResponse {
+    pub id: i64,
+    pub name: String,
+    pub kind: String,
+    pub config: serde_json::Value,
+    pub enabled: bool,
+}
+
+/// Channel config keys holding credentials: the SMTP password and the
+/// Gotify/ntfy tokens
+const CHANNEL_SECRETS: &[&str] = &["password", "token"];
+
+impl From<NotificationChannel> for NotificationChannelResponse {
+    fn from(c: NotificationChannel) -> Self {
+        let mut config: serde_json::Value = serde_json::from_str(&c.config).unwrap_or_default();
+        // Credentials are write-only, like the password in a logged DB URL
+        if let Some(fields) = config.as_object_mut() {
+            for key in CHANNEL_SECRETS {
+                if let Some(value) = fields.get_mut(*key).filter(|v| !v.is_null()) {
+                    *value = serde_json::Value::from("***");
+                }
+            }
+        }
+
+        Self {
+            id: c.id,
+            name: c.name,
+            kind: c.kind,
+            config,
+            enabled: c.enabled,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationChannelInput {
+    pub name: String,
+    pub kind: String,
+    pub config: serde_json::Value,
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Incident {
+    pub id: i64,
+    pub client_id: String,
+    pub rule_id: Option<i64>,
+    pub metric_type: String,
+    pub threshold: f64,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    pub peak_value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncidentQuery {
+    pub client_id: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -87,23 +1065,120 @@ pub struct Setting {
 pub struct Stats {
     pub client_id: String,
     pub metric_type: String,
+    /// Start of the bucket, for bucketed stats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bucket: Option<String>,
     pub min: f64,
     pub max: f64,
     pub avg: f64,
+    pub stddev: f64,
+    /// Estimated from the buckets' medians over longer ranges
+    pub p50: f64,
+    /// Tail percentiles over longer ranges are the highest of the buckets',
+    /// so they never under-report
+    pub p90: f64,
     pub p95: f64,
+    pub p99: f64,
     pub count: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ClientStorage {
+    pub client_id: String,
+    pub hostname: String,
+    pub metric_rows: i64,
+    pub rollup_rows: i64,
+    pub oldest_metric: Option<String>,
+    pub newest_metric: Option<String>,
+    /// Raw sample retention in effect for this client
+    #[sqlx(default)]
+    pub retention_days: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageReport {
+    pub size_bytes: i64,
+    pub free_bytes: i64,
+    pub clients: Vec<ClientStorage>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub metrics_deleted: u64,
+    pub rollups_deleted: u64,
+    pub vacuumed: bool,
+    pub size_before: i64,
+    pub size_after: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupQuery {
+    pub vacuum: Option<bool>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StatsQuery {
     pub metric_type: Option<String>,
     pub hours: Option<i64>,
+    /// "1m", "1h" or "1d" for stats per bucket; the whole range by default
+    pub bucket: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilesystemQuery {
+    pub hours: Option<i64>,
+    pub limit: Option<i64>,
+    /// Only this mount point
+    pub mount: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkQuery {
+    pub hours: Option<i64>,
+    pub limit: Option<i64>,
+    /// Only this interface
+    pub interface: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiskIoQuery {
+    pub hours: Option<i64>,
+    pub limit: Option<i64>,
+    /// Only this block device
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeriesQuery {
+    pub hours: Option<i64>,
+    /// Samples to read, not series values
+    pub limit: Option<i64>,
+    /// Only series of this name; built-in metric types included
+    pub name: Option<String>,
+    /// Only series matching a label selector, e.g. `queue=mail`
+    pub labels: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GpuQuery {
+    pub hours: Option<i64>,
+    pub limit: Option<i64>,
+    /// Only this device index
+    pub gpu: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessQuery {
+    /// Most recent snapshots to return, newest first
+    pub limit: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MetricsQuery {
     pub hours: Option<i64>,
     pub limit: Option<i64>,
+    /// "auto" (default), "raw", "1m", "1h" or "1d"
+    pub resolution: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -117,3 +1192,404 @@ pub struct CreateClientResponse {
     pub hostname: String,
     pub token: String,
 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LiveQuery {
+    /// Comma-separated list of client ids to subscribe to; all clients when absent
+    pub client_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum LiveCommand {
+    Subscribe { client_ids: Vec<String> },
+    Unsubscribe { client_ids: Vec<String> },
+    SubscribeAll,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub name: String,
+    pub key_hash: String,
+    pub role: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyResponse {
+    pub id: i64,
+    pub name: String,
+    pub role: String,
+    pub created_at: String,
+}
+
+impl From<ApiKey> for ApiKeyResponse {
+    fn from(k: ApiKey) -> Self {
+        Self {
+            id: k.id,
+            name: k.name,
+            role: k.role,
+            created_at: k.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateApiKeyResponse {
+    pub id: i64,
+    pub name: String,
+    pub role: Role,
+    pub key: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric() -> Metric {
+        Metric {
+            id: 1,
+            client_id: "c1".to_string(),
+            cpu_usage: 0.0,
+            ram_usage: 0.0,
+            disk_usage: 0.0,
+            inode_usage: 0.0,
+            docker_sz: None,
+            gpu_usage: None,
+            cpu: None,
+            memory: None,
+            gpus: vec![],
+            filesystems: vec![],
+            network: vec![],
+            disk_io: vec![],
+            docker: None,
+            systemd: None,
+            sensors: None,
+            values: vec![],
+            timestamp: "2023-11-14T22:13:20+00:00".to_string(),
+        }
+    }
+
+    fn gpu(index: i64, memory_used: i64, temperature: f64, power_watts: f64) -> GpuDetail {
+        GpuDetail {
+            index,
+            name: "NVIDIA A100".to_string(),
+            memory_used: Some(memory_used),
+            memory_total: Some(80),
+            temperature: Some(temperature),
+            power_watts: Some(power_watts),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_gpu_values_span_all_devices() {
+        let metric = Metric {
+            gpu_usage: Some(50.0),
+            gpus: vec![gpu(0, 20, 61.0, 250.0), gpu(1, 60, 83.0, 300.0)],
+            ..metric()
+        };
+
+        assert_eq!(metric.value("gpu_memory"), Some(75.0));
+        assert_eq!(metric.value("gpu_temp"), Some(83.0));
+        assert_eq!(metric.value("gpu_power"), Some(550.0));
+        assert_eq!(metric.value("cpu_iowait"), None);
+
+        let idle = Metric {
+            gpus: vec![],
+            ..metric
+        };
+        assert_eq!(idle.value("gpu_temp"), None);
+    }
+
+    #[test]
+    fn test_targeted_values_read_the_named_mount() {
+        let fs = |mount_point: &str, disk_usage: f64| FilesystemUsage {
+            mount_point: mount_point.to_string(),
+            disk_usage,
+            inode_usage: Some(disk_usage / 10.0),
+            ..Default::default()
+        };
+        let metric = Metric {
+            disk_usage: 40.0,
+            inode_usage: 4.0,
+            filesystems: vec![fs("/", 40.0), fs("/data", 95.0)],
+            ..metric()
+        };
+
+        assert_eq!(metric.value_for("disk", None), Some(40.0));
+        assert_eq!(metric.value_for("disk", Some("/data")), Some(95.0));
+        assert_eq!(metric.value_for("inode", Some("/data")), Some(9.5));
+        assert_eq!(metric.value_for("disk", Some("/backup")), None);
+        assert_eq!(metric.value_for("cpu", Some("/data")), None);
+    }
+
+    #[test]
+    fn test_network_values_in_mbit() {
+        let net = |interface: &str, tx_bytes: f64| NetworkUsage {
+            interface: interface.to_string(),
+            tx_bytes,
+            tx_errors: 1.0,
+            rx_errors: 2.0,
+            ..Default::default()
+        };
+        let metric = Metric {
+            network: vec![net("eth0", 125_000_000.0), net("eth1", 12_500_000.0)],
+            ..metric()
+        };
+
+        assert_eq!(metric.value("net_tx_mbps"), Some(1100.0));
+        assert_eq!(metric.value("net_errors"), Some(6.0));
+        assert_eq!(metric.value_for("net_tx_mbps", Some("eth0")), Some(1000.0));
+        assert_eq!(metric.value_for("net_tx_mbps", Some("wlan0")), None);
+    }
+
+    #[test]
+    fn test_disk_io_values() {
+        let dev = |device: &str, write_bytes: f64, util: f64| DiskIo {
+            device: device.to_string(),
+            write_bytes,
+            read_iops: 10.0,
+            write_iops: 40.0,
+            util,
+            ..Default::default()
+        };
+        let metric = Metric {
+            disk_io: vec![
+                dev("nvme0n1", 200_000_000.0, 35.0),
+                dev("sda", 50_000_000.0, 98.0),
+            ],
+            ..metric()
+        };
+
+        assert_eq!(metric.value("io_write_mbs"), Some(250.0));
+        assert_eq!(metric.value("io_iops"), Some(100.0));
+        assert_eq!(metric.value("io_util"), Some(98.0));
+        assert_eq!(metric.value_for("io_util", Some("nvme0n1")), Some(35.0));
+        assert_eq!(metric.value_for("io_util", Some("sdb")), None);
+    }
+
+    #[test]
+    fn test_watched_unit_failures() {
+        let unit = |name: &str, active_state: &str| UnitState {
+            name: name.to_string(),
+            active_state: active_state.to_string(),
+            sub_state: active_state.to_string(),
+        };
+        let metric = Metric {
+            systemd: Some(SystemdStatus {
+                failed: vec!["nginx.service".to_string(), "backup.timer".to_string()],
+                watched: vec![
+                    unit("nginx.service", "failed"),
+                    unit("postgresql.service", "active"),
+                ],
+            }),
+            ..metric()
+        };
+
+        assert_eq!(metric.value("systemd_failed"), Some(2.0));
+        assert_eq!(metric.value("unit_failed"), Some(1.0));
+        assert_eq!(metric.value_for("unit_failed", Some("nginx")), Some(1.0));
+        assert_eq!(
+            metric.value_for("unit_failed", Some("postgresql.service")),
+            Some(0.0)
+        );
+        assert_eq!(metric.value_for("unit_failed", Some("redis")), None);
+    }
+
+    #[test]
+    fn test_sensor_temperatures() {
+        let temperature = |chip: &str, label: &str, celsius: f64| Temperature {
+            chip: chip.to_string(),
+            label: label.to_string(),
+            celsius,
+            ..Default::default()
+        };
+        let metric = Metric {
+            sensors: Some(SensorReadings {
+                temperatures: vec![
+                    temperature("coretemp", "Package id 0", 71.0),
+                    temperature("coretemp", "Core 0", 68.0),
+                    temperature("nvme", "Composite", 44.9),
+                    temperature("drivetemp", "Composite", 79.0),
+                ],
+                fans: vec![],
+            }),
+            ..metric()
+        };
+
+        assert_eq!(metric.value("temp"), Some(79.0));
+        assert_eq!(metric.value("cpu_temp"), Some(71.0));
+        assert_eq!(metric.value_for("temp", Some("Core 0")), Some(68.0));
+        assert_eq!(metric.value_for("temp", Some("nvme/Composite")), Some(44.9));
+        assert_eq!(metric.value_for("temp", Some("Tctl")), None);
+    }
+
+    #[test]
+    fn test_reported_series() {
+        let series = |name: &str, labels: &[(&str, &str)], value: f64| SeriesValue {
+            name: name.to_string(),
+            labels: labels
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            value,
+        };
+        let metric = Metric {
+            cpu_usage: 12.0,
+            values: vec![
+                series("queue_depth", &[("queue", "mail"), ("env", "prod")], 40.0),
+                series("queue_depth", &[("queue", "jobs"), ("env", "prod")], 7.0),
+                series("backup_age_seconds", &[], 3600.0),
+            ],
+            ..metric()
+        };
+
+        assert_eq!(metric.value("queue_depth"), Some(40.0));
+        assert_eq!(metric.value("backup_age_seconds"), Some(3600.0));
+        assert_eq!(
+            metric.value(r#"queue_depth{"env":"prod","queue":"jobs"}"#),
+            Some(7.0)
+        );
+        assert_eq!(metric.value("missing"), None);
+        assert_eq!(
+            metric.value_for("queue_depth", Some("queue=jobs")),
+            Some(7.0)
+        );
+        assert_eq!(
+            metric.value_for("queue_depth", Some("env=prod, queue=mail")),
+            Some(40.0)
+        );
+        assert_eq!(metric.value_for("queue_depth", Some("env=dev")), None);
+        assert_eq!(metric.value_for("queue_depth", Some("nonsense")), None);
+        assert!(accepts_target("queue_depth"));
+        assert_eq!(unit("queue_depth"), "");
+
+        // Built-in fields come first as unlabelled series
+        let all = metric.series();
+        assert_eq!(all[0], series("cpu", &[], 12.0));
+        assert_eq!(all.len(), 4 + 3);
+
+        let key = metric.values[0].key();
+        assert_eq!(key, r#"queue_depth{"env":"prod","queue":"mail"}"#);
+        assert_eq!(
+            SeriesValue::from_key(&key, 40.0).as_ref(),
+            Some(&metric.values[0])
+        );
+
+        assert!(metric.values[0].is_valid());
+        assert!(!series("cpu", &[], 1.0).is_valid());
+        assert!(!series("cpu_core_3", &[], 1.0).is_valid());
+        assert!(!series("9lives", &[], 1.0).is_valid());
+        assert!(!series("jobs", &[("hostname", "x")], 1.0).is_valid());
+    }
+
+    #[test]
+    fn test_memory_pressure_values() {
+        let averages = |avg10: f64| PsiAverages {
+            avg10,
+            ..Default::default()
+        };
+        let memory = MemoryDetail {
+            total_bytes: 8 << 30,
+            available_bytes: 2 << 30,
+            cached_bytes: 3 << 30,
+            swap_total_bytes: 0,
+            swap_used_bytes: 0,
+            pressure: Some(Pressure {
+                cpu: PsiResource {
+                    some: averages(1.5),
+                    full: None,
+                },
+                memory: PsiResource {
+                    some: averages(20.0),
+                    full: Some(averages(7.5)),
+                },
+                io: PsiResource::default(),
+            }),
+            oom_kills_total: Some(4),
+            oom_kills: Some(1),
+        };
+
+        assert_eq!(memory.value("mem_used"), Some(75.0));
+        // No swap configured
+        assert_eq!(memory.value("swap"), None);
+        assert_eq!(memory.value("psi_cpu"), Some(1.5));
+        assert_eq!(memory.value("psi_memory"), Some(20.0));
+        assert_eq!(memory.value("psi_memory_full"), Some(7.5));
+        assert_eq!(memory.value("psi_io"), Some(0.0));
+        assert_eq!(memory.value("oom_kills"), Some(1.0));
+
+        let swapping = MemoryDetail {
+            swap_total_bytes: 4 << 30,
+            swap_used_bytes: 1 << 30,
+            pressure: None,
+            ..memory
+        };
+        assert_eq!(swapping.value("swap"), Some(25.0));
+        assert_eq!(swapping.value("psi_memory"), None);
+    }
+
+    #[test]
+    fn test_top_offenders_by_alert_kind() {
+        let process = |pid: i64, cpu_percent: f64, rss_bytes: i64| ProcessInfo {
+            pid,
+            name: format!("proc{pid}"),
+            user: None,
+            cpu_percent,
+            rss_bytes,
+        };
+        let snapshot = ProcessSnapshot {
+            timestamp: "2023-11-14T22:13:20+00:00".to_string(),
+            processes: vec![
+                process(1, 95.0, 200 << 20),
+                process(2, 0.5, 8 << 30),
+                process(3, 40.0, 50 << 20),
+            ],
+        };
+
+        let pids = |top: Vec<ProcessInfo>| top.iter().map(|p| p.pid).collect::<Vec<_>>();
+        assert_eq!(pids(snapshot.top_offenders("cpu", 2)), vec![1, 3]);
+        assert_eq!(pids(snapshot.top_offenders("ram", 2)), vec![2, 1]);
+        assert_eq!(pids(snapshot.top_offenders("psi_memory", 1)), vec![2]);
+    }
+
+    #[test]
+    fn test_channel_response_masks_secrets() {
+        let channel = |kind: &str, config: serde_json::Value| NotificationChannel {
+            id: 1,
+            name: kind.to_string(),
+            kind: kind.to_string(),
+            config: config.to_string(),
+            enabled: true,
+        };
+
+        let email = NotificationChannelResponse::from(channel(
+            "email",
+            serde_json::json!({ "host": "smtp", "username": "ops", "password": "hunter2" }),
+        ));
+        assert_eq!(email.config["password"], "***");
+        assert_eq!(email.config["username"], "ops");
+
+        let gotify = NotificationChannelResponse::from(channel(
+            "gotify",
+            serde_json::json!({ "url": "https://push", "token": "secret" }),
+        ));
+        assert_eq!(gotify.config["token"], "***");
+        assert_eq!(gotify.config["url"], "https://push");
+
+        // Nothing to hide when no token is set
+        let ntfy = NotificationChannelResponse::from(channel(
+            "ntfy",
+            serde_json::json!({ "url": "https://ntfy", "topic": "ops", "token": null }),
+        ));
+        assert!(ntfy.config["token"].is_null());
+    }
+}
@@ -0,0 +1,170 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{Notification, Notifier};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// "starttls" (default), "tls" or "none"
+    pub security: Option<String>,
+}
+
+pub struct EmailNotifier {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Vec<Mailbox>,
+}
+
+impl EmailNotifier {
+    pub fn new(config: EmailConfig) -> Result<Self> {
+        let mut builder = match config.security.as_deref().unwrap_or("starttls") {
+            "starttls" => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)?,
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&config.host)?,
+            "none" => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host),
+            other => anyhow::bail!("Unknown SMTP security mode: {}", other),
+        };
+
+        if let Some(port) = config.port {
+            builder = builder.port(port);
+        }
+        if let (Some(username), Some(password)) = (config.username, config.password) {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        if config.to.is_empty() {
+            anyhow::bail!("Email channel has no recipients");
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            from: config.from.parse()?,
+            to: config
+                .to
+                .iter()
+                .map(|addr| addr.parse())
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let mut message = Message::builder()
+            .from(self.from.clone())
+            .subject(&notification.title)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            message = message.to(to.clone());
+        }
+
+        self.transport
+            .send(message.body(notification.text.clone())?)
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::NotificationStatus;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::TcpListener,
+        sync::oneshot,
+    };
+
+    /// Minimal SMTP server accepting a single message and returning its DATA
+    async fn stub_smtp_server() -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = oneshot::channel();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, mut write) = stream.into_split();
+            let mut lines = BufReader::new(read).lines();
+            let mut data = String::new();
+            let mut in_data = false;
+
+            write.write_all(b"220 stub ESMTP\r\n").await.unwrap();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        write.write_all(b"250 queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+
+                let command = line.to_ascii_uppercase();
+                if command.starts_with("EHLO") || command.starts_with("HELO") {
+                    write.write_all(b"250 stub\r\n").await.unwrap();
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    write.write_all(b"354 go ahead\r\n").await.unwrap();
+                } else if command.starts_with("QUIT") {
+                    write.write_all(b"221 bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    write.write_all(b"250 ok\r\n").await.unwrap();
+                }
+            }
+            let _ = tx.send(data);
+        });
+
+        (port, rx)
+    }
+
+    #[tokio::test]
+    async fn test_email_delivered_to_smtp_server() {
+        let (port, rx) = stub_smtp_server().await;
+        let notifier = EmailNotifier::new(EmailConfig {
+            host: "127.0.0.1".to_string(),
+            port: Some(port),
+            username: None,
+            password: None,
+            from: "monitor@example.com".to_string(),
+            to: vec!["oncall@example.com".to_string()],
+            security: Some("none".to_string()),
+        })
+        .unwrap();
+
+        let notification = Notification {
+            status: NotificationStatus::Resolved,
+            client_id: "c1".to_string(),
+            hostname: "db-1".to_string(),
+            metric_type: "ram".to_string(),
+            value: 40.0,
+            threshold: 90.0,
+            peak: Some(95.0),
+            started_at: "2024-01-01T00:00:00+00:00".to_string(),
+            ended_at: Some("2024-01-01T00:05:00+00:00".to_string()),
+            processes: vec![],
+            title: "[RESOLVED] RAM on db-1".to_string(),
+            text: "RAM on db-1 is back at 40.0%".to_string(),
+        };
+        notifier.send(&notification).await.unwrap();
+
+        let data = rx.await.unwrap();
+        assert!(data.contains("Subject: [RESOLVED] RAM on db-1"));
+        assert!(data.contains("To: oncall@example.com"));
+        assert!(data.contains("RAM on db-1 is back at 40.0%"));
+    }
+}
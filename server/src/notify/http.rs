@@ -0,0 +1,374 @@
+use std::{sync::LazyLock, time::Duration};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use reqwest::Client;
+
+use super::{Notification, NotificationStatus, Notifier};
+
+/// Alerts are sent one at a time, so an endpoint that never answers must
+/// not hold up the ones behind it
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// HTTP client shared by every notifier
+fn client() -> Client {
+    static CLIENT: LazyLock<Client> = LazyLock::new(|| {
+        Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("HTTP client with default TLS settings")
+    });
+    CLIENT.clone()
+}
+
+async fn post_json(client: &Client, url: &str, payload: &serde_json::Value) -> Result<()> {
+    let response = client.post(url).json(payload).send().await?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("{} returned status: {}", url, response.status());
+    }
+
+    Ok(())
+}
+
+/// Slack incoming webhook
+pub struct SlackNotifier {
+    client: Client,
+    url: String,
+}
+
+impl SlackNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: client(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for SlackNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let payload = serde_json::json!({
+            "text": notification.text,
+            "mrkdwn": true
+        });
+        post_json(&self.client, &self.url, &payload).await
+    }
+}
+
+/// Generic JSON webhook: posts the full notification
+pub struct WebhookNotifier {
+    client: Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: client(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let payload = serde_json::to_value(notification)?;
+        post_json(&self.client, &self.url, &payload).await
+    }
+}
+
+/// Discord channel webhook
+pub struct DiscordNotifier {
+    client: Client,
+    url: String,
+}
+
+impl DiscordNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: client(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for DiscordNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let payload = serde_json::json!({ "content": notification.text });
+        post_json(&self.client, &self.url, &payload).await
+    }
+}
+
+/// Microsoft Teams incoming webhook (MessageCard format)
+pub struct TeamsNotifier {
+    client: Client,
+    url: String,
+}
+
+impl TeamsNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: client(),
+            url,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for TeamsNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let color = match notification.status {
+            NotificationStatus::Firing => "D93025",
+            NotificationStatus::Resolved => "1E8E3E",
+        };
+        let payload = serde_json::json!({
+            "@type": "MessageCard",
+            "@context": "https://schema.org/extensions",
+            "summary": notification.title,
+            "themeColor": color,
+            "title": notification.title,
+            "text": notification.text
+        });
+        post_json(&self.client, &self.url, &payload).await
+    }
+}
+
+/// ntfy topic publish
+pub struct NtfyNotifier {
+    client: Client,
+    url: String,
+    topic: String,
+    token: Option<String>,
+}
+
+impl NtfyNotifier {
+    pub fn new(url: String, topic: String, token: Option<String>) -> Self {
+        Self {
+            client: client(),
+            url,
+            topic,
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for NtfyNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), self.topic);
+        let (priority, tags) = match notification.status {
+            NotificationStatus::Firing => ("high", "rotating_light"),
+            NotificationStatus::Resolved => ("default", "white_check_mark"),
+        };
+
+        let mut request = self
+            .client
+            .post(&url)
+            .header("Title", &notification.title)
+            .header("Priority", priority)
+            .header("Tags", tags)
+            .body(notification.text.clone());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            anyhow::bail!("{} returned status: {}", url, response.status());
+        }
+
+        Ok(())
+    }
+}
+
+/// Gotify application message
+pub struct GotifyNotifier {
+    client: Client,
+    url: String,
+    token: String,
+}
+
+impl GotifyNotifier {
+    pub fn new(url: String, token: String) -> Self {
+        Self {
+            client: client(),
+            url,
+            token,
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for GotifyNotifier {
+    async fn send(&self, notification: &Notification) -> Result<()> {
+        let url = format!("{}/message", self.url.trim_end_matches('/'));
+        let priority = match notification.status {
+            NotificationStatus::Firing => 8,
+            NotificationStatus::Resolved => 4,
+        };
+        let payload = serde_json::json!({
+            "title": notification.title,
+            "message": notification.text,
+            "priority": priority
+        });
+
+        let response = self
+            .client
+            .post(&url)
+            .header("X-Gotify-Key", &self.token)
+            .json(&payload)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!("{} returned status: {}", url, response.status());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Bytes,
+        extract::State,
+        http::{HeaderMap, Uri},
+        routing::post,
+        Router,
+    };
+    use tokio::sync::mpsc;
+
+    type Captured = (String, HeaderMap, Bytes);
+
+    /// Start a local HTTP server that records every request it receives
+    async fn stub_server() -> (String, mpsc::UnboundedReceiver<Captured>) {
+        let (tx, rx) = mpsc::unbounded_channel::<Captured>();
+        let app = Router::new()
+            .fallback(post(
+                |State(tx): State<mpsc::UnboundedSender<Captured>>,
+                 uri: Uri,
+                 headers: HeaderMap,
+                 body: Bytes| async move {
+                    let _ = tx.send((uri.to_string(), headers, body));
+                },
+            ))
+            .with_state(tx);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        (format!("http://{}", addr), rx)
+    }
+
+    fn notification() -> Notification {
+        Notification {
+            status: NotificationStatus::Firing,
+            client_id: "c1".to_string(),
+            hostname: "web-1".to_string(),
+            metric_type: "cpu".to_string(),
+            value: 97.5,
+            threshold: 90.0,
+            peak: None,
+            started_at: "2024-01-01T00:00:00+00:00".to_string(),
+            ended_at: None,
+            processes: vec![],
+            title: "[FIRING] CPU on web-1".to_string(),
+            text: "CPU on web-1 is at 97.5%".to_string(),
+        }
+    }
+
+    fn json(body: &Bytes) -> serde_json::Value {
+        serde_json::from_slice(body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json_webhooks() {
+        let (base, mut rx) = stub_server().await;
+        let n = notification();
+
+        SlackNotifier::new(format!("{}/slack", base))
+            .send(&n)
+            .await
+            .unwrap();
+        let (_, _, body) = rx.recv().await.unwrap();
+        assert_eq!(json(&body)["text"], "CPU on web-1 is at 97.5%");
+
+        WebhookNotifier::new(format!("{}/hook", base))
+            .send(&n)
+            .await
+            .unwrap();
+        let (_, _, body) = rx.recv().await.unwrap();
+        assert_eq!(json(&body)["status"], "firing");
+        assert_eq!(json(&body)["hostname"], "web-1");
+
+        DiscordNotifier::new(format!("{}/discord", base))
+            .send(&n)
+            .await
+            .unwrap();
+        let (_, _, body) = rx.recv().await.unwrap();
+        assert_eq!(json(&body)["content"], "CPU on web-1 is at 97.5%");
+
+        TeamsNotifier::new(format!("{}/teams", base))
+            .send(&n)
+            .await
+            .unwrap();
+        let (_, _, body) = rx.recv().await.unwrap();
+        assert_eq!(json(&body)["@type"], "MessageCard");
+        assert_eq!(json(&body)["title"], "[FIRING] CPU on web-1");
+    }
+
+    #[tokio::test]
+    async fn test_ntfy_and_gotify() {
+        let (base, mut rx) = stub_server().await;
+        let n = notification();
+
+        NtfyNotifier::new(base.clone(), "alerts".to_string(), Some("tk".to_string()))
+            .send(&n)
+            .await
+            .unwrap();
+        let (uri, headers, body) = rx.recv().await.unwrap();
+        assert_eq!(uri, "/alerts");
+        assert_eq!(headers["title"], "[FIRING] CPU on web-1");
+        assert_eq!(headers["authorization"], "Bearer tk");
+        assert_eq!(&body[..], b"CPU on web-1 is at 97.5%");
+
+        GotifyNotifier::new(base, "app-token".to_string())
+            .send(&n)
+            .await
+            .unwrap();
+        let (uri, headers, body) = rx.recv().await.unwrap();
+        assert_eq!(uri, "/message");
+        assert_eq!(headers["x-gotify-key"], "app-token");
+        assert_eq!(json(&body)["priority"], 8);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unresponsive_endpoint_times_out() {
+        // Accepts connections, never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = vec![];
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let started = tokio::time::Instant::now();
+        let sent = WebhookNotifier::new(format!("http://{}/hook", addr))
+            .send(&notification())
+            .await;
+
+        let err = sent.unwrap_err();
+        assert!(err.downcast_ref::<reqwest::Error>().unwrap().is_timeout());
+        assert!(started.elapsed() >= REQUEST_TIMEOUT);
+    }
+}
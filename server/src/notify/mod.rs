@@ -0,0 +1,111 @@
+mod email;
+mod http;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::models::{NotificationChannel, ProcessInfo};
+
+pub use email::{EmailConfig, EmailNotifier};
+pub use http::{
+    DiscordNotifier, GotifyNotifier, NtfyNotifier, SlackNotifier, TeamsNotifier, WebhookNotifier,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationStatus {
+    Firing,
+    Resolved,
+}
+
+/// A rendered alert, handed to every notifier attached to the rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Notification {
+    pub status: NotificationStatus,
+    pub client_id: String,
+    pub hostname: String,
+    pub metric_type: String,
+    pub value: f64,
+    pub threshold: f64,
+    pub peak: Option<f64>,
+    pub started_at: String,
+    pub ended_at: Option<String>,
+    /// Top offenders on the host when the alert fired
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub processes: Vec<ProcessInfo>,
+    pub title: String,
+    pub text: String,
+}
+
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, notification: &Notification) -> Result<()>;
+}
+
+/// Build a notifier from a channel stored in the database
+pub fn build_notifier(channel: &NotificationChannel) -> Result<Box<dyn Notifier>> {
+    let config: serde_json::Value = serde_json::from_str(&channel.config)?;
+
+    let notifier: Box<dyn Notifier> = match channel.kind.as_str() {
+        "slack" => Box::new(SlackNotifier::new(required_str(&config, "url")?)),
+        "webhook" => Box::new(WebhookNotifier::new(required_str(&config, "url")?)),
+        "discord" => Box::new(DiscordNotifier::new(required_str(&config, "url")?)),
+        "teams" => Box::new(TeamsNotifier::new(required_str(&config, "url")?)),
+        "ntfy" => Box::new(NtfyNotifier::new(
+            required_str(&config, "url")?,
+            required_str(&config, "topic")?,
+            optional_str(&config, "token"),
+        )),
+        "gotify" => Box::new(GotifyNotifier::new(
+            required_str(&config, "url")?,
+            required_str(&config, "token")?,
+        )),
+        "email" => Box::new(EmailNotifier::new(serde_json::from_value::<EmailConfig>(
+            config,
+        )?)?),
+        other => anyhow::bail!("Unknown notification channel kind: {}", other),
+    };
+
+    Ok(notifier)
+}
+
+fn required_str(config: &serde_json::Value, key: &str) -> Result<String> {
+    optional_str(config, key).ok_or_else(|| anyhow::anyhow!("Channel config is missing `{}`", key))
+}
+
+fn optional_str(config: &serde_json::Value, key: &str) -> Option<String> {
+    config
+        .get(key)
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(kind: &str, config: serde_json::Value) -> NotificationChannel {
+        NotificationChannel {
+            id: 1,
+            name: "test".to_string(),
+            kind: kind.to_string(),
+            config: config.to_string(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_build_notifier_validates_config() {
+        let url = serde_json::json!({ "url": "http://localhost/hook" });
+        for kind in ["slack", "webhook", "discord", "teams"] {
+            assert!(build_notifier(&channel(kind, url.clone())).is_ok());
+        }
+
+        assert!(build_notifier(&channel("ntfy", url.clone())).is_err());
+        assert!(build_notifier(&channel("gotify", url.clone())).is_err());
+        assert!(build_notifier(&channel("pager", url)).is_err());
+        assert!(build_notifier(&channel("email", serde_json::json!({}))).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+
+use crate::{
+    alerts,
+    models::CleanupReport,
+    rollups::{self, Tier},
+    DbPool,
+};
+
+/// Setting holding how many days raw samples are kept; clients can override
+/// it through their own settings
+pub const RETENTION_SETTING: &str = "retention_days";
+
+/// Raw retention used when the `retention_days` setting is not set
+const DEFAULT_RETENTION_DAYS: i64 = 7;
+
+/// Rows deleted per statement; SQLite holds its write lock only this long
+const DELETE_CHUNK: i64 = 5000;
+
+/// Pause between chunks so ingestion can get the write lock in between
+const CHUNK_PAUSE: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Setting overriding a rollup tier's built-in retention, e.g. `rollup_retention_days_1h`
+pub fn tier_setting(tier: &Tier) -> String {
+    format!("rollup_retention_days_{}", tier.name)
+}
+
+/// How long raw samples and rollups are kept
+pub struct RetentionPolicy {
+    default_days: i64,
+    client_days: HashMap<String, i64>,
+    tier_days: Vec<(Tier, i64)>,
+}
+
+impl RetentionPolicy {
+    pub async fn load(pool: &DbPool) -> Result<Self> {
+        let default_days = parse_days(pool.get_setting(RETENTION_SETTING).await?)
+            .unwrap_or(DEFAULT_RETENTION_DAYS);
+
+        let client_days = pool
+            .get_client_overrides(RETENTION_SETTING)
+            .await?
+            .into_iter()
+            .filter_map(|(client_id, value)| parse_days(Some(value)).map(|d| (client_id, d)))
+            .collect();
+
+        let mut tier_days = vec![];
+        for tier in rollups::TIERS {
+            let days = parse_days(pool.get_setting(&tier_setting(tier)).await?)
+                .unwrap_or(tier.retention_days);
+            tier_days.push((*tier, days));
+        }
+
+        Ok(Self {
+            default_days,
+            client_days,
+            tier_days,
+        })
+    }
+
+    pub fn days_for(&self, client_id: &str) -> i64 {
+        self.client_days
+            .get(client_id)
+            .copied()
+            .unwrap_or(self.default_days)
+    }
+}
+
+/// Retention values must be whole, positive day counts; anything else falls
+/// back to the default rather than wiping data
+fn parse_days(value: Option<String>) -> Option<i64> {
+    value
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|d| *d > 0)
+}
+
+/// Delete raw samples and rollups past their retention, in bounded chunks
+pub async fn run_cleanup(pool: &DbPool, now: DateTime<Utc>) -> Result<CleanupReport> {
+    let policy = RetentionPolicy::load(pool).await?;
+    let mut report = CleanupReport::default();
+
+    // Raw samples the rollup task hasn't aggregated yet are kept regardless,
+    // otherwise a short retention would leave holes in the coarser tiers
+    let mut rolled_up_until = Some(DateTime::<Utc>::MAX_UTC);
+    for tier in rollups::TIERS {
+        let done = pool
+            .get_rollup_progress(tier.resolution_sec)
+            .await?
+            .and_then(|t| alerts::parse_timestamp(&t));
+        rolled_up_until = rolled_up_until.zip(done).map(|(a, b)| a.min(b));
+    }
+
+    if let Some(rolled_up_until) = rolled_up_until {
+        for client in pool.get_all_clients().await? {
+            let cutoff = (now - Duration::days(policy.days_for(&client.id)))
+                .min(rolled_up_until)
+                .to_rfc3339();
+            report.metrics_deleted +=
+                delete_chunked(|| pool.delete_metrics_before(&client.id, &cutoff, DELETE_CHUNK))
+                    .await?;
+        }
+    }
+
+    for (tier, days) in &policy.tier_days {
+        let cutoff = (now - Duration::days(*days)).to_rfc3339();
+        report.rollups_deleted += delete_chunked(|| {
+            pool.delete_rollups_before(tier.resolution_sec, &cutoff, DELETE_CHUNK)
+        })
+        .await?;
+    }
+
+    Ok(report)
+}
+
+async fn delete_chunked<F, Fut>(mut delete: F) -> Result<u64>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<u64>>,
+{
+    let mut total = 0;
+    loop {
+        let deleted = delete().await?;
+        total += deleted;
+        if deleted < DELETE_CHUNK as u64 {
+            return Ok(total);
+        }
+        tokio::time::sleep(CHUNK_PAUSE).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MetricInput;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn sample(at: DateTime<Utc>) -> MetricInput {
+        serde_json::from_value(json!({
+            "cpu_usage": 10.0,
+            "ram_usage": 50.0,
+            "disk_usage": 10.0,
+            "inode_usage": 1.0,
+            "docker_sz": null,
+            "gpu_usage": null,
+            "timestamp": at.to_rfc3339(),
+        }))
+        .unwrap()
+    }
+
+    /// A client with one raw sample this many days before `now`
+    async fn client_with_samples(
+        pool: &DbPool,
+        hostname: &str,
+        now: DateTime<Utc>,
+        days_ago: &[i64],
+    ) -> String {
+        let client = pool.create_client(hostname).await.unwrap();
+        let samples: Vec<MetricInput> = days_ago
+            .iter()
+            .map(|d| sample(now - Duration::days(*d)))
+            .collect();
+        pool.insert_metrics(&client.id, &samples).await.unwrap();
+        client.id
+    }
+
+    /// Days before `now` of each raw sample left for a client, oldest first
+    async fn days_left(pool: &DbPool, client_id: &str, now: DateTime<Utc>) -> Vec<i64> {
+        let from = (now - Duration::days(3650)).to_rfc3339();
+        let to = (now + Duration::days(1)).to_rfc3339();
+        pool.get_metrics_between(client_id, &from, &to)
+            .await
+            .unwrap()
+            .iter()
+            .map(|m| (now - alerts::parse_timestamp(&m.timestamp).unwrap()).num_days())
+            .collect()
+    }
+
+    async fn add_rollup(pool: &DbPool, client_id: &str, resolution: i64, at: DateTime<Utc>) {
+        let agg = rollups::aggregate(&[10.0]).unwrap();
+        let buckets = BTreeMap::from([(at, vec![("cpu".to_string(), agg)])]);
+        pool.upsert_rollups(client_id, resolution, &buckets)
+            .await
+            .unwrap();
+    }
+
+    async fn rollup_days_left(
+        pool: &DbPool,
+        client_id: &str,
+        resolution: i64,
+        now: DateTime<Utc>,
+    ) -> Vec<i64> {
+        let mut days: Vec<i64> = pool
+            .get_rollups(client_id, resolution, 24 * 3650)
+            .await
+            .unwrap()
+            .iter()
+            .map(|r| (now - alerts::parse_timestamp(&r.bucket).unwrap()).num_days())
+            .collect();
+        days.sort();
+        days
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_honours_overrides_and_rollup_progress() {
+        let pool = crate::db::memory().await;
+        let now = DateTime::from_timestamp(1_701_388_800, 0).unwrap();
+        let days = [40, 10, 2, 0];
+        let short = client_with_samples(&pool, "short", now, &days).await;
+        let long = client_with_samples(&pool, "long", now, &days).await;
+        let default = client_with_samples(&pool, "default", now, &days).await;
+        pool.set_client_setting(&short, RETENTION_SETTING, "1")
+            .await
+            .unwrap();
+        pool.set_client_setting(&long, RETENTION_SETTING, "30")
+            .await
+            .unwrap();
+        pool.set_setting(&tier_setting(&rollups::TIERS[1]), "20")
+            .await
+            .unwrap();
+
+        for tier in rollups::TIERS {
+            add_rollup(
+                &pool,
+                &default,
+                tier.resolution_sec,
+                now - Duration::days(40),
+            )
+            .await;
+            add_rollup(
+                &pool,
+                &default,
+                tier.resolution_sec,
+                now - Duration::days(10),
+            )
+            .await;
+        }
+        // The slowest tier has only rolled up to three days ago
+        for (tier, days) in rollups::TIERS.iter().zip([0, 1, 3]) {
+            let done = (now - Duration::days(days)).to_rfc3339();
+            pool.set_rollup_progress(tier.resolution_sec, &done, 0)
+                .await
+                .unwrap();
+        }
+
+        let report = run_cleanup(&pool, now).await.unwrap();
+
+        // One day of retention, but the sample two days old isn't rolled up yet
+        assert_eq!(days_left(&pool, &short, now).await, vec![2, 0]);
+        assert_eq!(days_left(&pool, &default, now).await, vec![2, 0]);
+        assert_eq!(days_left(&pool, &long, now).await, vec![10, 2, 0]);
+        assert_eq!(report.metrics_deleted, 5);
+
+        // 1m keeps 30 days, 1h is overridden to 20, 1d keeps five years
+        assert_eq!(rollup_days_left(&pool, &default, 60, now).await, vec![10]);
+        assert_eq!(rollup_days_left(&pool, &default, 3600, now).await, vec![10]);
+        assert_eq!(
+            rollup_days_left(&pool, &default, 86400, now).await,
+            vec![10, 40]
+        );
+        assert_eq!(report.rollups_deleted, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_keeps_raw_samples_until_every_tier_has_run() {
+        let pool = crate::db::memory().await;
+        let now = DateTime::from_timestamp(1_701_388_800, 0).unwrap();
+        let client = client_with_samples(&pool, "web-1", now, &[40, 10, 0]).await;
+        add_rollup(&pool, &client, 60, now - Duration::days(40)).await;
+        for tier in &rollups::TIERS[..2] {
+            pool.set_rollup_progress(tier.resolution_sec, &now.to_rfc3339(), 0)
+                .await
+                .unwrap();
+        }
+
+        let report = run_cleanup(&pool, now).await.unwrap();
+
+        assert_eq!(days_left(&pool, &client, now).await, vec![40, 10, 0]);
+        assert_eq!(report.metrics_deleted, 0);
+        // Rollups past their retention go regardless
+        assert!(rollup_days_left(&pool, &client, 60, now).await.is_empty());
+        assert_eq!(report.rollups_deleted, 1);
+    }
+
+    #[test]
+    fn test_parse_days_rejects_non_positive() {
+        assert_eq!(parse_days(Some("30".to_string())), Some(30));
+        assert_eq!(parse_days(Some(" 2 ".to_string())), Some(2));
+        assert_eq!(parse_days(Some("0".to_string())), None);
+        assert_eq!(parse_days(Some("-1".to_string())), None);
+        assert_eq!(parse_days(Some("forever".to_string())), None);
+        assert_eq!(parse_days(None), None);
+    }
+}
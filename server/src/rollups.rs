@@ -0,0 +1,776 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::FromRow;
+use tracing::debug;
+
+use crate::{
+    alerts,
+    models::{self, CpuDetail, Metric, MetricRollup, SeriesValue, METRIC_TYPES},
+    DbPool,
+};
+
+/// Buckets are only aggregated once this long has passed after they close,
+/// so samples still sitting in an agent's report buffer make it in.
+const GRACE_SECS: i64 = 180;
+
+/// A rollup resolution and how long its rows are kept
+#[derive(Debug, Clone, Copy)]
+pub struct Tier {
+    pub name: &'static str,
+    pub resolution_sec: i64,
+    pub retention_days: i64,
+}
+
+pub const TIERS: &[Tier] = &[
+    Tier {
+        name: "1m",
+        resolution_sec: 60,
+        retention_days: 30,
+    },
+    Tier {
+        name: "1h",
+        resolution_sec: 3600,
+        retention_days: 365,
+    },
+    Tier {
+        name: "1d",
+        resolution_sec: 86400,
+        retention_days: 1825,
+    },
+];
+
+/// Raw samples are reported every second
+const RAW_RESOLUTION_SEC: i64 = 1;
+
+/// Auto resolution picks the finest tier that keeps a chart under this many points
+const MAX_POINTS: i64 = 3600;
+
+pub fn tier_by_name(name: &str) -> Option<Tier> {
+    TIERS.iter().copied().find(|t| t.name == name)
+}
+
+/// Finest resolution whose point count over `hours` stays under `MAX_POINTS`;
+/// `None` means raw samples.
+pub fn auto_tier(hours: i64) -> Option<Tier> {
+    let range_sec = hours * 3600;
+    if range_sec / RAW_RESOLUTION_SEC <= MAX_POINTS {
+        return None;
+    }
+    TIERS
+        .iter()
+        .copied()
+        .find(|t| range_sec / t.resolution_sec <= MAX_POINTS)
+        .or(TIERS.last().copied())
+}
+
+/// Tier stats over `hours` are computed from: the finest one that still
+/// holds the whole range, and no coarser than the requested bucket
+pub fn stats_tier(hours: i64, bucket: Option<Tier>) -> Tier {
+    let max_resolution = bucket.map_or(i64::MAX, |b| b.resolution_sec);
+    let mut candidates = TIERS
+        .iter()
+        .copied()
+        .filter(|t| t.resolution_sec <= max_resolution);
+    candidates
+        .clone()
+        .find(|t| t.retention_days * 24 >= hours)
+        .or(candidates.next_back())
+        .unwrap_or(TIERS[0])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregate {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    /// Population standard deviation
+    pub stddev: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub count: i64,
+}
+
+pub fn aggregate(values: &[f64]) -> Option<Aggregate> {
+    if values.is_empty() {
+        return None;
+    }
+
+    let count = values.len() as i64;
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let avg = values.iter().sum::<f64>() / count as f64;
+    let variance = values.iter().map(|v| (v - avg).powi(2)).sum::<f64>() / count as f64;
+
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let percentile = |p: f64| sorted[((count as f64 * p) as usize).min(sorted.len() - 1)];
+
+    Some(Aggregate {
+        min,
+        max,
+        avg,
+        stddev: variance.sqrt(),
+        p50: percentile(0.50),
+        p90: percentile(0.90),
+        p95: percentile(0.95),
+        p99: percentile(0.99),
+        count,
+    })
+}
+
+/// Count-weighted sums of aggregates, which can be added up across buckets
+/// (in SQL, by `Storage::sum_rollups`). Averages and standard deviations
+/// combine exactly. Percentiles don't: the median becomes a count-weighted
+/// mean of the buckets' medians, an estimate, and the tail percentiles the
+/// highest of the buckets', an upper bound, so a spike confined to a few
+/// buckets isn't averaged away.
+#[derive(Debug, Clone, Copy, PartialEq, FromRow)]
+pub struct AggregateSums {
+    pub min: f64,
+    pub max: f64,
+    pub count: i64,
+    /// Sum of values
+    pub sum: f64,
+    /// Sum of squared values
+    pub sum_sq: f64,
+    pub p50_sum: f64,
+    pub p90_max: f64,
+    pub p95_max: f64,
+    pub p99_max: f64,
+}
+
+impl AggregateSums {
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+            count: self.count + other.count,
+            sum: self.sum + other.sum,
+            sum_sq: self.sum_sq + other.sum_sq,
+            p50_sum: self.p50_sum + other.p50_sum,
+            p90_max: self.p90_max.max(other.p90_max),
+            p95_max: self.p95_max.max(other.p95_max),
+            p99_max: self.p99_max.max(other.p99_max),
+        }
+    }
+
+    pub fn aggregate(&self) -> Aggregate {
+        let n = self.count.max(1) as f64;
+        let avg = self.sum / n;
+        Aggregate {
+            min: self.min,
+            max: self.max,
+            avg,
+            stddev: (self.sum_sq / n - avg * avg).max(0.0).sqrt(),
+            p50: self.p50_sum / n,
+            p90: self.p90_max,
+            p95: self.p95_max,
+            p99: self.p99_max,
+            count: self.count,
+        }
+    }
+}
+
+impl From<Aggregate> for AggregateSums {
+    fn from(agg: Aggregate) -> Self {
+        let n = agg.count as f64;
+        Self {
+            min: agg.min,
+            max: agg.max,
+            count: agg.count,
+            sum: agg.avg * n,
+            sum_sq: (agg.stddev * agg.stddev + agg.avg * agg.avg) * n,
+            p50_sum: agg.p50 * n,
+            p90_max: agg.p90,
+            p95_max: agg.p95,
+            p99_max: agg.p99,
+        }
+    }
+}
+
+/// Length of the RFC 3339 prefix rollup buckets within one bucket of
+/// `bucket_sec` share, for grouping them in SQL; 0 puts them all in one group
+pub fn bucket_prefix_len(bucket_sec: Option<i64>) -> i64 {
+    match bucket_sec {
+        Some(60) => 16,    // 2023-11-14T22:13
+        Some(3600) => 13,  // 2023-11-14T22
+        Some(86400) => 10, // 2023-11-14
+        _ => 0,
+    }
+}
+
+/// Summed rollups of one metric type, per bucket group when grouped
+#[derive(Debug, Clone, FromRow)]
+pub struct RollupSums {
+    /// Earliest rollup bucket in the group
+    pub bucket: String,
+    pub metric_type: String,
+    #[sqlx(flatten)]
+    pub sums: AggregateSums,
+}
+
+pub fn bucket_start(at: DateTime<Utc>, resolution_sec: i64) -> DateTime<Utc> {
+    let secs = at.timestamp();
+    DateTime::from_timestamp(secs - secs.rem_euclid(resolution_sec), 0).unwrap_or(at)
+}
+
+/// Group raw samples into buckets and aggregate every metric type and
+/// reported series per bucket
+pub fn aggregate_buckets(
+    metrics: &[Metric],
+    resolution_sec: i64,
+) -> BTreeMap<DateTime<Utc>, Vec<(String, Aggregate)>> {
+    let mut buckets: BTreeMap<DateTime<Utc>, Vec<&Metric>> = BTreeMap::new();
+    for metric in metrics {
+        if let Some(at) = alerts::parse_timestamp(&metric.timestamp) {
+            buckets
+                .entry(bucket_start(at, resolution_sec))
+                .or_default()
+                .push(metric);
+        }
+    }
+
+    buckets
+        .into_iter()
+        .map(|(bucket, samples)| {
+            let series: BTreeSet<String> = samples.iter().flat_map(|m| m.series_keys()).collect();
+            let aggregates = METRIC_TYPES
+                .iter()
+                .map(|t| t.to_string())
+                .chain(series)
+                .filter_map(|metric_type| {
+                    let values: Vec<f64> = samples
+                        .iter()
+                        .filter_map(|m| m.value(&metric_type))
+                        .collect();
+                    aggregate(&values).map(|a| (metric_type, a))
+                })
+                .collect();
+            (bucket, aggregates)
+        })
+        .collect()
+}
+
+/// Pivot rollup rows (newest bucket first) back into metric samples holding
+/// each bucket's averages, so chart consumers of raw metrics keep working
+pub fn to_metrics(client_id: &str, rollups: &[MetricRollup]) -> Vec<Metric> {
+    let mut metrics: Vec<Metric> = vec![];
+
+    for rollup in rollups {
+        if metrics
+            .last()
+            .map(|m| m.timestamp != rollup.bucket)
+            .unwrap_or(true)
+        {
+            metrics.push(Metric {
+                id: 0,
+                client_id: client_id.to_string(),
+                cpu_usage: 0.0,
+                ram_usage: 0.0,
+                disk_usage: 0.0,
+                inode_usage: 0.0,
+                docker_sz: None,
+                gpu_usage: None,
+                cpu: None,
+                memory: None,
+                gpus: vec![],
+                filesystems: vec![],
+                network: vec![],
+                disk_io: vec![],
+                docker: None,
+                systemd: None,
+                sensors: None,
+                values: vec![],
+                timestamp: rollup.bucket.clone(),
+            });
+        }
+
+        let metric = metrics.last_mut().expect("pushed above");
+        match rollup.metric_type.as_str() {
+            key if !models::is_builtin(key) => {
+                metric.values.extend(SeriesValue::from_key(key, rollup.avg))
+            }
+            "cpu" => metric.cpu_usage = rollup.avg,
+            "ram" => metric.ram_usage = rollup.avg,
+            "disk" => metric.disk_usage = rollup.avg,
+            "inode" => metric.inode_usage = rollup.avg,
+            "gpu" => metric.gpu_usage = Some(rollup.avg),
+            "docker" => metric.docker_sz = Some(rollup.avg.round() as i64),
+            other if other.starts_with("cpu_") || other.starts_with("load") => metric
+                .cpu
+                .get_or_insert_with(CpuDetail::default)
+                .set(other, rollup.avg),
+            // Per-device readings can't be rebuilt from host-wide aggregates
+            _ => {}
+        }
+    }
+
+    metrics
+}
+
+/// The tier a tier is built from; `None` for the finest, built from raw
+/// samples
+fn source_tier(tier: Tier) -> Option<Tier> {
+    TIERS
+        .iter()
+        .copied()
+        .take_while(|t| t.resolution_sec < tier.resolution_sec)
+        .last()
+}
+
+/// Merge rollups of a finer tier into buckets of `resolution_sec`, the way
+/// stats combine them (see `AggregateSums`)
+fn merge_rollups(
+    rollups: &[MetricRollup],
+    resolution_sec: i64,
+) -> BTreeMap<DateTime<Utc>, Vec<(String, Aggregate)>> {
+    let mut sums: BTreeMap<DateTime<Utc>, BTreeMap<&str, AggregateSums>> = BTreeMap::new();
+    for rollup in rollups {
+        let Some(at) = alerts::parse_timestamp(&rollup.bucket) else {
+            continue;
+        };
+        let row = AggregateSums::from(Aggregate {
+            min: rollup.min,
+            max: rollup.max,
+            avg: rollup.avg,
+            stddev: rollup.stddev,
+            p50: rollup.p50,
+            p90: rollup.p90,
+            p95: rollup.p95,
+            p99: rollup.p99,
+            count: rollup.count,
+        });
+        sums.entry(bucket_start(at, resolution_sec))
+            .or_default()
+            .entry(&rollup.metric_type)
+            .and_modify(|total| *total = total.merge(row))
+            .or_insert(row);
+    }
+
+    sums.into_iter()
+        .map(|(bucket, types)| {
+            let aggregates = types
+                .into_iter()
+                .map(|(metric_type, sums)| (metric_type.to_string(), sums.aggregate()))
+                .collect();
+            (bucket, aggregates)
+        })
+        .collect()
+}
+
+/// Aggregates of one client's `tier` buckets in `[from, to)`: from raw
+/// samples for the finest tier, from the source tier's rollups otherwise
+async fn read_buckets(
+    pool: &DbPool,
+    tier: Tier,
+    client_id: &str,
+    from: &str,
+    to: &str,
+) -> Result<BTreeMap<DateTime<Utc>, Vec<(String, Aggregate)>>> {
+    Ok(match source_tier(tier) {
+        Some(source) => {
+            let rollups = pool
+                .get_rollups_between(client_id, source.resolution_sec, from, to)
+                .await?;
+            merge_rollups(&rollups, tier.resolution_sec)
+        }
+        None => {
+            let metrics = pool.get_metrics_between(client_id, from, to).await?;
+            aggregate_buckets(&metrics, tier.resolution_sec)
+        }
+    })
+}
+
+/// Aggregate every closed bucket of a tier that hasn't been rolled up yet,
+/// and re-aggregate the buckets of samples that arrived late.
+/// Only the finest tier reads raw samples, a few hours per client at a
+/// time; coarser tiers are built from the tier below, and never run ahead
+/// of it.
+pub async fn rollup_tier(pool: &DbPool, tier: Tier, now: DateTime<Utc>) -> Result<usize> {
+    let mut end = bucket_start(now - Duration::seconds(GRACE_SECS), tier.resolution_sec);
+    let chunk = Duration::seconds(tier.resolution_sec.max(6 * 3600));
+    // Samples past this id are left for the next run, whatever their time
+    let mut metric_id = pool.get_max_metric_id().await?;
+
+    if let Some(source) = source_tier(tier) {
+        let Some(source_done) = pool
+            .get_rollup_progress(source.resolution_sec)
+            .await?
+            .and_then(|done| alerts::parse_timestamp(&done))
+        else {
+            return Ok(0);
+        };
+        end = end.min(bucket_start(source_done, tier.resolution_sec));
+        // Late samples the source tier hasn't folded in yet wait for it
+        metric_id = metric_id.min(pool.get_rollup_metric_id(source.resolution_sec).await?);
+    }
+    let mut written = 0;
+
+    let mut start = match pool.get_rollup_progress(tier.resolution_sec).await? {
+        Some(done) => {
+            let seen = pool.get_rollup_metric_id(tier.resolution_sec).await?;
+            written += rollup_late(pool, tier, chunk, &done, seen, metric_id).await?;
+            match alerts::parse_timestamp(&done) {
+                Some(t) => t,
+                None => return Ok(written),
+            }
+        }
+        // First run: backfill from the oldest raw sample still retained
+        None => match pool.get_oldest_metric_timestamp().await? {
+            Some(oldest) => match alerts::parse_timestamp(&oldest) {
+                Some(t) => bucket_start(t, tier.resolution_sec),
+                None => end,
+            },
+            None => end,
+        },
+    };
+
+    let clients = pool.get_all_clients().await?;
+    let mut advanced = false;
+
+    while start < end {
+        let chunk_end = (start + chunk).min(end);
+        let (from, to) = (start.to_rfc3339(), chunk_end.to_rfc3339());
+
+        for client in &clients {
+            let buckets = read_buckets(pool, tier, &client.id, &from, &to).await?;
+            if buckets.is_empty() {
+                continue;
+            }
+
+            written += pool
+                .upsert_rollups(&client.id, tier.resolution_sec, &buckets)
+                .await?;
+        }
+
+        pool.set_rollup_progress(tier.resolution_sec, &to, metric_id)
+            .await?;
+        start = chunk_end;
+        advanced = true;
+    }
+
+    // Record the samples seen even when no bucket closed, so late ones
+    // aren't re-aggregated again next run
+    if !advanced {
+        pool.set_rollup_progress(tier.resolution_sec, &start.to_rfc3339(), metric_id)
+            .await?;
+    }
+
+    if written > 0 {
+        debug!("Rolled up {} {} buckets", written, tier.name);
+    }
+
+    Ok(written)
+}
+
+/// Re-aggregate the buckets of samples inserted since `seen` that belong
+/// before `done`, from everything now in those buckets
+async fn rollup_late(
+    pool: &DbPool,
+    tier: Tier,
+    chunk: Duration,
+    done: &str,
+    seen: i64,
+    metric_id: i64,
+) -> Result<usize> {
+    let mut late: BTreeMap<String, BTreeSet<DateTime<Utc>>> = BTreeMap::new();
+    for (client_id, timestamp) in pool.get_late_metrics(seen, metric_id, done).await? {
+        if let Some(at) = alerts::parse_timestamp(&timestamp) {
+            late.entry(client_id)
+                .or_default()
+                .insert(bucket_start(at, tier.resolution_sec));
+        }
+    }
+
+    let step = Duration::seconds(tier.resolution_sec);
+    let mut written = 0;
+    for (client_id, buckets) in late {
+        // Adjacent buckets are read back together, up to a chunk at a time
+        let mut ranges: Vec<(DateTime<Utc>, DateTime<Utc>)> = vec![];
+        for bucket in buckets {
+            match ranges.last_mut() {
+                Some((from, to)) if *to == bucket && bucket + step - *from <= chunk => {
+                    *to = bucket + step
+                }
+                _ => ranges.push((bucket, bucket + step)),
+            }
+        }
+
+        for (from, to) in ranges {
+            let buckets =
+                read_buckets(pool, tier, &client_id, &from.to_rfc3339(), &to.to_rfc3339()).await?;
+            written += pool
+                .upsert_rollups(&client_id, tier.resolution_sec, &buckets)
+                .await?;
+        }
+    }
+
+    if written > 0 {
+        debug!(
+            "Re-aggregated {} {} buckets for late samples",
+            written, tier.name
+        );
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::MetricInput;
+    use serde_json::json;
+
+    fn metric(secs: i64, cpu: f64) -> Metric {
+        Metric {
+            id: 0,
+            client_id: "c1".to_string(),
+            cpu_usage: cpu,
+            ram_usage: 50.0,
+            disk_usage: 10.0,
+            inode_usage: 1.0,
+            docker_sz: None,
+            gpu_usage: None,
+            cpu: None,
+            memory: None,
+            gpus: vec![],
+            filesystems: vec![],
+            network: vec![],
+            disk_io: vec![],
+            docker: None,
+            systemd: None,
+            sensors: None,
+            values: vec![],
+            timestamp: DateTime::from_timestamp(1_700_000_080 + secs, 0)
+                .unwrap()
+                .to_rfc3339(),
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let values: Vec<f64> = (1..=100).map(|v| v as f64).collect();
+        let agg = aggregate(&values).unwrap();
+        assert_eq!(agg.min, 1.0);
+        assert_eq!(agg.max, 100.0);
+        assert_eq!(agg.avg, 50.5);
+        assert_eq!(agg.p50, 51.0);
+        assert_eq!(agg.p90, 91.0);
+        assert_eq!(agg.p95, 96.0);
+        assert_eq!(agg.p99, 100.0);
+        assert!((agg.stddev - 28.866).abs() < 0.001);
+        assert_eq!(agg.count, 100);
+        assert!(aggregate(&[]).is_none());
+    }
+
+    #[test]
+    fn test_sums_combine_buckets() {
+        let values: Vec<f64> = (1..=100).map(|v| v as f64).collect();
+        let whole = aggregate(&values).unwrap();
+        let (first, second) = values.split_at(30);
+        let sums = AggregateSums::from(aggregate(first).unwrap())
+            .merge(aggregate(second).unwrap().into())
+            .aggregate();
+
+        assert_eq!(sums.min, whole.min);
+        assert_eq!(sums.max, whole.max);
+        assert_eq!(sums.count, whole.count);
+        assert!((sums.avg - whole.avg).abs() < 1e-9);
+        assert!((sums.stddev - whole.stddev).abs() < 1e-9);
+        // Percentiles are only estimated from the buckets'
+        assert!((sums.p50 - whole.p50).abs() < 15.0);
+        assert!(sums.p99 >= whole.p99);
+    }
+
+    #[test]
+    fn test_sums_keep_a_short_spike() {
+        // One bucket of a spike among many quiet ones
+        let quiet = aggregate(&[10.0; 60]).unwrap();
+        let spike = aggregate(&[95.0; 60]).unwrap();
+        let sums = (0..59)
+            .fold(AggregateSums::from(spike), |sums, _| {
+                sums.merge(quiet.into())
+            })
+            .aggregate();
+
+        // The median barely moves, the tail keeps the spike
+        assert!((sums.p50 - 10.0) < 2.0);
+        assert_eq!(sums.p90, 95.0);
+        assert_eq!(sums.p95, 95.0);
+        assert_eq!(sums.p99, 95.0);
+    }
+
+    #[test]
+    fn test_stats_tier() {
+        assert_eq!(stats_tier(24, None).name, "1m");
+        assert_eq!(stats_tier(24 * 90, None).name, "1h");
+        assert_eq!(stats_tier(24 * 90, tier_by_name("1m")).name, "1m");
+        assert_eq!(stats_tier(24 * 3650, None).name, "1d");
+    }
+
+    #[test]
+    fn test_aggregate_buckets_splits_on_boundaries() {
+        // 1_700_000_080 is 40s into a minute: 20 samples before the boundary, 10 after
+        let metrics: Vec<Metric> = (0..30).map(|s| metric(s, s as f64)).collect();
+        let buckets = aggregate_buckets(&metrics, 60);
+
+        assert_eq!(buckets.len(), 2);
+        let counts: Vec<i64> = buckets
+            .values()
+            .map(|aggs| aggs.iter().find(|(t, _)| *t == "cpu").unwrap().1.count)
+            .collect();
+        assert_eq!(counts, vec![20, 10]);
+
+        // Metrics absent from every sample produce no aggregate
+        let first = buckets.values().next().unwrap();
+        assert!(first.iter().all(|(t, _)| *t != "gpu"));
+    }
+
+    #[test]
+    fn test_auto_tier() {
+        assert!(auto_tier(1).is_none());
+        assert_eq!(auto_tier(12).unwrap().name, "1m");
+        assert_eq!(auto_tier(24).unwrap().name, "1m");
+        assert_eq!(auto_tier(24 * 7).unwrap().name, "1h");
+        assert_eq!(auto_tier(24 * 365).unwrap().name, "1d");
+    }
+
+    #[test]
+    fn test_to_metrics_restores_cpu_detail() {
+        let rollup = |metric_type: &str, avg: f64| MetricRollup {
+            client_id: "c1".to_string(),
+            resolution: 60,
+            metric_type: metric_type.to_string(),
+            bucket: "2023-11-14T22:13:00+00:00".to_string(),
+            min: avg,
+            max: avg,
+            avg,
+            stddev: 0.0,
+            p50: avg,
+            p90: avg,
+            p95: avg,
+            p99: avg,
+            count: 60,
+        };
+        let rollups = [
+            rollup("cpu", 40.0),
+            rollup("cpu_iowait", 12.5),
+            rollup("load5", 3.0),
+            rollup("cpu_core_1", 80.0),
+            rollup(r#"queue_depth{"queue":"mail"}"#, 12.0),
+        ];
+
+        let metrics = to_metrics("c1", &rollups);
+        assert_eq!(metrics.len(), 1);
+        assert_eq!(metrics[0].value("cpu"), Some(40.0));
+        assert_eq!(metrics[0].value("cpu_iowait"), Some(12.5));
+        assert_eq!(metrics[0].value("load5"), Some(3.0));
+        assert_eq!(metrics[0].value("cpu_core_0"), Some(0.0));
+        assert_eq!(metrics[0].value("cpu_core_1"), Some(80.0));
+        assert_eq!(metrics[0].value("cpu_core_2"), None);
+        assert_eq!(
+            metrics[0].value_for("queue_depth", Some("queue=mail")),
+            Some(12.0)
+        );
+    }
+
+    fn sample(at: DateTime<Utc>, cpu: f64) -> MetricInput {
+        serde_json::from_value(json!({
+            "cpu_usage": cpu,
+            "ram_usage": 50.0,
+            "disk_usage": 10.0,
+            "inode_usage": 1.0,
+            "docker_sz": null,
+            "gpu_usage": null,
+            "timestamp": at.to_rfc3339(),
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_late_samples_are_rolled_up() {
+        let db = crate::db::memory().await;
+        let client = db.create_client("web-1").await.unwrap();
+        let minute = DateTime::from_timestamp(1_700_000_040, 0).unwrap();
+        let now = minute + Duration::seconds(GRACE_SECS + 120);
+        let tier = tier_by_name("1m").unwrap();
+
+        let on_time: Vec<_> = (0..3)
+            .map(|s| sample(minute + Duration::seconds(s), 10.0))
+            .collect();
+        db.insert_metrics(&client.id, &on_time).await.unwrap();
+        assert!(rollup_tier(&db, tier, now).await.unwrap() > 0);
+        let cpu = |rollups: Vec<MetricRollup>| {
+            rollups
+                .into_iter()
+                .find(|r| r.metric_type == "cpu" && r.bucket == minute.to_rfc3339())
+                .map(|r| (r.count, r.avg))
+        };
+        let rolled_up = db.get_rollups(&client.id, 60, 24 * 365 * 10).await.unwrap();
+        assert_eq!(cpu(rolled_up), Some((3, 10.0)));
+
+        // Flushed from an agent's buffer after the bucket was rolled up
+        let late = [sample(minute + Duration::seconds(30), 50.0)];
+        db.insert_metrics(&client.id, &late).await.unwrap();
+        assert!(rollup_tier(&db, tier, now).await.unwrap() > 0);
+        let rolled_up = db.get_rollups(&client.id, 60, 24 * 365 * 10).await.unwrap();
+        assert_eq!(cpu(rolled_up), Some((4, 20.0)));
+
+        // Nothing new arrived, so nothing is re-aggregated
+        assert_eq!(rollup_tier(&db, tier, now).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_coarser_tiers_build_on_finer_rollups() {
+        let db = crate::db::memory().await;
+        let client = db.create_client("web-1").await.unwrap();
+        let hour = DateTime::from_timestamp(1_699_999_200, 0).unwrap();
+        let now = hour + Duration::seconds(3600 + GRACE_SECS + 60);
+        let (minutes, hours) = (tier_by_name("1m").unwrap(), tier_by_name("1h").unwrap());
+        let cpu = |rollups: Vec<MetricRollup>| {
+            rollups
+                .into_iter()
+                .find(|r| r.metric_type == "cpu" && r.bucket == hour.to_rfc3339())
+                .map(|r| (r.count, r.avg, r.max))
+        };
+
+        // Two minutes of samples, 10% then 30%
+        let samples: Vec<_> = (0..120)
+            .map(|s| {
+                sample(
+                    hour + Duration::seconds(s),
+                    if s < 60 { 10.0 } else { 30.0 },
+                )
+            })
+            .collect();
+        db.insert_metrics(&client.id, &samples).await.unwrap();
+
+        // Nothing to build from until the minute tier has run
+        assert_eq!(rollup_tier(&db, hours, now).await.unwrap(), 0);
+        assert_eq!(db.get_rollup_progress(3600).await.unwrap(), None);
+
+        rollup_tier(&db, minutes, now).await.unwrap();
+        assert!(rollup_tier(&db, hours, now).await.unwrap() > 0);
+        let rolled_up = db
+            .get_rollups(&client.id, 3600, 24 * 365 * 10)
+            .await
+            .unwrap();
+        assert_eq!(cpu(rolled_up), Some((120, 20.0, 30.0)));
+
+        // A late sample reaches the hour only once its minute is re-aggregated
+        let late = [sample(hour + Duration::seconds(600), 80.0)];
+        db.insert_metrics(&client.id, &late).await.unwrap();
+        assert_eq!(rollup_tier(&db, hours, now).await.unwrap(), 0);
+        rollup_tier(&db, minutes, now).await.unwrap();
+        assert!(rollup_tier(&db, hours, now).await.unwrap() > 0);
+        let rolled_up = db
+            .get_rollups(&client.id, 3600, 24 * 365 * 10)
+            .await
+            .unwrap();
+        assert_eq!(cpu(rolled_up), Some((121, 2480.0 / 121.0, 80.0)));
+    }
+}
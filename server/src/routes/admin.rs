@@ -0,0 +1,74 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+
+use crate::{
+    models::{CleanupQuery, CleanupReport, StorageReport},
+    retention::{self, RetentionPolicy},
+    AppState,
+};
+
+pub async fn get_storage(State(state): State<AppState>) -> Result<Json<StorageReport>, StatusCode> {
+    let (size_bytes, free_bytes) = state
+        .db
+        .get_database_size()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let policy = RetentionPolicy::load(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut clients = state
+        .db
+        .get_client_storage()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    for client in &mut clients {
+        client.retention_days = policy.days_for(&client.client_id);
+    }
+
+    Ok(Json(StorageReport {
+        size_bytes,
+        free_bytes,
+        clients,
+    }))
+}
+
+/// Apply retention now, optionally followed by a VACUUM to hand freed pages
+/// back to the filesystem
+pub async fn run_cleanup(
+    State(state): State<AppState>,
+    Query(query): Query<CleanupQuery>,
+) -> Result<Json<CleanupReport>, StatusCode> {
+    let (size_before, _) = state
+        .db
+        .get_database_size()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut report = retention::run_cleanup(&state.db, Utc::now())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if query.vacuum.unwrap_or(false) {
+        state
+            .db
+            .vacuum()
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        report.vacuumed = true;
+    }
+
+    let (size_after, _) = state
+        .db
+        .get_database_size()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    report.size_before = size_before;
+    report.size_after = size_after;
+
+    Ok(Json(report))
+}
@@ -0,0 +1,153 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use tracing::warn;
+
+use crate::{
+    models::{NotificationChannel, NotificationChannelInput, NotificationChannelResponse},
+    notify::{self, Notification, NotificationStatus},
+    AppState,
+};
+
+pub async fn list_channels(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<NotificationChannelResponse>>, StatusCode> {
+    let channels = state
+        .db
+        .get_notification_channels()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        channels
+            .into_iter()
+            .map(NotificationChannelResponse::from)
+            .collect(),
+    ))
+}
+
+pub async fn create_channel(
+    State(state): State<AppState>,
+    Json(input): Json<NotificationChannelInput>,
+) -> Result<(StatusCode, Json<NotificationChannelResponse>), StatusCode> {
+    // Reject configs we could never deliver with
+    let candidate = NotificationChannel {
+        id: 0,
+        name: input.name.clone(),
+        kind: input.kind.clone(),
+        config: input.config.to_string(),
+        enabled: true,
+    };
+    if let Err(e) = notify::build_notifier(&candidate) {
+        warn!("Rejected notification channel {}: {}", input.name, e);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let channel = state
+        .db
+        .create_notification_channel(&input)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(NotificationChannelResponse::from(channel)),
+    ))
+}
+
+pub async fn delete_channel(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    let deleted = state
+        .db
+        .delete_notification_channel(id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Send a test notification through a channel
+pub async fn test_channel(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    let channel = state
+        .db
+        .get_notification_channel(id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let notifier = notify::build_notifier(&channel).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let notification = Notification {
+        status: NotificationStatus::Firing,
+        client_id: String::new(),
+        hostname: "status-monitor".to_string(),
+        metric_type: "test".to_string(),
+        value: 0.0,
+        threshold: 0.0,
+        peak: None,
+        started_at: Utc::now().to_rfc3339(),
+        ended_at: None,
+        processes: vec![],
+        title: "Test notification".to_string(),
+        text: format!("Test notification for channel `{}`", channel.name),
+    };
+
+    notifier.send(&notification).await.map_err(|e| {
+        warn!("Test notification for {} failed: {}", channel.name, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn get_rule_channels(
+    State(state): State<AppState>,
+    Path(rule_id): Path<i64>,
+) -> Result<Json<Vec<i64>>, StatusCode> {
+    state
+        .db
+        .get_alert_rule_by_id(rule_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let ids = state
+        .db
+        .get_rule_channel_ids(rule_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(ids))
+}
+
+pub async fn set_rule_channels(
+    State(state): State<AppState>,
+    Path(rule_id): Path<i64>,
+    Json(channel_ids): Json<Vec<i64>>,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .db
+        .get_alert_rule_by_id(rule_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    state
+        .db
+        .set_rule_channels(rule_id, &channel_ids)
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    Ok(StatusCode::OK)
+}
@@ -1,42 +1,114 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
 
+use chrono::Utc;
+
 use crate::{
-    db,
-    models::{ClientResponse, CreateClientRequest, CreateClientResponse},
+    alerts::OfflinePolicy,
+    models::{
+        Client, ClientResponse, Container, CreateClientRequest, CreateClientResponse, ProcessQuery,
+        ProcessSnapshot,
+    },
     AppState,
 };
 
 pub async fn list_clients(
     State(state): State<AppState>,
 ) -> Result<Json<Vec<ClientResponse>>, StatusCode> {
-    let clients = db::get_all_clients(&state.db)
+    let clients = state
+        .db
+        .get_all_clients()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let policy = OfflinePolicy::load(&state.db)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(clients.into_iter().map(ClientResponse::from).collect()))
+    Ok(Json(
+        clients
+            .into_iter()
+            .map(|c| client_response(&policy, c))
+            .collect(),
+    ))
 }
 
 pub async fn get_client(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<ClientResponse>, StatusCode> {
-    let client = db::get_client_by_id(&state.db, &id)
+    let client = state
+        .db
+        .get_client_by_id(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let policy = OfflinePolicy::load(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(client_response(&policy, client)))
+}
+
+/// The client's containers as of its latest Docker poll
+pub async fn get_containers(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Container>>, StatusCode> {
+    state
+        .db
+        .get_client_by_id(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let containers = state
+        .db
+        .get_containers(&id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(containers))
+}
+
+/// The client's latest top-N process snapshots, newest first
+pub async fn get_processes(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<ProcessQuery>,
+) -> Result<Json<Vec<ProcessSnapshot>>, StatusCode> {
+    state
+        .db
+        .get_client_by_id(&id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    Ok(Json(ClientResponse::from(client)))
+    let snapshots = state
+        .db
+        .get_process_snapshots(&id, query.limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(snapshots))
+}
+
+fn client_response(policy: &OfflinePolicy, client: Client) -> ClientResponse {
+    let now = Utc::now();
+    let status = policy.status(&client, now);
+    let offline_since = policy.offline_since(&client, now).map(|t| t.to_rfc3339());
+    ClientResponse::new(client, status, offline_since)
 }
 
 pub async fn create_client(
     State(state): State<AppState>,
     Json(input): Json<CreateClientRequest>,
 ) -> Result<(StatusCode, Json<CreateClientResponse>), StatusCode> {
-    let client = db::create_client(&state.db, &input.hostname)
+    let client = state
+        .db
+        .create_client(&input.hostname)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -54,7 +126,9 @@ pub async fn delete_client(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<StatusCode, StatusCode> {
-    let deleted = db::delete_client(&state.db, &id)
+    let deleted = state
+        .db
+        .delete_client(&id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -0,0 +1,203 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+
+use crate::{
+    models::{Incident, IncidentQuery},
+    AppState,
+};
+
+pub async fn list_incidents(
+    State(state): State<AppState>,
+    Query(query): Query<IncidentQuery>,
+) -> Result<Json<Vec<Incident>>, StatusCode> {
+    let incidents = state
+        .db
+        .get_incidents(&query)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(incidents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::Telemetry;
+    use std::{collections::HashMap, sync::Arc};
+    use tokio::sync::{broadcast, mpsc, Mutex};
+
+    async fn state() -> AppState {
+        let (alert_tx, _) = mpsc::channel(1);
+        let (live_tx, _) = broadcast::channel(1);
+        AppState {
+            db: crate::db::memory().await,
+            alert_tx,
+            alert_states: Arc::new(Mutex::new(HashMap::new())),
+            live_tx,
+            telemetry: Arc::new(Telemetry::default()),
+            ingest_states: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn query(from: Option<&str>, to: Option<&str>, limit: Option<i64>) -> IncidentQuery {
+        IncidentQuery {
+            client_id: None,
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+            limit,
+        }
+    }
+
+    async fn list(state: &AppState, query: IncidentQuery) -> Vec<String> {
+        let Json(incidents) = list_incidents(State(state.clone()), Query(query))
+            .await
+            .unwrap();
+        incidents.into_iter().map(|i| i.started_at).collect()
+    }
+
+    #[tokio::test]
+    async fn test_open_resolve_and_dedupe() {
+        let state = state().await;
+        let client = state.db.create_client("web-1").await.unwrap();
+        let open = |started_at: &'static str| {
+            let db = state.db.clone();
+            let client_id = client.id.clone();
+            async move {
+                db.open_incident(&client_id, None, "offline", 60.0, started_at, 0.0)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        assert!(open("2023-11-14T22:00:00+00:00").await.is_some());
+        // Still open, e.g. re-fired after a restart
+        assert!(open("2023-11-14T22:01:00+00:00").await.is_none());
+
+        let resolved = state
+            .db
+            .resolve_incident(
+                &client.id,
+                None,
+                "offline",
+                "2023-11-14T22:10:00+00:00",
+                600.0,
+            )
+            .await
+            .unwrap();
+        assert!(resolved);
+        assert!(!state
+            .db
+            .resolve_incident(
+                &client.id,
+                None,
+                "offline",
+                "2023-11-14T22:11:00+00:00",
+                1.0
+            )
+            .await
+            .unwrap());
+
+        // A new outage opens a new incident
+        assert!(open("2023-11-14T22:20:00+00:00").await.is_some());
+
+        let Json(incidents) = list_incidents(State(state.clone()), Query(query(None, None, None)))
+            .await
+            .unwrap();
+        assert_eq!(incidents.len(), 2);
+        assert_eq!(incidents[0].started_at, "2023-11-14T22:20:00+00:00");
+        assert_eq!(incidents[0].ended_at, None);
+        assert_eq!(
+            incidents[1].ended_at.as_deref(),
+            Some("2023-11-14T22:10:00+00:00")
+        );
+        assert_eq!(incidents[1].peak_value, 600.0);
+    }
+
+    #[tokio::test]
+    async fn test_range_returns_overlapping_incidents() {
+        let state = state().await;
+        let client = state.db.create_client("web-1").await.unwrap();
+        let spans = [
+            (
+                "2023-11-14T21:00:00+00:00",
+                Some("2023-11-14T21:30:00+00:00"),
+            ),
+            (
+                "2023-11-14T22:00:00+00:00",
+                Some("2023-11-14T22:10:00+00:00"),
+            ),
+            ("2023-11-14T22:20:00+00:00", None),
+        ];
+        for (started_at, ended_at) in spans {
+            state
+                .db
+                .open_incident(&client.id, None, "offline", 60.0, started_at, 0.0)
+                .await
+                .unwrap();
+            if let Some(ended_at) = ended_at {
+                state
+                    .db
+                    .resolve_incident(&client.id, None, "offline", ended_at, 0.0)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        let range = |from, to| query(from, to, None);
+        assert_eq!(
+            list(
+                &state,
+                range(
+                    Some("2023-11-14T22:05:00+00:00"),
+                    Some("2023-11-14T22:15:00+00:00")
+                )
+            )
+            .await,
+            vec!["2023-11-14T22:00:00+00:00"]
+        );
+        // Open incidents overlap every later range
+        assert_eq!(
+            list(&state, range(Some("2023-11-15T00:00:00+00:00"), None)).await,
+            vec!["2023-11-14T22:20:00+00:00"]
+        );
+        assert_eq!(
+            list(&state, range(None, Some("2023-11-14T21:15:00+00:00"))).await,
+            vec!["2023-11-14T21:00:00+00:00"]
+        );
+        assert_eq!(
+            list(
+                &state,
+                range(
+                    Some("2023-11-14T21:30:00+00:00"),
+                    Some("2023-11-14T22:00:00+00:00")
+                )
+            )
+            .await,
+            vec!["2023-11-14T22:00:00+00:00", "2023-11-14T21:00:00+00:00"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_limit_defaults_to_100() {
+        let state = state().await;
+        let client = state.db.create_client("web-1").await.unwrap();
+        for i in 0..105 {
+            let at = format!("2023-11-14T22:{:02}:{:02}+00:00", i / 60, i % 60);
+            state
+                .db
+                .open_incident(&client.id, None, &format!("series_{}", i), 90.0, &at, 95.0)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(list(&state, query(None, None, None)).await.len(), 100);
+        let newest = list(&state, query(None, None, Some(2))).await;
+        assert_eq!(
+            newest,
+            vec!["2023-11-14T22:01:44+00:00", "2023-11-14T22:01:43+00:00"]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+};
+
+use crate::{
+    ingest::{self, Frames},
+    models::Client,
+    routes::metrics::{authenticate_client, store_batch},
+    AppState,
+};
+
+const PROTOBUF: &str = "application/x-protobuf";
+
+/// Prometheus remote_write receiver (`remote_write.url: <server>/api/v1/write`)
+pub async fn remote_write(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let client = authenticate_client(&state, &headers).await?;
+    let frames = ingest::decode_remote_write(&body, &client.hostname)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    store_frames(&state, &client, frames).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// OTLP/HTTP metrics receiver (`otlphttp.endpoint: <server>`), protobuf only
+pub async fn otlp_metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<([(header::HeaderName, &'static str); 1], Vec<u8>), StatusCode> {
+    let client = authenticate_client(&state, &headers).await?;
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with(PROTOBUF) {
+        return Err(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    let frames =
+        ingest::decode_otlp(&body, &client.hostname).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    store_frames(&state, &client, frames).await?;
+
+    // An empty ExportMetricsServiceResponse encodes to zero bytes
+    Ok(([(header::CONTENT_TYPE, PROTOBUF)], vec![]))
+}
+
+async fn store_frames(state: &AppState, client: &Client, frames: Frames) -> Result<(), StatusCode> {
+    if frames.is_empty() {
+        return Ok(());
+    }
+
+    let client_state = {
+        let mut states = state.ingest_states.lock().await;
+        Arc::clone(states.entry(client.id.clone()).or_default())
+    };
+
+    // Held across the write so concurrent shards from one host take turns.
+    // The state only moves on once the batch is stored, so a failed write
+    // leaves this push's frames for the sender's retry.
+    let mut client_state = client_state.lock().await;
+    let mut next = client_state.clone();
+    let metrics = next.samples_from(frames);
+
+    // No agent version to record; keep whatever the client reported last
+    store_batch(state, client, None, &metrics).await?;
+    *client_state = next;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{db::SqliteStorage, ingest::Ratio, migrations, telemetry::Telemetry, DbPool};
+    use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+    use std::collections::HashMap;
+    use tokio::sync::{broadcast, mpsc, Mutex};
+
+    async fn state(pool: SqlitePool) -> AppState {
+        let db: DbPool = Arc::new(SqliteStorage::new(pool));
+        migrations::run(&db).await.unwrap();
+        let (alert_tx, _) = mpsc::channel(1);
+        let (live_tx, _) = broadcast::channel(1);
+        AppState {
+            db,
+            alert_tx,
+            alert_states: Arc::new(Mutex::new(HashMap::new())),
+            live_tx,
+            telemetry: Arc::new(Telemetry::default()),
+            ingest_states: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    fn frames(timestamp_ms: i64) -> Frames {
+        let mut frames = Frames::default();
+        let frame = frames.at(timestamp_ms);
+        frame.cpu_util = Some(Ratio {
+            free: 1.0,
+            total: 4.0,
+        });
+        frame.memory = Some(Ratio {
+            free: 2.0,
+            total: 8.0,
+        });
+        frames
+    }
+
+    #[tokio::test]
+    async fn test_failed_store_keeps_pending_frames() {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let state = state(pool.clone()).await;
+        let client = state.db.create_client("web-1").await.unwrap();
+
+        // The first scrape waits for a later one to finish it
+        store_frames(&state, &client, frames(1_700_000_000_000))
+            .await
+            .unwrap();
+
+        sqlx::query("ALTER TABLE metrics RENAME TO metrics_away")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let failed = store_frames(&state, &client, frames(1_700_000_015_000)).await;
+        assert_eq!(failed, Err(StatusCode::INTERNAL_SERVER_ERROR));
+        sqlx::query("ALTER TABLE metrics_away RENAME TO metrics")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        // The sender retries the same push, which still brings both scrapes
+        store_frames(&state, &client, frames(1_700_000_015_000))
+            .await
+            .unwrap();
+        let stored = state.db.get_latest_metrics(&client.id, 10).await.unwrap();
+        assert_eq!(stored.len(), 2);
+        for metric in &stored {
+            assert_eq!(metric.cpu_usage, 75.0);
+            assert_eq!(metric.ram_usage, 75.0);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Extension, Json,
+};
+
+use crate::{
+    auth::{self, AuthContext},
+    models::{ApiKeyResponse, CreateApiKeyRequest, CreateApiKeyResponse},
+    AppState,
+};
+
+pub async fn list_keys(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ApiKeyResponse>>, StatusCode> {
+    let keys = state
+        .db
+        .get_api_keys()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(keys.into_iter().map(ApiKeyResponse::from).collect()))
+}
+
+pub async fn create_key(
+    State(state): State<AppState>,
+    Json(input): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreateApiKeyResponse>), StatusCode> {
+    let key = auth::generate_key();
+    let created = state
+        .db
+        .create_api_key(&input.name, &auth::hash_key(&key), input.role)
+        .await
+        .map_err(|_| StatusCode::CONFLICT)?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(CreateApiKeyResponse {
+            id: created.id,
+            name: created.name,
+            role: input.role,
+            key,
+        }),
+    ))
+}
+
+pub async fn delete_key(
+    State(state): State<AppState>,
+    Extension(caller): Extension<AuthContext>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, StatusCode> {
+    // Don't let an admin lock themselves out by revoking the key in use
+    if caller.key_id == id {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let deleted = state
+        .db
+        .delete_api_key(id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Identity and role of the calling key
+pub async fn whoami(Extension(caller): Extension<AuthContext>) -> Json<AuthContext> {
+    Json(caller)
+}
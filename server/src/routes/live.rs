@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::Response,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+use crate::{
+    models::{LiveCommand, LiveQuery, Metric},
+    AppState,
+};
+
+pub async fn live_feed(
+    State(state): State<AppState>,
+    Query(query): Query<LiveQuery>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let filter = Filter::from_query(query.client_id.as_deref());
+    let rx = state.live_tx.subscribe();
+
+    ws.on_upgrade(move |socket| handle_socket(socket, rx, filter))
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    mut rx: broadcast::Receiver<Metric>,
+    mut filter: Filter,
+) {
+    loop {
+        tokio::select! {
+            received = rx.recv() => match received {
+                Ok(metric) => {
+                    if !is_subscribed(&filter, &metric.client_id) {
+                        continue;
+                    }
+
+                    let payload = match serde_json::to_string(&metric) {
+                        Ok(p) => p,
+                        Err(_) => continue,
+                    };
+
+                    if socket.send(Message::Text(payload)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Live feed subscriber lagged, skipped {} metrics", skipped);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str::<LiveCommand>(&text) {
+                    Ok(command) => apply_command(&mut filter, command),
+                    Err(e) => debug!("Ignoring invalid live feed command: {}", e),
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+fn parse_client_ids(raw: &str) -> HashSet<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Which clients a connection receives. A connection starts on everything
+/// unless `?client_id=` narrows it; `subscribe` on an unfiltered connection
+/// narrows it to the named clients, and `unsubscribe` on one excludes them.
+#[derive(Debug, PartialEq)]
+enum Filter {
+    All { except: HashSet<String> },
+    Only(HashSet<String>),
+}
+
+impl Filter {
+    fn from_query(client_ids: Option<&str>) -> Self {
+        match client_ids {
+            Some(raw) => Filter::Only(parse_client_ids(raw)),
+            None => Filter::All {
+                except: HashSet::new(),
+            },
+        }
+    }
+}
+
+fn is_subscribed(filter: &Filter, client_id: &str) -> bool {
+    match filter {
+        Filter::All { except } => !except.contains(client_id),
+        Filter::Only(ids) => ids.contains(client_id),
+    }
+}
+
+fn apply_command(filter: &mut Filter, command: LiveCommand) {
+    match command {
+        LiveCommand::Subscribe { client_ids } => match filter {
+            Filter::All { .. } => *filter = Filter::Only(client_ids.into_iter().collect()),
+            Filter::Only(ids) => ids.extend(client_ids),
+        },
+        LiveCommand::Unsubscribe { client_ids } => match filter {
+            Filter::All { except } => except.extend(client_ids),
+            Filter::Only(ids) => {
+                for id in &client_ids {
+                    ids.remove(id);
+                }
+            }
+        },
+        LiveCommand::SubscribeAll => {
+            *filter = Filter::All {
+                except: HashSet::new(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> HashSet<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn names(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    fn metric(client_id: &str) -> Metric {
+        Metric {
+            client_id: client_id.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_client_ids() {
+        assert_eq!(parse_client_ids("a,b"), ids(&["a", "b"]));
+        assert_eq!(parse_client_ids(" a , ,b,,a "), ids(&["a", "b"]));
+        assert!(parse_client_ids("").is_empty());
+        assert!(parse_client_ids(" , ").is_empty());
+
+        assert_eq!(
+            Filter::from_query(Some("a,b")),
+            Filter::Only(ids(&["a", "b"]))
+        );
+        assert_eq!(
+            Filter::from_query(None),
+            Filter::All {
+                except: HashSet::new()
+            }
+        );
+    }
+
+    #[test]
+    fn test_subscribe_and_unsubscribe() {
+        let mut filter = Filter::from_query(Some("a"));
+        apply_command(
+            &mut filter,
+            LiveCommand::Subscribe {
+                client_ids: names(&["b"]),
+            },
+        );
+        assert_eq!(filter, Filter::Only(ids(&["a", "b"])));
+
+        apply_command(
+            &mut filter,
+            LiveCommand::Unsubscribe {
+                client_ids: names(&["a", "z"]),
+            },
+        );
+        assert_eq!(filter, Filter::Only(ids(&["b"])));
+
+        apply_command(&mut filter, LiveCommand::SubscribeAll);
+        assert!(is_subscribed(&filter, "a"));
+        assert!(is_subscribed(&filter, "z"));
+
+        // Subscribing from the unfiltered feed narrows it
+        apply_command(
+            &mut filter,
+            LiveCommand::Subscribe {
+                client_ids: names(&["c"]),
+            },
+        );
+        assert_eq!(filter, Filter::Only(ids(&["c"])));
+    }
+
+    #[test]
+    fn test_unsubscribe_while_subscribed_to_all() {
+        let mut filter = Filter::from_query(None);
+        apply_command(
+            &mut filter,
+            LiveCommand::Unsubscribe {
+                client_ids: names(&["noisy"]),
+            },
+        );
+        assert!(!is_subscribed(&filter, "noisy"));
+        assert!(is_subscribed(&filter, "quiet"));
+
+        apply_command(&mut filter, LiveCommand::SubscribeAll);
+        assert!(is_subscribed(&filter, "noisy"));
+    }
+
+    #[test]
+    fn test_broadcast_metrics_filtered_by_client() {
+        let filter = Filter::from_query(Some("web-1,db-1"));
+        let delivered: Vec<String> = ["web-1", "cache-1", "db-1", "web-2"]
+            .into_iter()
+            .map(metric)
+            .filter(|m| is_subscribed(&filter, &m.client_id))
+            .map(|m| m.client_id)
+            .collect();
+
+        assert_eq!(delivered, names(&["web-1", "db-1"]));
+    }
+}
@@ -1,21 +1,53 @@
 use axum::{
     extract::{Path, Query, State},
-    http::{header, StatusCode},
+    http::{header, HeaderName, StatusCode},
     Json,
 };
-use tracing::info;
+use chrono::Duration;
+use std::{sync::atomic::Ordering, time::Instant};
+use tracing::{info, warn};
 
 use crate::{
-    db,
-    models::{Metric, MetricBatch, MetricsQuery, Stats, StatsQuery},
+    alerts::{self, AlertEvent, AlertTransition},
+    models::{
+        self, Client, DiskIoQuery, DiskIoSample, FilesystemQuery, FilesystemSample, GpuQuery,
+        GpuSample, Metric, MetricBatch, MetricInput, MetricRollup, MetricsQuery, NetworkQuery,
+        NetworkSample, SeriesQuery, SeriesSample, SeriesValue, Stats, StatsQuery,
+    },
+    rollups::{self, Tier},
     AppState,
 };
 
 pub async fn report_metrics(
     State(state): State<AppState>,
     headers: axum::http::HeaderMap,
-    Json(batch): Json<MetricBatch>,
+    Json(mut batch): Json<MetricBatch>,
 ) -> Result<StatusCode, StatusCode> {
+    let client = authenticate_client(&state, &headers).await?;
+
+    // Drop bad series rather than the batch, or the agent would resend it forever
+    let mut dropped = 0;
+    for metric in &mut batch.metrics {
+        let before = metric.values.len();
+        metric.values.retain(SeriesValue::is_valid);
+        dropped += before - metric.values.len();
+    }
+    if dropped > 0 {
+        warn!(
+            "Dropped {} series values with invalid or reserved names from {}",
+            dropped, client.hostname
+        );
+    }
+    store_batch(&state, &client, batch.version.as_deref(), &batch.metrics).await?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Resolve the client from its `Authorization: Bearer <token>` header
+pub async fn authenticate_client(
+    state: &AppState,
+    headers: &axum::http::HeaderMap,
+) -> Result<Client, StatusCode> {
     // Extract token from Authorization header
     let token = headers
         .get(header::AUTHORIZATION)
@@ -24,20 +56,38 @@ pub async fn report_metrics(
         .ok_or(StatusCode::UNAUTHORIZED)?;
 
     // Get client by token
-    let client = db::get_client_by_token(&state.db, token)
+    state
+        .db
+        .get_client_by_token(token)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::UNAUTHORIZED)?;
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
 
+/// Store samples reported by a client, then feed the live feed and alerts
+pub async fn store_batch(
+    state: &AppState,
+    client: &Client,
+    version: Option<&str>,
+    metrics: &[MetricInput],
+) -> Result<(), StatusCode> {
     // Update client last_seen and version
-    db::update_client_last_seen(&state.db, &client.id, batch.version.as_deref())
+    state
+        .db
+        .update_client_last_seen(&client.id, version)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     // Insert metrics
-    let inserted = db::insert_metrics(&state.db, &client.id, &batch.metrics)
+    let started = Instant::now();
+    let inserted = state
+        .db
+        .insert_metrics(&client.id, metrics)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state
+        .telemetry
+        .record_batch(inserted.len(), started.elapsed());
 
     info!(
         "Received {} metrics from client {} ({})",
@@ -46,84 +96,359 @@ pub async fn report_metrics(
         client.id
     );
 
-    // Check alert rules
-    if let Some(latest) = inserted.last() {
-        check_alerts(&state, &client.id, latest).await;
+    // Push to live feed subscribers (no receivers is not an error)
+    for metric in &inserted {
+        let _ = state.live_tx.send(metric.clone());
     }
 
-    Ok(StatusCode::OK)
+    // Check alert rules
+    check_alerts(state, &client.id, &inserted).await;
+
+    Ok(())
 }
 
-async fn check_alerts(state: &AppState, client_id: &str, metric: &Metric) {
-    let rules = match db::get_alert_rules_for_client(&state.db, client_id).await {
+async fn check_alerts(state: &AppState, client_id: &str, batch: &[Metric]) {
+    let rules = match state.db.get_alert_rules_for_client(client_id).await {
         Ok(r) => r,
         Err(_) => return,
     };
 
-    for rule in rules {
-        let value = match rule.metric_type.as_str() {
-            "cpu" => Some(metric.cpu_usage),
-            "ram" => Some(metric.ram_usage),
-            "disk" => Some(metric.disk_usage),
-            "inode" => Some(metric.inode_usage),
-            "gpu" => metric.gpu_usage,
-            _ => None,
-        };
-
-        if let Some(v) = value {
-            if v > rule.threshold {
-                // Check debounce
-                let key = format!("{}:{}", client_id, rule.metric_type);
-                let mut debounce = state.alert_debounce.lock().await;
-                let now = std::time::Instant::now();
-
-                if let Some(last_alert) = debounce.get(&key) {
-                    if now.duration_since(*last_alert).as_secs() < 300 {
-                        // 5 minute debounce
-                        continue;
-                    }
-                }
+    let first_sample = match batch
+        .first()
+        .and_then(|m| alerts::parse_timestamp(&m.timestamp))
+    {
+        Some(t) => t,
+        None => return,
+    };
+
+    // Rules we have no state for yet (new rule, server restart) are seeded
+    // from recent history so a breach already in progress keeps its start time
+    let needs_history = {
+        let states = state.alert_states.lock().await;
+        rules
+            .iter()
+            .any(|r| !states.contains_key(&(client_id.to_string(), r.id)))
+    };
+
+    let history = if needs_history {
+        let window = rules.iter().map(|r| r.duration_sec).max().unwrap_or(0);
+        let since = (first_sample - Duration::seconds(window)).to_rfc3339();
+        state
+            .db
+            .get_metrics_since(client_id, &since)
+            .await
+            .unwrap_or_default()
+    } else {
+        vec![]
+    };
+
+    let mut events = vec![];
+    {
+        let mut states = state.alert_states.lock().await;
+
+        for rule in rules {
+            let key = (client_id.to_string(), rule.id);
+            let samples = if !states.contains_key(&key) && !history.is_empty() {
+                &history
+            } else {
+                batch
+            };
 
-                debounce.insert(key.clone(), now);
-                drop(debounce);
+            let tracker = states.entry(key).or_default();
+            for metric in samples {
+                let value = match metric.value_for(&rule.metric_type, rule.target.as_deref()) {
+                    Some(v) => v,
+                    None => continue,
+                };
+                let at = match alerts::parse_timestamp(&metric.timestamp) {
+                    Some(t) => t,
+                    None => continue,
+                };
 
-                // Fire alert
-                let _ = state.alert_tx.send((client_id.to_string(), rule, v)).await;
+                match tracker.observe(&rule, value, at) {
+                    Some(AlertTransition::Fired { since }) => events.push(AlertEvent::Fired {
+                        client_id: client_id.to_string(),
+                        rule: rule.clone(),
+                        value,
+                        started_at: since,
+                    }),
+                    Some(AlertTransition::Resolved { since, peak }) => {
+                        events.push(AlertEvent::Resolved {
+                            client_id: client_id.to_string(),
+                            rule: rule.clone(),
+                            value,
+                            peak,
+                            started_at: since,
+                            ended_at: at,
+                        })
+                    }
+                    None => {}
+                }
             }
         }
     }
+
+    for event in events {
+        state.telemetry.alert_events.fetch_add(1, Ordering::Relaxed);
+        let _ = state.alert_tx.send(event).await;
+    }
+}
+
+const RESOLUTION_HEADER: HeaderName = HeaderName::from_static("x-resolution");
+
+/// Rollup tier for a query; `Ok(None)` means raw samples
+fn resolve_tier(query: &MetricsQuery) -> Result<Option<Tier>, StatusCode> {
+    match query.resolution.as_deref().unwrap_or("auto") {
+        "auto" => Ok(rollups::auto_tier(query.hours.unwrap_or(24))),
+        "raw" => Ok(None),
+        name => rollups::tier_by_name(name)
+            .map(Some)
+            .ok_or(StatusCode::BAD_REQUEST),
+    }
 }
 
+/// Metrics over the requested range. Short ranges return raw samples; longer
+/// ones return one averaged sample per rollup bucket. The resolution used is
+/// reported in the `X-Resolution` header.
 pub async fn get_metrics(
     State(state): State<AppState>,
     Path(client_id): Path<String>,
     Query(query): Query<MetricsQuery>,
-) -> Result<Json<Vec<Metric>>, StatusCode> {
+) -> Result<([(HeaderName, &'static str); 1], Json<Vec<Metric>>), StatusCode> {
     // Verify client exists
-    db::get_client_by_id(&state.db, &client_id)
+    state
+        .db
+        .get_client_by_id(&client_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    let metrics = db::get_metrics(&state.db, &client_id, query.hours, query.limit)
+    let tier = match resolve_tier(&query)? {
+        Some(tier) => tier,
+        None => {
+            let metrics = state
+                .db
+                .get_metrics(&client_id, query.hours, query.limit)
+                .await
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            return Ok(([(RESOLUTION_HEADER, "raw")], Json(metrics)));
+        }
+    };
+
+    let rows = state
+        .db
+        .get_rollups(&client_id, tier.resolution_sec, query.hours.unwrap_or(24))
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    Ok(Json(metrics))
+    let mut metrics = rollups::to_metrics(&client_id, &rows);
+    if let Some(limit) = query.limit {
+        metrics.truncate(limit.max(0) as usize);
+    }
+
+    Ok(([(RESOLUTION_HEADER, tier.name)], Json(metrics)))
 }
 
+/// Full rollup rows (min/max/avg/p95/count) for a tier, defaulting to the
+/// one auto resolution would pick
+pub async fn get_rollups(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+    Query(query): Query<MetricsQuery>,
+) -> Result<Json<Vec<MetricRollup>>, StatusCode> {
+    // Verify client exists
+    state
+        .db
+        .get_client_by_id(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let tier = resolve_tier(&query)?
+        .or_else(|| rollups::tier_by_name("1m"))
+        .ok_or(StatusCode::BAD_REQUEST)?;
+
+    let rows = state
+        .db
+        .get_rollups(&client_id, tier.resolution_sec, query.hours.unwrap_or(24))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(rows))
+}
+
+/// Per-mount filesystem series, optionally narrowed to one mount with `?mount=`
+pub async fn get_filesystem_metrics(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+    Query(query): Query<FilesystemQuery>,
+) -> Result<Json<Vec<FilesystemSample>>, StatusCode> {
+    // Verify client exists
+    state
+        .db
+        .get_client_by_id(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let samples = state
+        .db
+        .get_filesystem_metrics(&client_id, query.hours, query.limit, query.mount.as_deref())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(samples))
+}
+
+/// Per-interface network series, optionally narrowed with `?interface=`
+pub async fn get_network_metrics(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+    Query(query): Query<NetworkQuery>,
+) -> Result<Json<Vec<NetworkSample>>, StatusCode> {
+    // Verify client exists
+    state
+        .db
+        .get_client_by_id(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let samples = state
+        .db
+        .get_network_metrics(
+            &client_id,
+            query.hours,
+            query.limit,
+            query.interface.as_deref(),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(samples))
+}
+
+/// Per-device disk I/O series, optionally narrowed with `?device=`
+pub async fn get_disk_io_metrics(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+    Query(query): Query<DiskIoQuery>,
+) -> Result<Json<Vec<DiskIoSample>>, StatusCode> {
+    // Verify client exists
+    state
+        .db
+        .get_client_by_id(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let samples = state
+        .db
+        .get_disk_io_metrics(
+            &client_id,
+            query.hours,
+            query.limit,
+            query.device.as_deref(),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(samples))
+}
+
+/// Per-device GPU series, optionally narrowed to one device with `?gpu=`
+pub async fn get_gpu_metrics(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+    Query(query): Query<GpuQuery>,
+) -> Result<Json<Vec<GpuSample>>, StatusCode> {
+    // Verify client exists
+    state
+        .db
+        .get_client_by_id(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let samples = state
+        .db
+        .get_gpu_metrics(&client_id, query.hours, query.limit, query.gpu)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(samples))
+}
+
+/// Every value as a name/labels/value series, built-in metric types
+/// included, optionally narrowed with `?name=` and `?labels=k=v,...`
+pub async fn get_series(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+    Query(query): Query<SeriesQuery>,
+) -> Result<Json<Vec<SeriesSample>>, StatusCode> {
+    // Verify client exists
+    state
+        .db
+        .get_client_by_id(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let selector = match query.labels.as_deref() {
+        Some(labels) => models::parse_selector(labels).ok_or(StatusCode::BAD_REQUEST)?,
+        None => vec![],
+    };
+
+    let metrics = state
+        .db
+        .get_metrics(&client_id, query.hours, query.limit)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let samples = metrics
+        .iter()
+        .flat_map(|metric| {
+            metric.series().into_iter().map(|series| SeriesSample {
+                timestamp: metric.timestamp.clone(),
+                series,
+            })
+        })
+        .filter(|s| query.name.as_deref().is_none_or(|n| s.series.name == n))
+        .filter(|s| s.series.matches(&selector))
+        .collect();
+
+    Ok(Json(samples))
+}
+
+/// Min/max/avg/stddev and percentiles per metric type over the requested
+/// range, optionally for one metric type and per `bucket` ("1m", "1h", "1d")
 pub async fn get_stats(
     State(state): State<AppState>,
     Path(client_id): Path<String>,
     Query(query): Query<StatsQuery>,
 ) -> Result<Json<Vec<Stats>>, StatusCode> {
     // Verify client exists
-    db::get_client_by_id(&state.db, &client_id)
+    state
+        .db
+        .get_client_by_id(&client_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    let stats = db::get_stats(&state.db, &client_id, query.hours)
+    let bucket = query
+        .bucket
+        .as_deref()
+        .map(|name| rollups::tier_by_name(name).ok_or(StatusCode::BAD_REQUEST))
+        .transpose()?;
+
+    let stats = state
+        .db
+        .get_stats(
+            &client_id,
+            query.hours,
+            query.metric_type.as_deref(),
+            bucket,
+        )
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -135,12 +460,16 @@ pub async fn get_latest_metrics(
     Path(client_id): Path<String>,
 ) -> Result<Json<Vec<Metric>>, StatusCode> {
     // Verify client exists
-    db::get_client_by_id(&state.db, &client_id)
+    state
+        .db
+        .get_client_by_id(&client_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
         .ok_or(StatusCode::NOT_FOUND)?;
 
-    let metrics = db::get_latest_metrics(&state.db, &client_id, 60)
+    let metrics = state
+        .db
+        .get_latest_metrics(&client_id, 60)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
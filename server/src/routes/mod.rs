@@ -1,20 +1,33 @@
+pub mod admin;
+pub mod channels;
 pub mod clients;
+pub mod incidents;
+pub mod ingest;
+pub mod keys;
+pub mod live;
 pub mod metrics;
+pub mod prometheus;
 pub mod settings;
 
+use std::collections::HashMap;
+
 use axum::{
     body::Body,
-    extract::State,
-    http::{header, Request, StatusCode},
+    extract::{Query, State},
+    http::{header, Request, StatusCode, Uri},
     middleware::Next,
     response::Response,
 };
+use tracing::Span;
 
-use crate::{db, AppState};
+use crate::{
+    auth::{self, AuthContext, Role},
+    AppState,
+};
 
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Result<Response, StatusCode> {
     let auth_header = request
@@ -22,14 +35,91 @@ pub async fn auth_middleware(
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok());
 
+    // Browsers can't set headers on WebSocket handshakes, so the live feed
+    // also accepts the key as a `token` query parameter
     let token = match auth_header {
-        Some(h) if h.starts_with("Bearer ") => &h[7..],
+        Some(h) if h.starts_with("Bearer ") => h[7..].to_string(),
+        _ if request.uri().path() == "/ws/live" => {
+            query_token(request.uri()).ok_or(StatusCode::UNAUTHORIZED)?
+        }
         _ => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    // Verify token exists in database
-    match db::get_client_by_token(&state.db, token).await {
-        Ok(Some(_)) => Ok(next.run(request).await),
-        _ => Err(StatusCode::UNAUTHORIZED),
+    // Verify key exists in database
+    let key = state
+        .db
+        .get_api_key_by_hash(&auth::hash_key(&token))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let role = Role::parse(&key.role).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if role != Role::Admin && auth::requires_admin(request.method(), request.uri().path()) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    request.extensions_mut().insert(AuthContext {
+        key_id: key.id,
+        name: key.name,
+        role,
+    });
+
+    Ok(next.run(request).await)
+}
+
+pub async fn health() -> StatusCode {
+    StatusCode::OK
+}
+
+/// The percent-decoded `token` query parameter
+fn query_token(uri: &Uri) -> Option<String> {
+    let Query(mut params) = Query::<HashMap<String, String>>::try_from_uri(uri).ok()?;
+    params.remove("token")
+}
+
+/// `TraceLayer`'s default request span, with the `token` query parameter
+/// masked so live feed keys stay out of the logs
+pub fn request_span(request: &Request<Body>) -> Span {
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %redact_token(request.uri()),
+        version = ?request.version(),
+    )
+}
+
+fn redact_token(uri: &Uri) -> String {
+    let Some(query) = uri.query() else {
+        return uri.to_string();
+    };
+    let query: Vec<&str> = query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("token", _)) => "token=***",
+            _ => pair,
+        })
+        .collect();
+    format!("{}?{}", uri.path(), query.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_token_is_decoded() {
+        let uri: Uri = "/ws/live?client_id=a&token=sm_a%2Bb%3D".parse().unwrap();
+        assert_eq!(query_token(&uri).as_deref(), Some("sm_a+b="));
+        assert_eq!(query_token(&"/ws/live?client_id=a".parse().unwrap()), None);
+        assert_eq!(query_token(&"/ws/live".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn test_redact_token() {
+        let uri: Uri = "/ws/live?client_id=a&token=secret".parse().unwrap();
+        assert_eq!(redact_token(&uri), "/ws/live?client_id=a&token=***");
+        let uri: Uri = "/api/metrics/a?hours=1".parse().unwrap();
+        assert_eq!(redact_token(&uri), "/api/metrics/a?hours=1");
+        assert_eq!(redact_token(&"/api/health".parse().unwrap()), "/api/health");
     }
 }
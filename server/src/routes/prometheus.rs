@@ -0,0 +1,678 @@
+use axum::{
+    extract::State,
+    http::{header, StatusCode},
+};
+use chrono::Utc;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::atomic::Ordering,
+    time::Instant,
+};
+
+use crate::{
+    alerts::{self, OfflinePolicy},
+    models::{
+        ClientStatus, ContainerStats, DiskIo, FilesystemUsage, GpuDetail, Metric, NetworkUsage,
+        SeriesValue, METRIC_TYPES,
+    },
+    telemetry::Exposition,
+    AppState,
+};
+
+/// Gauge name and help text for each sample field
+fn gauge_for(metric_type: &str) -> (&'static str, &'static str) {
+    match metric_type {
+        "cpu" => ("cpu_usage_percent", "CPU usage of the latest sample"),
+        "ram" => ("ram_usage_percent", "Memory usage of the latest sample"),
+        "disk" => (
+            "disk_usage_percent",
+            "Root filesystem usage of the latest sample",
+        ),
+        "inode" => (
+            "inode_usage_percent",
+            "Root filesystem inode usage of the latest sample",
+        ),
+        "gpu" => ("gpu_usage_percent", "GPU usage of the latest sample"),
+        "docker" => (
+            "docker_size_bytes",
+            "Docker data directory size of the latest sample",
+        ),
+        "gpu_memory" => ("gpu_memory_max_percent", "Memory usage of the fullest GPU"),
+        "gpu_temp" => (
+            "gpu_temperature_max_celsius",
+            "Temperature of the hottest GPU",
+        ),
+        "gpu_power" => ("gpu_power_total_watts", "Power draw of all GPUs"),
+        "net_rx_mbps" => (
+            "network_receive_mbps",
+            "Traffic received on all interfaces, in Mbit/s",
+        ),
+        "net_tx_mbps" => (
+            "network_transmit_mbps",
+            "Traffic sent on all interfaces, in Mbit/s",
+        ),
+        "net_errors" => (
+            "network_errors_per_second",
+            "Receive and transmit errors on all interfaces",
+        ),
+        "net_drops" => (
+            "network_drops_per_second",
+            "Packets dropped on all interfaces",
+        ),
+        "io_read_mbs" => (
+            "disk_read_mbytes_per_second",
+            "Data read from all block devices, in MB/s",
+        ),
+        "io_write_mbs" => (
+            "disk_write_mbytes_per_second",
+            "Data written to all block devices, in MB/s",
+        ),
+        "io_iops" => (
+            "disk_iops",
+            "Reads and writes completed on all block devices",
+        ),
+        "io_await" => (
+            "disk_await_max_milliseconds",
+            "Average request latency of the slowest block device",
+        ),
+        "io_util" => (
+            "disk_utilization_max_percent",
+            "Busy time of the busiest block device",
+        ),
+        "docker_running" => ("docker_containers_running", "Running containers"),
+        "docker_stopped" => (
+            "docker_containers_stopped",
+            "Containers that exist but aren't running",
+        ),
+        "docker_unhealthy" => (
+            "docker_containers_unhealthy",
+            "Containers failing their healthcheck",
+        ),
+        "systemd_failed" => ("systemd_units_failed", "Failed systemd units"),
+        "unit_failed" => (
+            "systemd_watched_units_failed",
+            "Failed systemd units among the watched ones",
+        ),
+        "cpu_user" => ("cpu_user_percent", "CPU time in user mode"),
+        "cpu_system" => ("cpu_system_percent", "CPU time in kernel mode"),
+        "cpu_iowait" => ("cpu_iowait_percent", "CPU time waiting for I/O"),
+        "cpu_steal" => ("cpu_steal_percent", "CPU time stolen by the hypervisor"),
+        "load1" => ("load1", "1-minute load average"),
+        "load5" => ("load5", "5-minute load average"),
+        "load15" => ("load15", "15-minute load average"),
+        "mem_used" => (
+            "memory_used_percent",
+            "Memory in use, not counting reclaimable page cache",
+        ),
+        "swap" => ("swap_used_percent", "Swap space in use"),
+        "psi_cpu" => (
+            "pressure_cpu_some_avg10_percent",
+            "Time some tasks were stalled on CPU over the last 10 seconds",
+        ),
+        "psi_memory" => (
+            "pressure_memory_some_avg10_percent",
+            "Time some tasks were stalled on memory over the last 10 seconds",
+        ),
+        "psi_memory_full" => (
+            "pressure_memory_full_avg10_percent",
+            "Time all tasks were stalled on memory over the last 10 seconds",
+        ),
+        "psi_io" => (
+            "pressure_io_some_avg10_percent",
+            "Time some tasks were stalled on I/O over the last 10 seconds",
+        ),
+        "temp" => (
+            "temperature_max_celsius",
+            "Temperature of the hottest sensor",
+        ),
+        "cpu_temp" => (
+            "cpu_temperature_celsius",
+            "Temperature of the hottest CPU sensor",
+        ),
+        "oom_kills" => (
+            "oom_kills_last_sample",
+            "Processes killed by the OOM killer since the previous sample",
+        ),
+        _ => ("unknown", ""),
+    }
+}
+
+type GpuReading = fn(&GpuDetail) -> Option<f64>;
+
+/// Gauge name, help text and reading for each per-GPU field
+const GPU_GAUGES: &[(&str, &str, GpuReading)] = &[
+    ("gpu_utilization_percent", "GPU utilization", |g| {
+        g.utilization
+    }),
+    ("gpu_memory_used_bytes", "GPU memory in use", |g| {
+        g.memory_used.map(|v| v as f64)
+    }),
+    ("gpu_memory_total_bytes", "GPU memory installed", |g| {
+        g.memory_total.map(|v| v as f64)
+    }),
+    ("gpu_temperature_celsius", "GPU core temperature", |g| {
+        g.temperature
+    }),
+    ("gpu_power_watts", "GPU power draw", |g| g.power_watts),
+    ("gpu_fan_speed_percent", "GPU fan speed", |g| g.fan_speed),
+    ("gpu_processes", "Processes running on the GPU", |g| {
+        g.processes.map(|v| v as f64)
+    }),
+];
+
+type FilesystemReading = fn(&FilesystemUsage) -> Option<f64>;
+
+/// Gauge name, help text and reading for each per-mount field
+const FILESYSTEM_GAUGES: &[(&str, &str, FilesystemReading)] = &[
+    ("filesystem_size_bytes", "Filesystem size", |f| {
+        Some(f.total_bytes as f64)
+    }),
+    ("filesystem_used_bytes", "Filesystem space in use", |f| {
+        Some(f.used_bytes as f64)
+    }),
+    ("filesystem_usage_percent", "Filesystem space usage", |f| {
+        Some(f.disk_usage)
+    }),
+    (
+        "filesystem_inode_usage_percent",
+        "Filesystem inode usage",
+        |f| f.inode_usage,
+    ),
+];
+
+type NetworkReading = fn(&NetworkUsage) -> f64;
+
+/// Gauge name, help text and reading for each per-interface rate
+const NETWORK_GAUGES: &[(&str, &str, NetworkReading)] = &[
+    ("network_receive_bytes_per_second", "Bytes received", |n| {
+        n.rx_bytes
+    }),
+    ("network_transmit_bytes_per_second", "Bytes sent", |n| {
+        n.tx_bytes
+    }),
+    (
+        "network_receive_packets_per_second",
+        "Packets received",
+        |n| n.rx_packets,
+    ),
+    ("network_transmit_packets_per_second", "Packets sent", |n| {
+        n.tx_packets
+    }),
+    ("network_receive_errors_per_second", "Receive errors", |n| {
+        n.rx_errors
+    }),
+    (
+        "network_transmit_errors_per_second",
+        "Transmit errors",
+        |n| n.tx_errors,
+    ),
+    (
+        "network_receive_drops_per_second",
+        "Received packets dropped",
+        |n| n.rx_drops,
+    ),
+    (
+        "network_transmit_drops_per_second",
+        "Outgoing packets dropped",
+        |n| n.tx_drops,
+    ),
+];
+
+type DiskIoReading = fn(&DiskIo) -> f64;
+
+/// Gauge name, help text and reading for each per-device I/O figure
+const DISK_IO_GAUGES: &[(&str, &str, DiskIoReading)] = &[
+    ("disk_read_bytes_per_second", "Bytes read", |d| d.read_bytes),
+    ("disk_written_bytes_per_second", "Bytes written", |d| {
+        d.write_bytes
+    }),
+    ("disk_reads_per_second", "Reads completed", |d| d.read_iops),
+    ("disk_writes_per_second", "Writes completed", |d| {
+        d.write_iops
+    }),
+    ("disk_await_milliseconds", "Average request latency", |d| {
+        d.await_ms
+    }),
+    (
+        "disk_utilization_percent",
+        "Time the device was busy",
+        |d| d.util,
+    ),
+];
+
+type ContainerReading = fn(&ContainerStats) -> Option<f64>;
+
+/// Gauge name, help text and reading for each per-container field
+const CONTAINER_GAUGES: &[(&str, &str, ContainerReading)] = &[
+    (
+        "container_running",
+        "Whether the container is running",
+        |c| Some(if c.state == "running" { 1.0 } else { 0.0 }),
+    ),
+    ("container_cpu_percent", "Container CPU usage", |c| {
+        c.cpu_percent
+    }),
+    (
+        "container_memory_used_bytes",
+        "Container memory in use",
+        |c| c.memory_used.map(|v| v as f64),
+    ),
+    (
+        "container_memory_limit_bytes",
+        "Container memory limit",
+        |c| c.memory_limit.map(|v| v as f64),
+    ),
+    (
+        "container_restarts",
+        "Times the container was restarted",
+        |c| Some(c.restart_count as f64),
+    ),
+];
+
+/// Prometheus scrape endpoint: the latest sample of every client as labelled
+/// gauges, plus the server's own ingestion, alerting and database metrics
+pub async fn metrics(
+    State(state): State<AppState>,
+) -> Result<([(header::HeaderName, &'static str); 1], String), StatusCode> {
+    let clients = state
+        .db
+        .get_all_clients()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let policy = OfflinePolicy::load(&state.db)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let query_started = Instant::now();
+    let latest = state
+        .db
+        .get_latest_metric_per_client()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let query_seconds = query_started.elapsed().as_secs_f64();
+    let containers = state
+        .db
+        .get_all_containers()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (db_size, _) = state
+        .db
+        .get_database_size()
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let hostnames: HashMap<&str, &str> = clients
+        .iter()
+        .map(|c| (c.id.as_str(), c.hostname.as_str()))
+        .collect();
+    let mut samples: HashMap<&str, &Metric> = HashMap::new();
+    for metric in &latest {
+        samples.entry(metric.client_id.as_str()).or_insert(metric);
+    }
+
+    let mut exp = Exposition::default();
+    let now = Utc::now();
+
+    // Fleet data
+    exp.family(
+        "client_online",
+        "gauge",
+        "Whether the client reported within its offline window",
+    );
+    for client in &clients {
+        let online = policy.status(client, now) == ClientStatus::Online;
+        exp.sample(
+            "client_online",
+            &[("client_id", &client.id), ("hostname", &client.hostname)],
+            if online { 1.0 } else { 0.0 },
+        );
+    }
+
+    exp.family(
+        "client_last_seen_timestamp_seconds",
+        "gauge",
+        "Unix time of the client's last report",
+    );
+    for client in &clients {
+        if let Some(last_seen) = alerts::parse_timestamp(&client.last_seen) {
+            exp.sample(
+                "client_last_seen_timestamp_seconds",
+                &[("client_id", &client.id), ("hostname", &client.hostname)],
+                last_seen.timestamp() as f64,
+            );
+        }
+    }
+
+    for metric_type in METRIC_TYPES {
+        let (name, help) = gauge_for(metric_type);
+        exp.family(name, "gauge", help);
+        for (client_id, metric) in &samples {
+            if let Some(value) = metric.value(metric_type) {
+                let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+                exp.sample(
+                    name,
+                    &[("client_id", client_id), ("hostname", hostname)],
+                    value,
+                );
+            }
+        }
+    }
+
+    exp.family(
+        "cpu_core_usage_percent",
+        "gauge",
+        "Per-core CPU usage of the latest sample",
+    );
+    for (client_id, metric) in &samples {
+        let cores = metric
+            .cpu
+            .as_ref()
+            .map(|c| c.cores.as_slice())
+            .unwrap_or_default();
+        let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+        for (core, value) in cores.iter().enumerate() {
+            exp.sample(
+                "cpu_core_usage_percent",
+                &[
+                    ("client_id", client_id),
+                    ("hostname", hostname),
+                    ("core", &core.to_string()),
+                ],
+                *value,
+            );
+        }
+    }
+
+    exp.family(
+        "oom_kills_total",
+        "counter",
+        "Processes killed by the OOM killer since boot",
+    );
+    for (client_id, metric) in &samples {
+        let total = metric.memory.as_ref().and_then(|m| m.oom_kills_total);
+        if let Some(total) = total {
+            let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+            exp.sample(
+                "oom_kills_total",
+                &[("client_id", client_id), ("hostname", hostname)],
+                total as f64,
+            );
+        }
+    }
+
+    exp.family(
+        "sensor_temperature_celsius",
+        "gauge",
+        "Temperature of each hwmon or thermal zone sensor",
+    );
+    for (client_id, metric) in &samples {
+        let temperatures = metric
+            .sensors
+            .as_ref()
+            .map(|s| s.temperatures.as_slice())
+            .unwrap_or_default();
+        let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+        for temperature in temperatures {
+            exp.sample(
+                "sensor_temperature_celsius",
+                &[
+                    ("client_id", client_id),
+                    ("hostname", hostname),
+                    ("chip", &temperature.chip),
+                    ("sensor", &temperature.label),
+                ],
+                temperature.celsius,
+            );
+        }
+    }
+
+    exp.family("fan_speed_rpm", "gauge", "Speed of each hwmon fan");
+    for (client_id, metric) in &samples {
+        let fans = metric
+            .sensors
+            .as_ref()
+            .map(|s| s.fans.as_slice())
+            .unwrap_or_default();
+        let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+        for fan in fans {
+            exp.sample(
+                "fan_speed_rpm",
+                &[
+                    ("client_id", client_id),
+                    ("hostname", hostname),
+                    ("chip", &fan.chip),
+                    ("fan", &fan.label),
+                ],
+                fan.rpm,
+            );
+        }
+    }
+
+    exp.family(
+        "systemd_unit_active",
+        "gauge",
+        "Whether a watched systemd unit is active, labelled with its state",
+    );
+    for (client_id, metric) in &samples {
+        let units = metric
+            .systemd
+            .as_ref()
+            .map(|s| s.watched.as_slice())
+            .unwrap_or_default();
+        let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+        for unit in units {
+            exp.sample(
+                "systemd_unit_active",
+                &[
+                    ("client_id", client_id),
+                    ("hostname", hostname),
+                    ("unit", &unit.name),
+                    ("state", &unit.active_state),
+                ],
+                if unit.active_state == "active" {
+                    1.0
+                } else {
+                    0.0
+                },
+            );
+        }
+    }
+
+    // Per-device GPU readings
+    for (name, help, read) in GPU_GAUGES {
+        exp.family(name, "gauge", help);
+        for (client_id, metric) in &samples {
+            let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+            for gpu in &metric.gpus {
+                if let Some(value) = read(gpu) {
+                    exp.sample(
+                        name,
+                        &[
+                            ("client_id", client_id),
+                            ("hostname", hostname),
+                            ("gpu", &gpu.index.to_string()),
+                            ("name", &gpu.name),
+                        ],
+                        value,
+                    );
+                }
+            }
+        }
+    }
+
+    // Per-mount filesystem readings
+    for (name, help, read) in FILESYSTEM_GAUGES {
+        exp.family(name, "gauge", help);
+        for (client_id, metric) in &samples {
+            let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+            for fs in &metric.filesystems {
+                if let Some(value) = read(fs) {
+                    exp.sample(
+                        name,
+                        &[
+                            ("client_id", client_id),
+                            ("hostname", hostname),
+                            ("mountpoint", &fs.mount_point),
+                            ("device", &fs.device),
+                            ("fstype", &fs.fs_type),
+                        ],
+                        value,
+                    );
+                }
+            }
+        }
+    }
+
+    // Per-interface network rates
+    for (name, help, read) in NETWORK_GAUGES {
+        exp.family(name, "gauge", help);
+        for (client_id, metric) in &samples {
+            let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+            for net in &metric.network {
+                exp.sample(
+                    name,
+                    &[
+                        ("client_id", client_id),
+                        ("hostname", hostname),
+                        ("interface", &net.interface),
+                    ],
+                    read(net),
+                );
+            }
+        }
+    }
+
+    // Per-device disk I/O
+    for (name, help, read) in DISK_IO_GAUGES {
+        exp.family(name, "gauge", help);
+        for (client_id, metric) in &samples {
+            let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+            for io in &metric.disk_io {
+                exp.sample(
+                    name,
+                    &[
+                        ("client_id", client_id),
+                        ("hostname", hostname),
+                        ("device", &io.device),
+                    ],
+                    read(io),
+                );
+            }
+        }
+    }
+
+    // Latest container list of each client
+    for (name, help, read) in CONTAINER_GAUGES {
+        exp.family(name, "gauge", help);
+        for container in &containers {
+            let client_id = container.client_id.as_str();
+            let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+            if let Some(value) = read(&container.stats) {
+                exp.sample(
+                    name,
+                    &[
+                        ("client_id", client_id),
+                        ("hostname", hostname),
+                        ("container", &container.stats.name),
+                        ("image", &container.stats.image),
+                    ],
+                    value,
+                );
+            }
+        }
+    }
+
+    // Series reported by name, under `custom_` so they can't clash with ours
+    let mut series: BTreeMap<&str, Vec<(&str, &SeriesValue)>> = BTreeMap::new();
+    for (client_id, metric) in &samples {
+        for value in &metric.values {
+            series
+                .entry(&value.name)
+                .or_default()
+                .push((client_id, value));
+        }
+    }
+    for (name, values) in series {
+        let name = format!("custom_{name}");
+        exp.family(&name, "gauge", "Series reported by the agent");
+        for (client_id, value) in values {
+            let hostname = hostnames.get(client_id).copied().unwrap_or_default();
+            let mut labels = vec![("client_id", client_id), ("hostname", hostname)];
+            labels.extend(value.labels.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+            exp.sample(&name, &labels, value.value);
+        }
+    }
+
+    // Server self-metrics
+    let telemetry = &state.telemetry;
+    exp.metric(
+        "start_time_seconds",
+        "gauge",
+        "Unix time the server started",
+        telemetry.start_time as f64,
+    );
+    exp.metric(
+        "clients",
+        "gauge",
+        "Registered clients",
+        clients.len() as f64,
+    );
+    exp.metric(
+        "batches_received_total",
+        "counter",
+        "Metric batches accepted on /api/report",
+        telemetry.batches_received.load(Ordering::Relaxed) as f64,
+    );
+    exp.metric(
+        "samples_ingested_total",
+        "counter",
+        "Samples stored from all clients",
+        telemetry.samples_ingested.load(Ordering::Relaxed) as f64,
+    );
+    exp.histogram(
+        "batch_size",
+        "Samples per accepted batch",
+        &telemetry.batch_size,
+    );
+    exp.histogram(
+        "db_insert_duration_seconds",
+        "Time spent storing a batch",
+        &telemetry.db_insert_seconds,
+    );
+    exp.metric(
+        "db_query_duration_seconds",
+        "gauge",
+        "Time the latest-sample query took during this scrape",
+        query_seconds,
+    );
+    exp.metric(
+        "db_size_bytes",
+        "gauge",
+        "Size of the database file",
+        db_size as f64,
+    );
+    exp.metric(
+        "alert_events_total",
+        "counter",
+        "Alert transitions raised by metric rules",
+        telemetry.alert_events.load(Ordering::Relaxed) as f64,
+    );
+    exp.metric(
+        "alert_queue_depth",
+        "gauge",
+        "Alert events waiting for the notification worker",
+        (state.alert_tx.max_capacity() - state.alert_tx.capacity()) as f64,
+    );
+    exp.metric(
+        "live_subscribers",
+        "gauge",
+        "Open live feed WebSocket connections",
+        state.live_tx.receiver_count() as f64,
+    );
+
+    Ok((
+        [(header::CONTENT_TYPE, Exposition::CONTENT_TYPE)],
+        exp.finish(),
+    ))
+}
@@ -6,15 +6,18 @@ use axum::{
 use std::collections::HashMap;
 
 use crate::{
-    db,
-    models::{AlertRule, AlertRuleInput},
+    models::{
+        accepts_target, is_builtin, is_valid_name, parse_selector, AlertRule, AlertRuleInput,
+    },
     AppState,
 };
 
 pub async fn get_settings(
     State(state): State<AppState>,
 ) -> Result<Json<HashMap<String, String>>, StatusCode> {
-    let settings = db::get_all_settings(&state.db)
+    let settings = state
+        .db
+        .get_all_settings()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -28,7 +31,9 @@ pub async fn update_settings(
     Json(settings): Json<HashMap<String, String>>,
 ) -> Result<StatusCode, StatusCode> {
     for (key, value) in settings {
-        db::set_setting(&state.db, &key, &value)
+        state
+            .db
+            .set_setting(&key, &value)
             .await
             .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     }
@@ -36,10 +41,64 @@ pub async fn update_settings(
     Ok(StatusCode::OK)
 }
 
+/// Per-client overrides of global settings (e.g. `retention_days`)
+pub async fn get_client_settings(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+) -> Result<Json<HashMap<String, String>>, StatusCode> {
+    state
+        .db
+        .get_client_by_id(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let settings = state
+        .db
+        .get_client_settings(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(Json(
+        settings.into_iter().map(|s| (s.key, s.value)).collect(),
+    ))
+}
+
+/// Set client overrides; an empty value removes the override
+pub async fn update_client_settings(
+    State(state): State<AppState>,
+    Path(client_id): Path<String>,
+    Json(settings): Json<HashMap<String, String>>,
+) -> Result<StatusCode, StatusCode> {
+    state
+        .db
+        .get_client_by_id(&client_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    for (key, value) in settings {
+        let result = if value.is_empty() {
+            state
+                .db
+                .delete_client_setting(&client_id, &key)
+                .await
+                .map(|_| ())
+        } else {
+            state.db.set_client_setting(&client_id, &key, &value).await
+        };
+        result.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+
+    Ok(StatusCode::OK)
+}
+
 pub async fn get_alert_rules(
     State(state): State<AppState>,
 ) -> Result<Json<Vec<AlertRule>>, StatusCode> {
-    let rules = db::get_alert_rules(&state.db)
+    let rules = state
+        .db
+        .get_alert_rules()
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -50,7 +109,22 @@ pub async fn create_alert_rule(
     State(state): State<AppState>,
     Json(input): Json<AlertRuleInput>,
 ) -> Result<(StatusCode, Json<AlertRule>), StatusCode> {
-    let rule = db::create_alert_rule(&state.db, &input)
+    if !is_valid_name(&input.metric_type) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(target) = &input.target {
+        if !accepts_target(&input.metric_type) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        // Rules on reported series target a label selector
+        if !is_builtin(&input.metric_type) && parse_selector(target).is_none() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    let rule = state
+        .db
+        .create_alert_rule(&input)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
@@ -61,11 +135,19 @@ pub async fn delete_alert_rule(
     State(state): State<AppState>,
     Path(id): Path<i64>,
 ) -> Result<StatusCode, StatusCode> {
-    let deleted = db::delete_alert_rule(&state.db, id)
+    let deleted = state
+        .db
+        .delete_alert_rule(id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
     if deleted {
+        // Drop any alert state tracked for the removed rule
+        state
+            .alert_states
+            .lock()
+            .await
+            .retain(|(_, rule_id), _| *rule_id != id);
         Ok(StatusCode::NO_CONTENT)
     } else {
         Err(StatusCode::NOT_FOUND)
@@ -1,8 +1,14 @@
-use std::time::Duration;
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, time::Duration};
 use tokio::sync::mpsc;
 use tracing::{error, info};
 
-use crate::{db, models::AlertRule, DbPool};
+use crate::{
+    alerts::{self, AlertEvent, OfflinePolicy, OFFLINE_METRIC},
+    models::{self, ProcessInfo},
+    notify::{self, Notification, NotificationStatus, Notifier, SlackNotifier},
+    retention, rollups, DbPool,
+};
 
 pub async fn start_cleanup_task(pool: DbPool) {
     let mut interval = tokio::time::interval(Duration::from_secs(3600)); // Every hour
@@ -10,10 +16,13 @@ pub async fn start_cleanup_task(pool: DbPool) {
     loop {
         interval.tick().await;
 
-        match db::delete_old_metrics(&pool, 7).await {
-            Ok(deleted) => {
-                if deleted > 0 {
-                    info!("Cleanup task: deleted {} old metrics", deleted);
+        match retention::run_cleanup(&pool, Utc::now()).await {
+            Ok(report) => {
+                if report.metrics_deleted > 0 || report.rollups_deleted > 0 {
+                    info!(
+                        "Cleanup task: deleted {} old metrics and {} old rollups",
+                        report.metrics_deleted, report.rollups_deleted
+                    );
                 }
             }
             Err(e) => {
@@ -23,57 +32,485 @@ pub async fn start_cleanup_task(pool: DbPool) {
     }
 }
 
-pub async fn start_alert_worker(
-    pool: DbPool,
-    mut rx: mpsc::Receiver<(String, AlertRule, f64)>,
-) {
-    while let Some((client_id, rule, value)) = rx.recv().await {
-        // Get Slack webhook URL
-        let webhook_url = match db::get_setting(&pool, "slack_webhook_url").await {
-            Ok(Some(url)) if !url.is_empty() => url,
-            _ => continue,
+pub async fn start_rollup_task(pool: DbPool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+
+    loop {
+        interval.tick().await;
+
+        let now = Utc::now();
+        for tier in rollups::TIERS {
+            if let Err(e) = rollups::rollup_tier(&pool, *tier, now).await {
+                error!("Rollup task failed for {}: {}", tier.name, e);
+            }
+        }
+    }
+}
+
+pub async fn start_offline_monitor(pool: DbPool, alert_tx: mpsc::Sender<AlertEvent>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(15));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = check_offline_clients(&pool, &alert_tx, Utc::now()).await {
+            error!("Offline monitor failed: {}", e);
+        }
+    }
+}
+
+async fn check_offline_clients(
+    pool: &DbPool,
+    alert_tx: &mpsc::Sender<AlertEvent>,
+    now: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let policy = OfflinePolicy::load(pool).await?;
+    let open: HashMap<String, String> = pool
+        .get_open_incidents(OFFLINE_METRIC)
+        .await?
+        .into_iter()
+        .map(|i| (i.client_id, i.started_at))
+        .collect();
+
+    for client in pool.get_all_clients().await? {
+        let rule_id = policy.rule_for(&client.id).map(|r| r.id);
+
+        match (policy.offline_since(&client, now), open.get(&client.id)) {
+            (Some(last_seen), None) => {
+                alert_tx
+                    .send(AlertEvent::Offline {
+                        client_id: client.id.clone(),
+                        rule_id,
+                        window_sec: policy.window_for(&client.id),
+                        last_seen,
+                    })
+                    .await?;
+            }
+            (None, Some(started_at)) => {
+                let offline_since = alerts::parse_timestamp(started_at).unwrap_or(now);
+                let back_at = alerts::parse_timestamp(&client.last_seen).unwrap_or(now);
+                alert_tx
+                    .send(AlertEvent::Online {
+                        client_id: client.id.clone(),
+                        rule_id,
+                        offline_since,
+                        back_at,
+                    })
+                    .await?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn start_alert_worker(pool: DbPool, mut rx: mpsc::Receiver<AlertEvent>) {
+    while let Some(event) = rx.recv().await {
+        // Record the incident before notifying so postmortems don't depend on Slack
+        let notify = match record_incident(&pool, &event).await {
+            Ok(notify) => notify,
+            Err(e) => {
+                error!("Failed to record incident: {}", e);
+                true
+            }
         };
 
+        if !notify {
+            continue;
+        }
+
+        let client_id = event.client_id();
+        let notifiers = rule_notifiers(&pool, event.rule_id()).await;
+        if notifiers.is_empty() {
+            continue;
+        }
+
         // Get client hostname
-        let hostname = match db::get_client_by_id(&pool, &client_id).await {
+        let hostname = match pool.get_client_by_id(client_id).await {
             Ok(Some(client)) => client.hostname,
-            _ => client_id.clone(),
+            _ => client_id.to_string(),
         };
 
-        // Send Slack notification
-        let message = format!(
-            "🚨 *Alert*: {} on `{}` is at {:.1}% (threshold: {:.1}%)",
-            rule.metric_type.to_uppercase(),
-            hostname,
+        let processes = top_offenders(&pool, &event).await;
+        let notification = build_notification(&event, &hostname, processes);
+
+        for (name, notifier) in notifiers {
+            if let Err(e) = notifier.send(&notification).await {
+                error!("Failed to send notification via {}: {}", name, e);
+            } else {
+                info!(
+                    "Sent {} via {} for {} on {}",
+                    notification.title,
+                    name,
+                    event.metric_type(),
+                    hostname
+                );
+            }
+        }
+    }
+}
+
+/// Notifiers attached to a rule, falling back to the global Slack webhook
+/// setting for rules without any channels.
+async fn rule_notifiers(pool: &DbPool, rule_id: Option<i64>) -> Vec<(String, Box<dyn Notifier>)> {
+    let channels = match rule_id {
+        Some(id) => pool.get_channels_for_rule(id).await.unwrap_or_default(),
+        None => vec![],
+    };
+
+    if channels.is_empty() {
+        return match pool.get_setting("slack_webhook_url").await {
+            Ok(Some(url)) if !url.is_empty() => {
+                vec![("slack".to_string(), Box::new(SlackNotifier::new(url)) as _)]
+            }
+            _ => vec![],
+        };
+    }
+
+    channels
+        .into_iter()
+        .filter_map(|channel| match notify::build_notifier(&channel) {
+            Ok(notifier) => Some((channel.name, notifier)),
+            Err(e) => {
+                error!("Invalid notification channel {}: {}", channel.name, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Persist the incident for an alert event. Returns whether a notification
+/// should go out: re-firing an already open incident, or resolving one that
+/// was never opened, is not sent again.
+async fn record_incident(pool: &DbPool, event: &AlertEvent) -> anyhow::Result<bool> {
+    match event {
+        AlertEvent::Fired {
+            client_id,
+            rule,
             value,
-            rule.threshold
-        );
+            started_at,
+        } => {
+            let opened = pool
+                .open_incident(
+                    client_id,
+                    Some(rule.id),
+                    &rule.metric_type,
+                    rule.threshold,
+                    &started_at.to_rfc3339(),
+                    *value,
+                )
+                .await?;
+            Ok(opened.is_some())
+        }
+        AlertEvent::Resolved {
+            client_id,
+            rule,
+            peak,
+            ended_at,
+            ..
+        } => {
+            pool.resolve_incident(
+                client_id,
+                Some(rule.id),
+                &rule.metric_type,
+                &ended_at.to_rfc3339(),
+                *peak,
+            )
+            .await
+        }
+        AlertEvent::Offline {
+            client_id,
+            window_sec,
+            last_seen,
+            ..
+        } => {
+            let opened = pool
+                .open_incident(
+                    client_id,
+                    None,
+                    OFFLINE_METRIC,
+                    *window_sec as f64,
+                    &last_seen.to_rfc3339(),
+                    0.0,
+                )
+                .await?;
+            Ok(opened.is_some())
+        }
+        AlertEvent::Online {
+            client_id,
+            offline_since,
+            back_at,
+            ..
+        } => {
+            // Peak of an offline incident is how long the host was silent
+            let silent_sec = (*back_at - *offline_since).num_seconds() as f64;
+            pool.resolve_incident(
+                client_id,
+                None,
+                OFFLINE_METRIC,
+                &back_at.to_rfc3339(),
+                silent_sec,
+            )
+            .await
+        }
+    }
+}
+
+/// Processes named in a firing notification
+const TOP_OFFENDERS: usize = 5;
+/// Snapshots older than this when an alert fires no longer say who's to blame
+const SNAPSHOT_MAX_AGE_SECS: i64 = 120;
+
+/// The likely culprits of a firing metric alert, from the client's latest
+/// process snapshot
+async fn top_offenders(pool: &DbPool, event: &AlertEvent) -> Vec<ProcessInfo> {
+    let AlertEvent::Fired {
+        client_id, rule, ..
+    } = event
+    else {
+        return vec![];
+    };
+
+    let snapshot = match pool.get_process_snapshots(client_id, Some(1)).await {
+        Ok(snapshots) => snapshots.into_iter().next(),
+        Err(e) => {
+            error!("Failed to load process snapshot: {}", e);
+            None
+        }
+    };
+    snapshot
+        .filter(|s| {
+            alerts::parse_timestamp(&s.timestamp)
+                .is_some_and(|at| (Utc::now() - at).num_seconds() <= SNAPSHOT_MAX_AGE_SECS)
+        })
+        .map(|s| s.top_offenders(&rule.metric_type, TOP_OFFENDERS))
+        .unwrap_or_default()
+}
+
+fn process_lines(processes: &[ProcessInfo]) -> String {
+    if processes.is_empty() {
+        return String::new();
+    }
+    let lines: Vec<String> = processes
+        .iter()
+        .map(|p| {
+            format!(
+                "• `{}` (pid {}{}): {:.1}% CPU, {:.0} MB",
+                p.name,
+                p.pid,
+                p.user
+                    .as_ref()
+                    .map(|u| format!(", {}", u))
+                    .unwrap_or_default(),
+                p.cpu_percent,
+                p.rss_bytes as f64 / 1_000_000.0,
+            )
+        })
+        .collect();
+    format!("\nTop processes:\n{}", lines.join("\n"))
+}
 
-        if let Err(e) = send_slack_notification(&webhook_url, &message).await {
-            error!("Failed to send Slack notification: {}", e);
-        } else {
-            info!("Sent alert for {} on {}: {:.1}%", rule.metric_type, hostname, value);
+fn build_notification(
+    event: &AlertEvent,
+    hostname: &str,
+    processes: Vec<ProcessInfo>,
+) -> Notification {
+    match event {
+        AlertEvent::Fired {
+            client_id,
+            rule,
+            value,
+            started_at,
+        } => Notification {
+            status: NotificationStatus::Firing,
+            client_id: client_id.clone(),
+            hostname: hostname.to_string(),
+            metric_type: rule.metric_type.clone(),
+            value: *value,
+            threshold: rule.threshold,
+            peak: None,
+            started_at: started_at.to_rfc3339(),
+            ended_at: None,
+            title: format!(
+                "[FIRING] {} on {}",
+                rule.label(),
+                hostname
+            ),
+            text: format!(
+                "🚨 *Alert*: {} on `{}` is at {:.1}{unit} (threshold: {:.1}{unit}){}",
+                rule.label(),
+                hostname,
+                value,
+                rule.threshold,
+                process_lines(&processes),
+                unit = models::unit(&rule.metric_type),
+            ),
+            processes,
+        },
+        AlertEvent::Resolved {
+            client_id,
+            rule,
+            value,
+            peak,
+            started_at,
+            ended_at,
+        } => Notification {
+            status: NotificationStatus::Resolved,
+            client_id: client_id.clone(),
+            hostname: hostname.to_string(),
+            metric_type: rule.metric_type.clone(),
+            value: *value,
+            threshold: rule.threshold,
+            peak: Some(*peak),
+            started_at: started_at.to_rfc3339(),
+            ended_at: Some(ended_at.to_rfc3339()),
+            processes: vec![],
+            title: format!(
+                "[RESOLVED] {} on {}",
+                rule.label(),
+                hostname
+            ),
+            text: format!(
+                "✅ *Resolved*: {} on `{}` is back at {:.1}{unit} (threshold: {:.1}{unit}, peak: {:.1}{unit}, lasted {}m)",
+                rule.label(),
+                hostname,
+                value,
+                rule.threshold,
+                peak,
+                (*ended_at - *started_at).num_minutes(),
+                unit = models::unit(&rule.metric_type),
+            ),
+        },
+        AlertEvent::Offline {
+            client_id,
+            window_sec,
+            last_seen,
+            ..
+        } => Notification {
+            status: NotificationStatus::Firing,
+            client_id: client_id.clone(),
+            hostname: hostname.to_string(),
+            metric_type: OFFLINE_METRIC.to_string(),
+            value: (Utc::now() - *last_seen).num_seconds() as f64,
+            threshold: *window_sec as f64,
+            peak: None,
+            started_at: last_seen.to_rfc3339(),
+            ended_at: None,
+            processes: vec![],
+            title: format!("[OFFLINE] {}", hostname),
+            text: format!(
+                "🔌 *Offline*: `{}` has not reported since {}",
+                hostname,
+                last_seen.format("%Y-%m-%d %H:%M:%S UTC")
+            ),
+        },
+        AlertEvent::Online {
+            client_id,
+            offline_since,
+            back_at,
+            ..
+        } => {
+            let silent_sec = (*back_at - *offline_since).num_seconds() as f64;
+            Notification {
+                status: NotificationStatus::Resolved,
+                client_id: client_id.clone(),
+                hostname: hostname.to_string(),
+                metric_type: OFFLINE_METRIC.to_string(),
+                value: 0.0,
+                threshold: 0.0,
+                peak: Some(silent_sec),
+                started_at: offline_since.to_rfc3339(),
+                ended_at: Some(back_at.to_rfc3339()),
+                processes: vec![],
+                title: format!("[ONLINE] {}", hostname),
+                text: format!(
+                    "✅ *Online*: `{}` is reporting again after {}m",
+                    hostname,
+                    (*back_at - *offline_since).num_minutes()
+                ),
+            }
         }
     }
 }
 
-async fn send_slack_notification(webhook_url: &str, message: &str) -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
 
-    let payload = serde_json::json!({
-        "text": message,
-        "mrkdwn": true
-    });
+    /// Run one offline check, recording each event as the alert worker would
+    async fn check(pool: &DbPool, now: DateTime<Utc>) -> Vec<AlertEvent> {
+        let (tx, mut rx) = mpsc::channel(16);
+        check_offline_clients(pool, &tx, now).await.unwrap();
+        drop(tx);
 
-    let response = client
-        .post(webhook_url)
-        .json(&payload)
-        .send()
-        .await?;
+        let mut events = vec![];
+        while let Some(event) = rx.recv().await {
+            record_incident(pool, &event).await.unwrap();
+            events.push(event);
+        }
+        events
+    }
 
-    if !response.status().is_success() {
-        anyhow::bail!("Slack API returned status: {}", response.status());
+    #[tokio::test]
+    async fn test_offline_fires_once_per_outage_and_resolves() {
+        let db = crate::db::memory().await;
+        let client = db.create_client("web-1").await.unwrap();
+        db.update_client_last_seen(&client.id, Some("1.0"))
+            .await
+            .unwrap();
+        let last_seen = alerts::parse_timestamp(
+            &db.get_client_by_id(&client.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .last_seen,
+        )
+        .unwrap();
+
+        assert!(check(&db, last_seen + Duration::seconds(30))
+            .await
+            .is_empty());
+
+        let events = check(&db, last_seen + Duration::seconds(90)).await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(
+            &events[0],
+            AlertEvent::Offline { client_id, window_sec: 60, .. } if *client_id == client.id
+        ));
+        assert_eq!(
+            db.get_open_incidents(OFFLINE_METRIC).await.unwrap().len(),
+            1
+        );
+
+        // The open incident keeps later checks quiet for the same outage
+        assert!(check(&db, last_seen + Duration::seconds(150))
+            .await
+            .is_empty());
+
+        db.update_client_last_seen(&client.id, None).await.unwrap();
+        let events = check(&db, Utc::now()).await;
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], AlertEvent::Online { .. }));
+        assert!(db
+            .get_open_incidents(OFFLINE_METRIC)
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(check(&db, Utc::now()).await.is_empty());
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_never_reported_client_is_not_offline() {
+        let db = crate::db::memory().await;
+        let client = db.create_client("provisioning").await.unwrap();
+
+        assert!(check(&db, Utc::now() + Duration::days(1)).await.is_empty());
+
+        // Pushed metrics (remote_write, OTLP) carry no agent version but count
+        db.update_client_last_seen(&client.id, None).await.unwrap();
+        let events = check(&db, Utc::now() + Duration::days(1)).await;
+        assert!(matches!(&events[..], [AlertEvent::Offline { .. }]));
+    }
 }
@@ -0,0 +1,194 @@
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::Utc;
+
+/// Prometheus naming prefix for everything this server exports
+pub const PREFIX: &str = "status_monitor";
+
+const BATCH_SIZE_BUCKETS: &[f64] = &[1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0, 1200.0];
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+/// Server self-metrics, updated on the hot paths and rendered by `/metrics`
+pub struct Telemetry {
+    pub start_time: i64,
+    pub batches_received: AtomicU64,
+    pub samples_ingested: AtomicU64,
+    pub alert_events: AtomicU64,
+    pub batch_size: Histogram,
+    pub db_insert_seconds: Histogram,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            start_time: Utc::now().timestamp(),
+            batches_received: AtomicU64::new(0),
+            samples_ingested: AtomicU64::new(0),
+            alert_events: AtomicU64::new(0),
+            batch_size: Histogram::new(BATCH_SIZE_BUCKETS),
+            db_insert_seconds: Histogram::new(LATENCY_BUCKETS),
+        }
+    }
+}
+
+impl Telemetry {
+    pub fn record_batch(&self, samples: usize, insert_time: Duration) {
+        self.batches_received.fetch_add(1, Ordering::Relaxed);
+        self.samples_ingested
+            .fetch_add(samples as u64, Ordering::Relaxed);
+        self.batch_size.observe(samples as f64);
+        self.db_insert_seconds.observe(insert_time.as_secs_f64());
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+struct HistogramData {
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Cumulative histogram with fixed upper bounds
+pub struct Histogram {
+    bounds: &'static [f64],
+    data: Mutex<HistogramData>,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            data: Mutex::new(HistogramData {
+                counts: vec![0; bounds.len()],
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn observe(&self, value: f64) {
+        let mut data = self.data.lock().unwrap_or_else(|e| e.into_inner());
+        for (i, bound) in self.bounds.iter().enumerate() {
+            if value <= *bound {
+                data.counts[i] += 1;
+            }
+        }
+        data.sum += value;
+        data.count += 1;
+    }
+}
+
+/// Prometheus text exposition format (version 0.0.4) writer
+#[derive(Default)]
+pub struct Exposition {
+    out: String,
+}
+
+impl Exposition {
+    pub const CONTENT_TYPE: &'static str = "text/plain; version=0.0.4; charset=utf-8";
+
+    /// Start a metric family; samples for it must follow before the next one
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.out, "# HELP {}_{} {}", PREFIX, name, help);
+        let _ = writeln!(self.out, "# TYPE {}_{} {}", PREFIX, name, kind);
+    }
+
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let _ = write!(self.out, "{}_{}", PREFIX, name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+                .collect();
+            let _ = write!(self.out, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.out, " {}", format_value(value));
+    }
+
+    /// Single unlabelled gauge or counter
+    pub fn metric(&mut self, name: &str, kind: &str, help: &str, value: f64) {
+        self.family(name, kind, help);
+        self.sample(name, &[], value);
+    }
+
+    pub fn histogram(&mut self, name: &str, help: &str, histogram: &Histogram) {
+        let data = histogram
+            .data
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+
+        self.family(name, "histogram", help);
+        let bucket = format!("{}_bucket", name);
+        for (bound, count) in histogram.bounds.iter().zip(&data.counts) {
+            self.sample(&bucket, &[("le", &format_value(*bound))], *count as f64);
+        }
+        self.sample(&bucket, &[("le", "+Inf")], data.count as f64);
+        self.sample(&format!("{}_sum", name), &[], data.sum);
+        self.sample(&format!("{}_count", name), &[], data.count as f64);
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposition_escapes_labels() {
+        let mut exp = Exposition::default();
+        exp.family("cpu_usage_percent", "gauge", "CPU usage");
+        exp.sample("cpu_usage_percent", &[("hostname", "web \"1\"\\a")], 12.5);
+
+        assert_eq!(
+            exp.finish(),
+            "# HELP status_monitor_cpu_usage_percent CPU usage\n\
+             # TYPE status_monitor_cpu_usage_percent gauge\n\
+             status_monitor_cpu_usage_percent{hostname=\"web \\\"1\\\"\\\\a\"} 12.5\n"
+        );
+    }
+
+    #[test]
+    fn test_histogram_is_cumulative() {
+        let histogram = Histogram::new(&[1.0, 10.0]);
+        for value in [0.5, 5.0, 50.0] {
+            histogram.observe(value);
+        }
+
+        let mut exp = Exposition::default();
+        exp.histogram("batch_size", "Samples per batch", &histogram);
+        let text = exp.finish();
+
+        assert!(text.contains("status_monitor_batch_size_bucket{le=\"1\"} 1\n"));
+        assert!(text.contains("status_monitor_batch_size_bucket{le=\"10\"} 2\n"));
+        assert!(text.contains("status_monitor_batch_size_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("status_monitor_batch_size_sum 55.5\n"));
+        assert!(text.contains("status_monitor_batch_size_count 3\n"));
+    }
+}
@@ -0,0 +1,37 @@
+import { NextRequest } from 'next/server';
+
+// Read at request time on the server, so the key never reaches the browser
+const API_URL = process.env.API_URL || 'http://localhost:8080';
+const API_KEY = process.env.API_KEY;
+
+type Context = { params: Promise<{ path: string[] }> };
+
+// Forward /api/* to the backend with the dashboard's API key attached
+async function proxy(request: NextRequest, { params }: Context): Promise<Response> {
+  const { path } = await params;
+  const url = `${API_URL}/api/${path.map(encodeURIComponent).join('/')}${request.nextUrl.search}`;
+
+  const headers: Record<string, string> = {};
+  const contentType = request.headers.get('content-type');
+  if (contentType) {
+    headers['Content-Type'] = contentType;
+  }
+  if (API_KEY) {
+    headers.Authorization = `Bearer ${API_KEY}`;
+  }
+
+  const hasBody = request.method !== 'GET' && request.method !== 'HEAD';
+  const response = await fetch(url, {
+    method: request.method,
+    headers,
+    body: hasBody ? await request.arrayBuffer() : undefined,
+    cache: 'no-store',
+  });
+
+  return new Response(response.body, {
+    status: response.status,
+    headers: { 'Content-Type': response.headers.get('content-type') ?? 'application/json' },
+  });
+}
+
+export { proxy as GET, proxy as POST, proxy as PUT, proxy as DELETE };
@@ -9,7 +9,7 @@ import { getClient, getMetrics, getStats, getLatestMetrics } from '@/lib/api';
 import { MetricGauge } from '@/components/metric-gauge';
 import { HistoryChart } from '@/components/history-chart';
 import { StatsTable } from '@/components/stats-table';
-import { formatTimeAgo, formatBytes, isClientOnline, cn } from '@/lib/utils';
+import { formatTimeAgo, formatBytes, getClientStatusBgColor, cn } from '@/lib/utils';
 
 type Tab = 'live' | 'history' | 'stats';
 type TimeRange = 12 | 24 | 168; // hours
@@ -63,8 +63,6 @@ export default function ClientDetailPage() {
     );
   }
 
-  const online = isClientOnline(client.last_seen);
-
   return (
     <div>
       {/* Header */}
@@ -81,7 +79,7 @@ export default function ClientDetailPage() {
           <div className="flex items-center gap-3">
             <div className={cn(
               'w-3 h-3 rounded-full',
-              online ? 'bg-green-500' : 'bg-red-500'
+              getClientStatusBgColor(client.status)
             )} />
             <h1 className="text-2xl font-bold text-white">{client.hostname}</h1>
           </div>
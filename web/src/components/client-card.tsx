@@ -4,7 +4,7 @@ import Link from 'next/link';
 import { Cpu, HardDrive, MemoryStick, Gpu } from 'lucide-react';
 import { Client, Metric } from '@/types';
 import { Sparkline } from './sparkline';
-import { formatPercentage, formatTimeAgo, isClientOnline, cn } from '@/lib/utils';
+import { formatPercentage, formatTimeAgo, getClientStatusBgColor, cn } from '@/lib/utils';
 
 interface ClientCardProps {
   client: Client;
@@ -13,7 +13,6 @@ interface ClientCardProps {
 }
 
 export function ClientCard({ client, latestMetric, recentMetrics }: ClientCardProps) {
-  const online = isClientOnline(client.last_seen);
   const cpuData = recentMetrics.map(m => m.cpu_usage);
   const ramData = recentMetrics.map(m => m.ram_usage);
 
@@ -24,7 +23,7 @@ export function ClientCard({ client, latestMetric, recentMetrics }: ClientCardPr
           <div className="flex items-center gap-2">
             <div className={cn(
               'w-2 h-2 rounded-full',
-              online ? 'bg-green-500' : 'bg-red-500'
+              getClientStatusBgColor(client.status)
             )} />
             <h3 className="font-medium text-white truncate">{client.hostname}</h3>
           </div>
@@ -1,9 +1,9 @@
 import { Client, Metric, Stats, AlertRule } from '@/types';
 
-const API_URL = process.env.NEXT_PUBLIC_API_URL || 'http://localhost:8080';
-
+// Requests go through the dashboard's own /api route, which adds the API key
+// on the server (src/app/api/[...path]/route.ts)
 async function fetchAPI<T>(endpoint: string, options?: RequestInit): Promise<T> {
-  const response = await fetch(`${API_URL}${endpoint}`, {
+  const response = await fetch(endpoint, {
     ...options,
     headers: {
       'Content-Type': 'application/json',
@@ -35,7 +35,7 @@ export async function createClient(hostname: string): Promise<{ id: string; host
 }
 
 export async function deleteClient(id: string): Promise<void> {
-  await fetch(`${API_URL}/api/clients/${id}`, { method: 'DELETE' });
+  await fetch(`/api/clients/${id}`, { method: 'DELETE' });
 }
 
 // Metrics
@@ -60,7 +60,7 @@ export async function getSettings(): Promise<Record<string, string>> {
 }
 
 export async function updateSettings(settings: Record<string, string>): Promise<void> {
-  await fetch(`${API_URL}/api/settings`, {
+  await fetch('/api/settings', {
     method: 'POST',
     headers: { 'Content-Type': 'application/json' },
     body: JSON.stringify(settings),
@@ -80,5 +80,5 @@ export async function createAlertRule(rule: Omit<AlertRule, 'id'>): Promise<Aler
 }
 
 export async function deleteAlertRule(id: number): Promise<void> {
-  await fetch(`${API_URL}/api/alerts/${id}`, { method: 'DELETE' });
+  await fetch(`/api/alerts/${id}`, { method: 'DELETE' });
 }
@@ -1,4 +1,5 @@
 import { formatDistanceToNow, parseISO } from 'date-fns';
+import { Client } from '@/types';
 
 export function formatBytes(bytes: number | null | undefined): string {
   if (bytes === null || bytes === undefined) return 'N/A';
@@ -40,6 +41,13 @@ export function getStatusBgColor(value: number, thresholds = { warning: 70, crit
   return 'bg-green-500';
 }
 
+export function getClientStatusBgColor(status: Client['status']): string {
+  if (status === 'online') return 'bg-green-500';
+  if (status === 'offline') return 'bg-red-500';
+  // Registered but hasn't reported yet
+  return 'bg-gray-500';
+}
+
 export function isClientOnline(lastSeen: string, thresholdSeconds = 60): boolean {
   try {
     const lastSeenDate = parseISO(lastSeen);
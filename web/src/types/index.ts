@@ -3,6 +3,8 @@ export interface Client {
   hostname: string;
   last_seen: string;
   version: string | null;
+  status: 'pending' | 'online' | 'offline';
+  offline_since: string | null;
 }
 
 export interface Metric {
@@ -14,16 +16,196 @@ export interface Metric {
   inode_usage: number;
   docker_sz: number | null;
   gpu_usage: number | null;
+  cpu: CpuDetail | null;
+  memory: MemoryDetail | null;
+  gpus: GpuDetail[];
+  filesystems: FilesystemUsage[];
+  network: NetworkUsage[];
+  disk_io: DiskIo[];
+  docker: DockerSummary | null;
+  systemd: SystemdStatus | null;
+  sensors: SensorReadings | null;
+  values: SeriesValue[];
   timestamp: string;
 }
 
+export interface SeriesValue {
+  name: string;
+  labels: Record<string, string>;
+  value: number;
+}
+
+export interface SeriesSample extends SeriesValue {
+  timestamp: string;
+}
+
+export interface NetworkUsage {
+  interface: string;
+  rx_bytes: number;
+  tx_bytes: number;
+  rx_packets: number;
+  tx_packets: number;
+  rx_errors: number;
+  tx_errors: number;
+  rx_drops: number;
+  tx_drops: number;
+}
+
+export interface ProcessInfo {
+  pid: number;
+  name: string;
+  user: string | null;
+  cpu_percent: number;
+  rss_bytes: number;
+}
+
+export interface ProcessSnapshot {
+  timestamp: string;
+  processes: ProcessInfo[];
+}
+
+export interface SensorReadings {
+  temperatures: Temperature[];
+  fans: Fan[];
+}
+
+export interface Temperature {
+  chip: string;
+  label: string;
+  celsius: number;
+  high: number | null;
+  critical: number | null;
+}
+
+export interface Fan {
+  chip: string;
+  label: string;
+  rpm: number;
+}
+
+export interface SystemdStatus {
+  failed: string[];
+  watched: UnitState[];
+}
+
+export interface UnitState {
+  name: string;
+  active_state: string;
+  sub_state: string;
+}
+
+export interface DockerSummary {
+  running: number;
+  stopped: number;
+  unhealthy: number;
+  disk: DockerDiskUsage | null;
+}
+
+export interface DockerDiskUsage {
+  images_bytes: number;
+  containers_bytes: number;
+  volumes_bytes: number;
+  build_cache_bytes: number;
+}
+
+export interface Container {
+  client_id: string;
+  id: string;
+  name: string;
+  image: string;
+  state: string;
+  health: string | null;
+  restart_count: number;
+  cpu_percent: number | null;
+  memory_used: number | null;
+  memory_limit: number | null;
+  updated_at: string;
+}
+
+export interface DiskIo {
+  device: string;
+  read_bytes: number;
+  write_bytes: number;
+  read_iops: number;
+  write_iops: number;
+  await_ms: number;
+  util: number;
+}
+
+export interface FilesystemUsage {
+  mount_point: string;
+  device: string;
+  fs_type: string;
+  total_bytes: number;
+  used_bytes: number;
+  disk_usage: number;
+  inode_usage: number | null;
+}
+
+export interface GpuDetail {
+  index: number;
+  name: string;
+  uuid: string | null;
+  utilization: number | null;
+  memory_used: number | null;
+  memory_total: number | null;
+  temperature: number | null;
+  power_watts: number | null;
+  fan_speed: number | null;
+  processes: number | null;
+}
+
+export interface CpuDetail {
+  user: number;
+  system: number;
+  iowait: number;
+  steal: number;
+  load1: number;
+  load5: number;
+  load15: number;
+  cores: number[];
+}
+
+export interface MemoryDetail {
+  total_bytes: number;
+  available_bytes: number;
+  cached_bytes: number;
+  swap_total_bytes: number;
+  swap_used_bytes: number;
+  pressure: Pressure | null;
+  oom_kills_total: number | null;
+  oom_kills: number | null;
+}
+
+export interface Pressure {
+  cpu: PsiResource;
+  memory: PsiResource;
+  io: PsiResource;
+}
+
+export interface PsiResource {
+  some: PsiAverages;
+  full: PsiAverages | null;
+}
+
+export interface PsiAverages {
+  avg10: number;
+  avg60: number;
+  avg300: number;
+}
+
 export interface Stats {
   client_id: string;
   metric_type: string;
+  bucket?: string;
   min: number;
   max: number;
   avg: number;
+  stddev: number;
+  p50: number;
+  p90: number;
   p95: number;
+  p99: number;
   count: number;
 }
 
@@ -33,4 +215,5 @@ export interface AlertRule {
   metric_type: string;
   threshold: number;
   duration_sec: number;
+  target: string | null;
 }